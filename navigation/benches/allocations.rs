@@ -0,0 +1,51 @@
+//! Number of allocations and the peak memory of the search, they aren't
+//! measured by criterion. The counting allocator is kept out of the timing
+//! benches in `solver.rs`.
+
+use navigation::cost_generator::{CostMatrices, Params};
+use navigation::path_finder;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SIZES: [usize; 3] = [6, 9, 12];
+const SEED: u64 = 1;
+
+/// System allocator counting the allocations and the peak of allocated bytes.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn main() {
+    for &n in SIZES.iter() {
+        let energy = CostMatrices::generate(&Params::random(n, SEED)).energy;
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(allocated, Ordering::Relaxed);
+        path_finder::find(&energy);
+        println!(
+            "find/{}: {} allocations, peak {} bytes",
+            n,
+            ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+            PEAK.load(Ordering::Relaxed) - allocated,
+        );
+    }
+}
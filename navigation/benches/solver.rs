@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use navigation::cost_generator::{CostMatrices, Params};
+use navigation::path_finder;
+
+const SIZES: [usize; 3] = [6, 9, 12];
+const SEED: u64 = 1;
+
+fn generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    for &n in SIZES.iter() {
+        let params = Params::random(n, SEED);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &params, |b, params| {
+            b.iter(|| CostMatrices::generate(params))
+        });
+    }
+    group.finish();
+}
+
+fn find(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find");
+    group.sample_size(10);
+    for &n in SIZES.iter() {
+        let energy = CostMatrices::generate(&Params::random(n, SEED)).energy;
+        group.bench_with_input(BenchmarkId::from_parameter(n), &energy, |b, energy| {
+            b.iter(|| path_finder::find(energy))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, generate, find);
+criterion_main!(benches);
@@ -0,0 +1,2017 @@
+use crate::config::{
+    Command, Config, Heuristic, Input, MatrixKind, Optimize, OutputFormat, Solver,
+};
+use crate::formatter::{
+    self, DotFormatter, JsonFormatter, MarkdownFormatter, OutputFormatter, SvgFormatter,
+    TextFormatter,
+};
+use crate::path_finder::{self, SearchOptions};
+use crate::{
+    cost_generator, fleet, make_point, path_totals, plan_with_matrices, prepare_matrix, reorder,
+    route_stats, search_constraints, search_order, tour_vertices, CostMatrices, Error, FindResult,
+    Params, PartialParams, PathEdge, Plan, Stats,
+};
+use nalgebra::DMatrix;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, Write};
+use std::iter::once;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Zeroes the costs of returning to the start vertex 0, keeping the diagonal,
+/// so that the search optimizes the tour without its return edge.
+/// The blocked return edges stay infinite.
+fn free_return(cost: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut cost = cost.clone();
+    for i in 1..cost.nrows() {
+        if cost[(i, 0)].is_finite() {
+            cost[(i, 0)] = 0.0;
+        }
+    }
+    cost
+}
+
+/// The cost matrix that is searched, with [`free_return`] if it's configured.
+fn search_costs<'a>(cost: &'a DMatrix<f64>, config: &Config) -> Cow<'a, DMatrix<f64>> {
+    if config.free_return {
+        Cow::Owned(free_return(cost))
+    } else {
+        Cow::Borrowed(cost)
+    }
+}
+
+fn has_constraints(constraints: &SearchOptions) -> bool {
+    !constraints.precedences.is_empty() || constraints.required.is_some()
+}
+
+fn find_path(
+    cost: &DMatrix<f64>,
+    constraints: &SearchOptions,
+    config: &Config,
+) -> Result<FindResult, Error> {
+    // Only the branch-and-bound search respects the visiting constraints
+    if has_constraints(constraints)
+        && (config.heuristic.is_some() || config.solver != Solver::BranchAndBound)
+    {
+        return Err(Error::UnsupportedConstraints);
+    }
+    let options = SearchOptions {
+        queue_cap: config.queue_cap,
+        timeout: config.timeout,
+        single_precision: config.single_precision,
+        order: config.search_order,
+        ..constraints.clone()
+    };
+
+    match config.heuristic {
+        None => match config.solver {
+            Solver::BranchAndBound if config.progress => {
+                let result = path_finder::find_with_progress(cost, &options, &mut print_progress);
+                eprintln!();
+                result
+            }
+            Solver::BranchAndBound => path_finder::find_with_options(cost, &options),
+            Solver::HeldKarp => {
+                if cost.nrows() > path_finder::HELD_KARP_MAX_VERTICES {
+                    return Err(Error::TooManyPointsForHeldKarp(cost.nrows()));
+                }
+                path_finder::held_karp(cost, 0)
+            }
+            Solver::RandomRestart => {
+                path_finder::random_restarts(cost, config.restarts, config.seed)
+            }
+            Solver::Beam => path_finder::beam_search(cost, config.width),
+        },
+        Some(Heuristic::NearestNeighbor) => path_finder::nearest_neighbor(cost, 0),
+        Some(Heuristic::NearestNeighborTwoOpt) => {
+            path_finder::nearest_neighbor(cost, 0).map(|r| path_finder::two_opt(cost, r.get_path()))
+        }
+    }
+    .ok_or(Error::UnableToFindPath)
+}
+
+/// Finds the path optimal by `cost`, breaking the ties by the `tie_breaks`
+/// matrices in order, see [`path_finder::find_lexicographic`].
+fn find_lexicographic_path(
+    cost: &DMatrix<f64>,
+    tie_breaks: &[DMatrix<f64>],
+    constraints: &SearchOptions,
+    config: &Config,
+) -> Result<FindResult, Error> {
+    if constraints.required.is_some() || config.solver != Solver::BranchAndBound {
+        return Err(Error::UnsupportedTieBreaks);
+    }
+    let costs: Vec<&DMatrix<f64>> = once(cost).chain(tie_breaks).collect();
+
+    path_finder::find_lexicographic(&costs, &constraints.precedences).ok_or(Error::UnableToFindPath)
+}
+
+fn print_progress(stats: &Stats) {
+    let best_cost = stats
+        .best_cost
+        .map_or_else(|| String::from("-"), |c| format!("{:.3}", c));
+    eprint!(
+        "\rExpanded: {}, queue: {}, best: {}, elapsed: {:.1} s",
+        stats.nodes_expanded,
+        stats.queue_len,
+        best_cost,
+        stats.elapsed.as_secs_f64()
+    );
+}
+
+/// Runs the command and writes the output to the file
+/// specified in the config or to stdout.
+pub fn run(config: Config) -> Result<(), Error> {
+    match config.out_filename.clone() {
+        Some(filename) => run_to_writer(config, OutputFile::new(filename)),
+        None => run_to_writer(config, stdout()),
+    }
+}
+
+/// Runs the command and writes the output to the `writer`,
+/// the output file of the config is ignored.
+pub fn run_to_writer<W: Write>(config: Config, mut writer: W) -> Result<(), Error> {
+    let writer: &mut dyn Write = &mut writer;
+    match &config.command {
+        Command::Solve(Input::Params { files, optimize }) if config.dry_run => {
+            run_dry(files, optimize, &config, writer)
+        }
+        Command::Solve(Input::Params { optimize, .. }) if config.interactive => {
+            run_interactive(stdin().lock(), optimize, &config, writer)
+        }
+        Command::Solve(Input::Params { files, optimize }) if config.drones.is_some() => {
+            run_fleet(files, optimize, &config, writer)
+        }
+        Command::Solve(Input::Params { files, optimize }) => {
+            run_params(files, optimize, &config, writer)
+        }
+        Command::Solve(Input::Matrix(file)) => run_matrix(file, &config, writer),
+        Command::Generate(file) => run_generate(file, &config, writer),
+        Command::Validate(file) => run_validate(file, writer),
+    }
+}
+
+/// Output file that is created on the first write, so it's kept intact
+/// if the command fails before producing any output.
+struct OutputFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl OutputFile {
+    fn new(path: PathBuf) -> OutputFile {
+        OutputFile { path, file: None }
+    }
+
+    fn file(&mut self) -> std::io::Result<&mut File> {
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.path)?);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+fn run_generate(params_file: &Path, config: &Config, writer: &mut dyn Write) -> Result<(), Error> {
+    let params = Params::from_file(params_file)?;
+    let cost_matrices = CostMatrices::generate(&params);
+
+    let out_data = formatter::MatricesOutputData {
+        energy: formatter::Matrix(&cost_matrices.energy),
+        time: formatter::Matrix(&cost_matrices.time),
+        capacity: formatter::Matrix(&cost_matrices.capacity),
+        distance: formatter::Matrix(&cost_matrices.distance),
+    };
+
+    write_output(&out_data, config, writer)?;
+
+    Ok(())
+}
+
+/// Splits the points of the params file among the drones and outputs their routes.
+fn run_fleet(
+    params_files: &[PathBuf],
+    optimize: &Optimize,
+    config: &Config,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    let params_file = match params_files {
+        [params_file] => params_file,
+        _ => return Err(Error::DronesWithMultipleFiles),
+    };
+    let params = load_params(params_file, config)?;
+    let started = Instant::now();
+    let cost_matrices = generate_matrices(&params, config)?;
+    let fleet = fleet::plan_fleet_with(
+        &params,
+        cost_matrices,
+        *optimize,
+        config.drones.unwrap_or(1),
+        |cost| {
+            find_path(
+                &search_costs(cost, config),
+                &SearchOptions::default(),
+                config,
+            )
+        },
+    )?;
+    let elapsed_ms = elapsed_ms(started, config);
+
+    let drones = fleet
+        .plans
+        .into_iter()
+        .map(|plan| {
+            let mut out_data = path_output(&params, &plan, config.free_return)?;
+            out_data.stats = output_stats(&plan.result, config);
+            Ok(out_data)
+        })
+        .collect::<Result<_, Error>>()?;
+    let out_data = formatter::FleetOutputData {
+        schema_version: formatter::SCHEMA_VERSION,
+        drones,
+        makespan: fleet.makespan,
+        elapsed_ms,
+    };
+
+    write_output(&out_data, config, writer)?;
+
+    Ok(())
+}
+
+/// Solves the params read from the `reader` line by line until the end of input,
+/// one JSON object per line. The failed lines are reported and skipped.
+fn run_interactive<R: BufRead>(
+    reader: R,
+    optimize: &Optimize,
+    config: &Config,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    // A single SVG document can't hold several plots
+    if config.format == OutputFormat::Svg {
+        return Err(formatter::Error::Unsupported("SVG").into());
+    }
+
+    let mut total = 0;
+    let mut failed = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(cost_generator::ParamsParseError::from)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        total += 1;
+        let solved = parse_params(&line, config).and_then(|params| {
+            solve_loaded_params(params, optimize, config, |data| {
+                write_output(data, config, &mut *writer)?;
+                writeln!(writer)?;
+                Ok(writer.flush()?)
+            })
+        });
+        if let Err(e) = solved {
+            eprintln!("Line {}: {}", i + 1, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::BatchFailed { failed, total });
+    }
+
+    Ok(())
+}
+
+/// Reports the size of each problem without solving it.
+fn run_dry(
+    params_files: &[PathBuf],
+    optimize: &Optimize,
+    config: &Config,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    let sizes = params_files
+        .iter()
+        .map(|file| problem_size(file, *optimize, config))
+        .collect::<Result<_, _>>()?;
+
+    write_output(&formatter::DryRunOutputData(sizes), config, writer)?;
+
+    Ok(())
+}
+
+/// Generates the cost matrices of the params, using the cache if it's configured.
+/// With `--recenter` the costs are generated for the [recentered](Params::recentered)
+/// coordinates, the output keeps the absolute ones.
+fn generate_matrices(params: &Params, config: &Config) -> Result<CostMatrices, Error> {
+    let recentered;
+    let params = if config.recenter {
+        recentered = params.recentered();
+        &recentered
+    } else {
+        params
+    };
+    let (cost_matrices, nan_cells) = match &config.cache {
+        Some(cache) => CostMatrices::generate_cached_checked(params, cache)?,
+        None => CostMatrices::generate_checked(params),
+    };
+    check_nan_costs(&nan_cells, config)?;
+
+    Ok(cost_matrices)
+}
+
+/// Builds the cost matrix of the params file and measures its size,
+/// connectivity and the nearest neighbor upper bound of the tour cost.
+fn problem_size(
+    params_file: &Path,
+    optimize: Optimize,
+    config: &Config,
+) -> Result<formatter::ProblemSize, Error> {
+    let params = load_params(params_file, config)?;
+    let (cost_matrices, nan_cells) = CostMatrices::generate_checked(&params);
+    check_nan_costs(&nan_cells, config)?;
+    let cost = cost_matrices.get(optimize);
+    let n = cost.nrows();
+
+    Ok(formatter::ProblemSize {
+        file: params_file.display().to_string(),
+        n,
+        finite_edges: (0..n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .filter(|&(i, j)| i != j && cost[(i, j)].is_finite())
+            .count(),
+        isolated: (0..n)
+            .filter(|&v| path_finder::is_disconnected(cost, v))
+            .collect(),
+        nn_bound: path_finder::nearest_neighbor(cost, params.depot).map(|r| r.get_cost()),
+    })
+}
+
+fn run_validate(params_file: &Path, writer: &mut dyn Write) -> Result<(), Error> {
+    Params::from_file(params_file)?;
+    writeln!(writer, "OK").map_err(formatter::Error::from)?;
+
+    Ok(())
+}
+
+fn run_matrix(matrix_file: &Path, config: &Config, writer: &mut dyn Write) -> Result<(), Error> {
+    let cost_matrix = prepare_matrix(&cost_generator::matrix_from_file(matrix_file)?)?;
+    let cost_matrix = search_costs(&cost_matrix, config);
+    let started = Instant::now();
+    let result = find_path(&cost_matrix, &SearchOptions::default(), config)?;
+    let elapsed_ms = elapsed_ms(started, config);
+    check_extreme_edges(&cost_matrix, result.get_path(), config)?;
+
+    let out_data = formatter::MatrixOutputData {
+        schema_version: formatter::SCHEMA_VERSION,
+        path: tour_vertices(result.get_path()),
+        cost: result.get_cost(),
+        optimality: result.optimality(),
+        verification: output_verification(&cost_matrix, &result, config),
+        optimal_paths: optimal_tours(&cost_matrix, &SearchOptions::default(), config)?,
+        best_paths: best_tours(&cost_matrix, &SearchOptions::default(), config)?,
+        stats: output_stats(&result, config),
+        trace: output_trace(
+            &cost_matrix,
+            &(0..cost_matrix.nrows()).collect::<Vec<_>>(),
+            result.get_path(),
+            config,
+        ),
+        elapsed_ms,
+    };
+
+    write_output(&out_data, config, writer)?;
+
+    Ok(())
+}
+
+/// Solves each params file and outputs one result per file. In JSON format
+/// the results are written as an array, in text, Markdown and DOT formats
+/// each result is preceded by the header with the file name. A single file is output as is.
+/// Failed files are reported to stderr without aborting the remaining ones.
+fn run_params(
+    params_files: &[PathBuf],
+    optimize: &Optimize,
+    config: &Config,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    if let [params_file] = params_files {
+        return solve_params(params_file, optimize, config, |data| {
+            write_output(data, config, writer)
+        });
+    }
+
+    // A single SVG document can't hold several plots
+    if config.format == OutputFormat::Svg {
+        return Err(formatter::Error::Unsupported("SVG").into());
+    }
+
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for (i, params_file) in params_files.iter().enumerate() {
+        let solved = match config.format {
+            OutputFormat::Text => {
+                let separator = if i == 0 { "" } else { "\n" };
+                writeln!(writer, "{}=== {} ===", separator, params_file.display())
+                    .map_err(formatter::Error::from)?;
+                let formatter = TextFormatter {
+                    precision: config.precision,
+                };
+                solve_params(params_file, optimize, config, |data| {
+                    formatter.fmt(data, &mut *writer)
+                })
+            }
+            OutputFormat::Markdown => {
+                let separator = if i == 0 { "" } else { "\n" };
+                writeln!(writer, "{}# {}\n", separator, params_file.display())
+                    .map_err(formatter::Error::from)?;
+                let formatter = MarkdownFormatter {
+                    precision: config.precision,
+                };
+                solve_params(params_file, optimize, config, |data| {
+                    formatter.fmt(data, &mut *writer)
+                })
+            }
+            OutputFormat::Dot => {
+                let separator = if i == 0 { "" } else { "\n" };
+                writeln!(writer, "{}// {}", separator, params_file.display())
+                    .map_err(formatter::Error::from)?;
+                let formatter = dot_formatter(config);
+                solve_params(params_file, optimize, config, |data| {
+                    formatter.fmt(data, &mut *writer)
+                })
+            }
+            OutputFormat::Svg => unreachable!(),
+            OutputFormat::Json => solve_params(params_file, optimize, config, |data| {
+                Ok(serde_json::to_value(data)?)
+            })
+            .map(|result| results.push(result)),
+        };
+
+        if let Err(e) = solved {
+            eprintln!("{}: {}", params_file.display(), e);
+            failed += 1;
+        }
+    }
+    if config.format == OutputFormat::Json {
+        JsonFormatter {
+            compact: config.compact,
+            round: config.round,
+        }
+        .fmt(&results, writer)?;
+    }
+
+    if failed > 0 {
+        return Err(Error::BatchFailed {
+            failed,
+            total: params_files.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Warns about the coinciding points, or fails in the strict mode.
+fn check_duplicates(params: &Params, config: &Config) -> Result<(), Error> {
+    let duplicates = params.find_duplicates();
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    if config.strict {
+        return Err(Error::DuplicateCoords(duplicates));
+    }
+    for (i, j) in duplicates {
+        eprintln!("Warning: points {} and {} coincide", i + 1, j + 1);
+    }
+
+    Ok(())
+}
+
+/// Warns about the edges whose NaN costs were replaced with infinity,
+/// or fails in the strict mode, see [`CostMatrices::generate_checked`].
+fn check_nan_costs(cells: &[(usize, usize)], config: &Config) -> Result<(), Error> {
+    if let (true, Some(&(from, to))) = (config.strict, cells.first()) {
+        return Err(Error::NanCost { from, to });
+    }
+    for (from, to) in cells {
+        eprintln!(
+            "Warning: cost of the edge from point {} to point {} is not a number, the edge is excluded",
+            from + 1,
+            to + 1
+        );
+    }
+
+    Ok(())
+}
+
+/// Edges of the `path` whose cost is practically infinite or exceeds
+/// the `--max-edge-cost` threshold, as *(from, to, cost)*.
+fn extreme_edges(
+    cost: &DMatrix<f64>,
+    path: &[PathEdge],
+    config: &Config,
+) -> Vec<(usize, usize, f64)> {
+    let threshold = config.max_edge_cost.unwrap_or(f64::INFINITY);
+    path.iter()
+        .map(|e| (e.from(), e.to(), cost[(e.from(), e.to())]))
+        .filter(|&(_, _, c)| c > threshold || path_finder::is_near_infinite(c))
+        .collect()
+}
+
+/// Warns about the extreme edges of the found path, or fails
+/// in the strict mode, see [`extreme_edges`].
+fn check_extreme_edges(
+    cost: &DMatrix<f64>,
+    path: &[PathEdge],
+    config: &Config,
+) -> Result<(), Error> {
+    let edges = extreme_edges(cost, path, config);
+    if let (true, Some(&(from, to, cost))) = (config.strict, edges.first()) {
+        return Err(Error::ExtremeEdge { from, to, cost });
+    }
+    for (from, to, cost) in edges {
+        eprintln!(
+            "Warning: edge from point {} to point {} costs {:e}, the path is practically impossible",
+            from + 1,
+            to + 1,
+            cost
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads the params file over the base one, if any, and applies the options,
+/// see [`apply_params_options`].
+fn load_params(params_file: &Path, config: &Config) -> Result<Params, Error> {
+    let params = match &config.base {
+        Some(base) => Params::merge(
+            Params::from_file(base)?,
+            PartialParams::from_file(params_file)?,
+        )?,
+        None => Params::from_file(params_file)?,
+    };
+
+    apply_params_options(params, config)
+}
+
+/// Parses the JSON params over the base file, if any, and applies the options,
+/// see [`apply_params_options`].
+fn parse_params(s: &str, config: &Config) -> Result<Params, Error> {
+    let params = match &config.base {
+        Some(base) => Params::merge(Params::from_file(base)?, s.parse()?)?,
+        None => s.parse()?,
+    };
+
+    apply_params_options(params, config)
+}
+
+/// Applies the coordinates, depot and snapping options and checks the coinciding points.
+fn apply_params_options(mut params: Params, config: &Config) -> Result<Params, Error> {
+    if let Some(coords) = &config.coords {
+        params = params.with_coords(cost_generator::coords_from_file(coords)?)?;
+    }
+    if let Some(depot) = config.depot {
+        params = params.with_depot(depot)?;
+    }
+    if let Some(grid) = config.snap.or(params.snap_grid) {
+        params = params.snap_to_grid(grid)?;
+    }
+    check_duplicates(&params, config)?;
+
+    Ok(params)
+}
+
+/// Solves the params file and passes the result to the `output` function.
+fn solve_params<T, F>(
+    params_file: &Path,
+    optimize: &Optimize,
+    config: &Config,
+    output: F,
+) -> Result<T, Error>
+where
+    F: FnOnce(&formatter::OutputData<'_>) -> Result<T, formatter::Error>,
+{
+    solve_loaded_params(load_params(params_file, config)?, optimize, config, output)
+}
+
+/// Solves the params with the options applied and passes the result to the `output` function.
+fn solve_loaded_params<T, F>(
+    params: Params,
+    optimize: &Optimize,
+    config: &Config,
+    output: F,
+) -> Result<T, Error>
+where
+    F: FnOnce(&formatter::OutputData<'_>) -> Result<T, formatter::Error>,
+{
+    let constraints = search_constraints(&params);
+    let started = Instant::now();
+    let cost_matrices = generate_matrices(&params, config)?;
+    let tie_breaks: Vec<DMatrix<f64>> = config
+        .tie_breaks
+        .iter()
+        .map(|&tie_break| {
+            let cost = reorder(cost_matrices.get(tie_break), &search_order(&params));
+            search_costs(&cost, config).into_owned()
+        })
+        .collect();
+    let plan = plan_with_matrices(&params, cost_matrices, *optimize, |cost| {
+        let cost = search_costs(cost, config);
+        if tie_breaks.is_empty() {
+            find_path(&cost, &constraints, config)
+        } else {
+            find_lexicographic_path(&cost, &tie_breaks, &constraints, config)
+        }
+    })?;
+    let elapsed_ms = elapsed_ms(started, config);
+    check_extreme_edges(
+        plan.cost_matrices.get(*optimize),
+        plan.result.get_path(),
+        config,
+    )?;
+
+    let mut out_data = path_output(&params, &plan, config.free_return)?;
+    let Plan {
+        result,
+        cost_matrices,
+        ..
+    } = &plan;
+
+    let order = search_order(&params);
+    let search_matrix = reorder(cost_matrices.get(*optimize), &order);
+    let search_matrix = search_costs(&search_matrix, config);
+    let optimal_paths = optimal_tours(&search_matrix, &constraints, config)?
+        .into_iter()
+        .map(|tour| {
+            tour.into_iter()
+                .map(|i| make_point(&params, order[i]))
+                .collect()
+        })
+        .collect();
+
+    let best_paths = best_tours(&search_matrix, &constraints, config)?
+        .into_iter()
+        .map(|ranked| formatter::RankedPath {
+            cost: ranked.cost,
+            path: ranked
+                .path
+                .into_iter()
+                .map(|i| make_point(&params, order[i]))
+                .collect(),
+        })
+        .collect();
+
+    let shown = match &config.show_matrices {
+        Some(shown) => shown.clone(),
+        None => MatrixKind::defaults(*optimize),
+    };
+    let matrix = |kind, m| shown.contains(&kind).then_some(formatter::Matrix(m));
+    out_data.energy = matrix(MatrixKind::Energy, &cost_matrices.energy);
+    out_data.time = matrix(MatrixKind::Time, &cost_matrices.time);
+    out_data.capacity = matrix(MatrixKind::Capacity, &cost_matrices.capacity);
+    out_data.distance = matrix(MatrixKind::Distance, &cost_matrices.distance);
+    out_data.optimal_paths = optimal_paths;
+    out_data.best_paths = best_paths;
+    out_data.verification = output_verification(cost_matrices.get(*optimize), result, config);
+    out_data.stats = output_stats(result, config);
+    out_data.trace = output_trace(&search_matrix, &order, result.get_path(), config);
+    out_data.elapsed_ms = elapsed_ms;
+    if config.echo_params {
+        out_data.params = Some(&params);
+    }
+    if let (true, Some(battery_capacity)) = (config.battery_trace, params.usable_battery_capacity())
+    {
+        let remaining = battery_trace(&params, &out_data, battery_capacity, config.free_return);
+        out_data.first_infeasible = remaining.iter().position(|&c| c < 0.0);
+        out_data.remaining_capacity = remaining;
+    }
+
+    Ok(output(&out_data)?)
+}
+
+/// Output of the found path with its edges, total costs and the battery legs.
+/// The matrices, the alternative paths and the stats are left empty.
+/// With `free_return` the edge returning to the depot isn't included into the totals.
+fn path_output<'a>(
+    params: &Params,
+    plan: &Plan,
+    free_return: bool,
+) -> Result<formatter::OutputData<'a>, Error> {
+    let Plan {
+        path,
+        edges,
+        cost_matrices,
+        ..
+    } = plan;
+    let (energy_cost, time_cost, capacity_cost, distance_cost) = if free_return {
+        // The return edge may be blocked, so it's skipped rather than subtracted
+        let flown: Vec<_> = edges
+            .iter()
+            .filter(|e| e.to != params.depot)
+            .copied()
+            .collect();
+        path_totals(params, &flown, path)
+    } else {
+        (
+            plan.energy_cost,
+            plan.time_cost,
+            plan.capacity_cost,
+            plan.distance_cost,
+        )
+    };
+
+    let (fits_battery, legs) = match params.usable_battery_capacity() {
+        Some(battery_capacity) if capacity_cost > battery_capacity => {
+            let tour: Vec<usize> = path.iter().map(|p| p.point_index).collect();
+            let hover: Vec<f64> = (0..params.coords.len())
+                .map(|i| params.hover_capacity_at(i))
+                .collect();
+            let legs = split_legs(&tour, &cost_matrices.capacity, &hover, battery_capacity)?
+                .into_iter()
+                .map(|leg| leg.into_iter().map(|i| make_point(params, i)).collect())
+                .collect();
+            (Some(false), legs)
+        }
+        Some(_) => (Some(true), Vec::new()),
+        None => (None, Vec::new()),
+    };
+
+    Ok(formatter::OutputData {
+        schema_version: formatter::SCHEMA_VERSION,
+        energy: None,
+        time: None,
+        capacity: None,
+        distance: None,
+        path: path.clone(),
+        energy_cost,
+        time_cost,
+        capacity_cost,
+        distance_cost,
+        edges: edges.clone(),
+        optimality: plan.optimality,
+        fits_battery,
+        legs,
+        remaining_capacity: Vec::new(),
+        first_infeasible: None,
+        verification: None,
+        optimal_paths: Vec::new(),
+        best_paths: Vec::new(),
+        stats: None,
+        trace: Vec::new(),
+        elapsed_ms: None,
+        params: None,
+        order: path.iter().map(|p| p.point_index).collect(),
+    })
+}
+
+/// Remaining battery capacity at each point of the output path, starting
+/// with the full `battery_capacity`. Each point takes the capacity of the edge
+/// leading to it and of hovering at it, so the capacity left at the end
+/// is `battery_capacity - capacity_cost`. With `free_return` the edge
+/// returning to the depot takes nothing, as in the totals of [`path_output`].
+fn battery_trace(
+    params: &Params,
+    out_data: &formatter::OutputData<'_>,
+    battery_capacity: f64,
+    free_return: bool,
+) -> Vec<f64> {
+    let stops = out_data.path.iter().skip(1).zip(&out_data.edges);
+    once(battery_capacity)
+        .chain(stops.scan(battery_capacity, |remaining, (p, e)| {
+            if !(free_return && e.to == params.depot) {
+                *remaining -= e.capacity;
+            }
+            *remaining -= params.hover_capacity_at(p.point_index);
+            Some(*remaining)
+        }))
+        .collect()
+}
+
+/// Finds all the tours of the minimum cost if they're requested.
+fn optimal_tours(
+    cost: &DMatrix<f64>,
+    constraints: &SearchOptions,
+    config: &Config,
+) -> Result<Vec<Vec<usize>>, Error> {
+    if !config.all {
+        return Ok(Vec::new());
+    }
+    if constraints.required.is_some() {
+        return Err(Error::OptionalPointsWithMultiplePaths);
+    }
+
+    let (_, tours) =
+        path_finder::find_all(cost, &constraints.precedences).ok_or(Error::UnableToFindPath)?;
+    Ok(tours.iter().map(|tour| tour_vertices(tour)).collect())
+}
+
+/// Finds the cheapest tours if they're requested.
+fn best_tours(
+    cost: &DMatrix<f64>,
+    constraints: &SearchOptions,
+    config: &Config,
+) -> Result<Vec<formatter::RankedPath<usize>>, Error> {
+    let k = match config.k_best {
+        Some(k) => k,
+        None => return Ok(Vec::new()),
+    };
+    if constraints.required.is_some() {
+        return Err(Error::OptionalPointsWithMultiplePaths);
+    }
+
+    Ok(path_finder::find_k_best(cost, k, &constraints.precedences)
+        .iter()
+        .map(|result| formatter::RankedPath {
+            cost: result.get_cost(),
+            path: tour_vertices(result.get_path()),
+        })
+        .collect())
+}
+
+/// Splits the closed `tour` (starting and ending at the same point) into legs,
+/// so that the used battery capacity of each leg doesn't exceed `battery_capacity`.
+/// Each leg starts at the start point of the tour, visits the next points
+/// of the tour in order and returns back to the start point.
+/// `hover` is the used capacity of hovering at each point, paid at the points
+/// visited by the leg; the start point is landed at between the legs.
+fn split_legs(
+    tour: &[usize],
+    capacity: &DMatrix<f64>,
+    hover: &[f64],
+    battery_capacity: f64,
+) -> Result<Vec<Vec<usize>>, Error> {
+    let start = tour[0];
+    let mut legs = Vec::new();
+    let mut leg = vec![start];
+    let mut used = 0.0;
+
+    for &to in &tour[1..tour.len() - 1] {
+        if capacity[(start, to)] + hover[to] + capacity[(to, start)] > battery_capacity {
+            return Err(Error::OutOfBatteryRange(to));
+        }
+
+        let from = *leg.last().unwrap();
+        if used + capacity[(from, to)] + hover[to] + capacity[(to, start)] > battery_capacity {
+            // Go back to the start point and begin a new leg
+            leg.push(start);
+            legs.push(std::mem::replace(&mut leg, vec![start]));
+            used = 0.0;
+        }
+
+        used += capacity[(*leg.last().unwrap(), to)] + hover[to];
+        leg.push(to);
+    }
+    leg.push(start);
+    legs.push(leg);
+
+    Ok(legs)
+}
+
+/// Relative difference of the forward and reverse path costs
+/// above which the cost matrix is reported as asymmetric.
+const ASYMMETRY_TOLERANCE: f64 = 1e-9;
+
+fn output_verification(
+    cost: &DMatrix<f64>,
+    result: &FindResult,
+    config: &Config,
+) -> Option<formatter::Verification> {
+    if !config.verify {
+        return None;
+    }
+
+    let forward_cost: f64 = result
+        .get_path()
+        .iter()
+        .map(|e| cost[(e.from(), e.to())])
+        .sum();
+    let reverse_cost = result.reverse_cost(cost);
+    let tolerance = ASYMMETRY_TOLERANCE * forward_cost.abs().max(1.0);
+    Some(formatter::Verification {
+        forward_cost,
+        reverse_cost,
+        asymmetric: forward_cost != reverse_cost && (forward_cost - reverse_cost).abs() > tolerance,
+    })
+}
+
+fn output_stats(result: &FindResult, config: &Config) -> Option<formatter::Stats> {
+    if !config.stats {
+        return None;
+    }
+
+    Some(route_stats(result.stats()))
+}
+
+/// Branch-and-bound nodes on the way to the found `path`, if requested.
+/// Vertex `i` of the `cost` matrix is the point `order[i]`, the steps
+/// are mapped back to the point indices.
+fn output_trace(
+    cost: &DMatrix<f64>,
+    order: &[usize],
+    path: &[PathEdge],
+    config: &Config,
+) -> Vec<formatter::TraceStep> {
+    if !config.trace {
+        return Vec::new();
+    }
+
+    let mut inverse = vec![0; order.len()];
+    for (i, &v) in order.iter().enumerate() {
+        inverse[v] = i;
+    }
+    let tour: Vec<usize> = tour_vertices(path)
+        .into_iter()
+        .map(|v| inverse[v])
+        .collect();
+    path_finder::trace(cost, &tour)
+        .into_iter()
+        .map(|step| formatter::TraceStep {
+            edge: step.edge.map(|e| (order[e.from()], order[e.to()])),
+            lower_bound: step.lower_bound,
+            reduced_matrix: reorder(&step.reduced_matrix, &inverse),
+        })
+        .collect()
+}
+
+/// Milliseconds since `started`, unless the output must be deterministic.
+fn elapsed_ms(started: Instant, config: &Config) -> Option<f64> {
+    if config.deterministic {
+        None
+    } else {
+        Some(started.elapsed().as_secs_f64() * 1000.0)
+    }
+}
+
+/// The edges are labeled with the optimized parameter of the params files,
+/// the matrix tour has no edge costs to label.
+fn dot_formatter(config: &Config) -> DotFormatter {
+    let optimize = match &config.command {
+        Command::Solve(Input::Params { optimize, .. }) => *optimize,
+        _ => Optimize::Energy,
+    };
+
+    DotFormatter {
+        precision: config.precision,
+        optimize,
+    }
+}
+
+fn write_output<D, W: Write>(data: &D, config: &Config, writer: W) -> Result<(), formatter::Error>
+where
+    TextFormatter: OutputFormatter<W, D>,
+    MarkdownFormatter: OutputFormatter<W, D>,
+    DotFormatter: OutputFormatter<W, D>,
+    SvgFormatter: OutputFormatter<W, D>,
+    JsonFormatter: OutputFormatter<W, D>,
+{
+    match config.format {
+        OutputFormat::Text => TextFormatter {
+            precision: config.precision,
+        }
+        .fmt(data, writer),
+        OutputFormat::Markdown => MarkdownFormatter {
+            precision: config.precision,
+        }
+        .fmt(data, writer),
+        OutputFormat::Dot => dot_formatter(config).fmt(data, writer),
+        OutputFormat::Svg => SvgFormatter::default().fmt(data, writer),
+        OutputFormat::Json => JsonFormatter {
+            compact: config.compact,
+            round: config.round,
+        }
+        .fmt(data, writer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plan, solve_matrix, Optimality};
+
+    #[test]
+    #[rustfmt::skip]
+    fn split_legs_test() {
+        let capacity = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 1.155, 0.276, 1.526, 0.251, 0.925,
+            0.472, f64::INFINITY, 0.510, 0.399, 0.521, 0.339,
+            0.248, 1.166, f64::INFINITY, 1.289, 0.129, 0.868,
+            0.728, 0.285, 0.519, f64::INFINITY, 0.529, 0.361,
+            0.219, 1.173, 0.125, 1.294, f64::INFINITY, 0.739,
+            0.470, 0.567, 0.440, 0.702, 0.315, f64::INFINITY
+        ]).transpose();
+        let tour = [0, 2, 4, 5, 3, 1, 0];
+
+        let legs = split_legs(&tour, &capacity, &[0.0; 6], 3.0).unwrap();
+        assert_eq!(vec![tour.to_vec()], legs);
+
+        let legs = split_legs(&tour, &capacity, &[0.0; 6], 2.3).unwrap();
+        assert_eq!(vec![vec![0, 2, 4, 5, 0], vec![0, 3, 1, 0]], legs);
+        for leg in &legs {
+            let used: f64 = leg.windows(2).map(|e| capacity[(e[0], e[1])]).sum();
+            assert!(used <= 2.3);
+        }
+
+        // Point 3 alone takes 2.254 Ah to visit
+        match split_legs(&tour, &capacity, &[0.0; 6], 2.0) {
+            Err(Error::OutOfBatteryRange(3)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Hovering at point 5 doesn't fit into the first leg anymore
+        let hover = [0.0, 0.0, 0.0, 0.0, 0.0, 0.8];
+        let legs = split_legs(&tour, &capacity, &hover, 2.3).unwrap();
+        assert_eq!(vec![vec![0, 2, 4, 0], vec![0, 5, 0], vec![0, 3, 1, 0]], legs);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn extreme_edges_test() {
+        let inf = f64::INFINITY;
+        let huge = f64::MAX * (1.0 - 1e-12);
+        // The only tour goes through the huge edge from point 2 to point 3
+        let cost = DMatrix::from_vec(3, 3, vec![
+            0.0, 1.0, inf,
+            inf, 0.0, huge,
+            1.0, inf, 0.0,
+        ]).transpose();
+        let config = Config::from_iter(["navigation", "params.json", "-e"]);
+        let result = solve_matrix(&cost).unwrap();
+        assert_eq!(
+            vec![(1, 2, huge)],
+            extreme_edges(&cost, result.get_path(), &config)
+        );
+        assert!(check_extreme_edges(&cost, result.get_path(), &config).is_ok());
+
+        let file = std::env::temp_dir().join("navigation_extreme_edges_test.json");
+        let rows = format!("[[0, 1, null], [null, 0, {:e}], [1, null, 0]]", huge);
+        std::fs::write(&file, rows).unwrap();
+        let strict = run(Config::from_iter([
+            "navigation", "--matrix", file.to_str().unwrap(), "--strict",
+        ]));
+        std::fs::remove_file(&file).unwrap();
+        match strict {
+            Err(Error::ExtremeEdge { from: 1, to: 2, cost }) => assert_eq!(huge, cost),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Ordinary edges are reported only above the threshold
+        let params = Params::from_file("params.json").unwrap();
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        let energy = &plan.cost_matrices.energy;
+        assert!(extreme_edges(energy, plan.result.get_path(), &config).is_empty());
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--max-edge-cost", "90"]);
+        let edges = extreme_edges(energy, plan.result.get_path(), &config);
+        assert_eq!(vec![(0, 1)], edges.iter().map(|&(i, j, _)| (i, j)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn exit_code_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.battery_capacity = Some(0.1);
+        let file = std::env::temp_dir().join("navigation_exit_code_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let unsolvable = run(Config::from_iter([
+            "navigation",
+            file.to_str().unwrap(),
+            "-e",
+        ]));
+        std::fs::remove_file(&file).unwrap();
+        match unsolvable {
+            Err(e @ Error::OutOfBatteryRange(_)) => {
+                assert_eq!(Error::EXIT_UNSOLVABLE, e.exit_code())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let missing = run(Config::from_iter([
+            "navigation",
+            "missing_params.json",
+            "-e",
+        ]));
+        assert_eq!(Error::EXIT_IO, missing.unwrap_err().exit_code());
+        let invalid = run(Config::from_iter([
+            "navigation",
+            "params.json",
+            "-e",
+            "--depot",
+            "6",
+        ]));
+        assert_eq!(Error::EXIT_INVALID_INPUT, invalid.unwrap_err().exit_code());
+    }
+
+    #[test]
+    fn missing_params_file_test() {
+        let config = Config::from_iter(["navigation", "missing_params.json", "-e"]);
+        match run(config) {
+            Err(e @ Error::ParamsParse(cost_generator::ParamsParseError::IoWithPath { .. })) => {
+                assert!(e.to_string().contains("'missing_params.json'"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn batch_test() {
+        let out = std::env::temp_dir().join("navigation_batch_test.json");
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+        run(config).unwrap();
+
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        let results = json.as_array().unwrap();
+        assert_eq!(2, results.len());
+        assert_eq!(results[0]["energy_cost"], results[1]["energy_cost"]);
+
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "missing_params.json",
+            "-e",
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+        match run(config) {
+            Err(Error::BatchFailed {
+                failed: 1,
+                total: 2,
+            }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let text = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(text.starts_with("=== params.json ===\n"));
+        assert!(text.contains("\n=== missing_params.json ===\n"));
+    }
+
+    #[test]
+    fn echo_params_test() {
+        let out = std::env::temp_dir().join("navigation_echo_params_test.json");
+        let args = [
+            "navigation",
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ];
+        run(Config::from_iter(args.iter().chain(&["--echo-params"]))).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        let params = Params::from_file("params.json").unwrap();
+        assert_eq!(params.battery_voltage, json["params"]["battery_voltage"]);
+        assert_eq!(
+            params.coords.len(),
+            json["params"]["coords"].as_array().unwrap().len()
+        );
+
+        run(Config::from_iter(args)).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(json.get("params").is_none());
+    }
+
+    #[test]
+    fn show_matrices_test() {
+        let matrices = |args: &[&str]| {
+            let json_args = ["navigation", "params.json", "-e", "--format", "json"];
+            let config = Config::from_iter(json_args.iter().chain(args));
+            let mut buf = Vec::new();
+            run_to_writer(config, &mut buf).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+            ["energy", "time", "capacity", "distance"]
+                .iter()
+                .filter(|&&m| json.get(m).is_some())
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["energy", "time", "capacity"], matrices(&[]));
+        assert_eq!(vec!["distance"], matrices(&["--show-matrices", "distance"]));
+        assert!(matrices(&["--no-matrices"]).is_empty());
+    }
+
+    #[test]
+    fn run_to_writer_test() {
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--format", "json"]);
+        let mut buf = Vec::new();
+        run_to_writer(config, &mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let params = Params::from_file("params.json").unwrap();
+        assert_eq!(
+            params.coords.len() + 1,
+            json["path"].as_array().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn edges_output_test() {
+        let out = std::env::temp_dir().join("navigation_edges_test.json");
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+        run(config).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+
+        let edges = json["edges"].as_array().unwrap();
+        assert_eq!(json["path"].as_array().unwrap().len() - 1, edges.len());
+        for (prev, next) in edges.iter().zip(edges.iter().skip(1)) {
+            assert_eq!(prev["to"], next["from"]);
+        }
+        for key in &["energy", "time", "capacity", "distance"] {
+            let sum: f64 = edges.iter().map(|e| e[key].as_f64().unwrap()).sum();
+            let total = json[format!("{}_cost", key)].as_f64().unwrap();
+            assert!(approx_eq!(f64, total, sum, epsilon = 1e-9, ulps = 0));
+        }
+    }
+
+    #[test]
+    fn hover_totals_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.hover_times = Some(vec![10.0, 20.0, 0.0, 0.0, 0.0, 30.0]);
+        let file = std::env::temp_dir().join("navigation_hover_totals_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--deterministic"]);
+        let totals = solve_params(&file, &Optimize::Energy, &config, |data| {
+            Ok((
+                data.energy_cost,
+                data.time_cost,
+                data.capacity_cost,
+                data.edges.clone(),
+            ))
+        });
+        std::fs::remove_file(&file).unwrap();
+        let (energy, time, capacity, edges) = totals.unwrap();
+
+        // Hovering is paid once at each of the points, regardless of the tour,
+        // and isn't included into the edge costs anymore
+        let moving: (f64, f64, f64) = edges.iter().fold((0.0, 0.0, 0.0), |sum, e| {
+            (sum.0 + e.energy, sum.1 + e.time, sum.2 + e.capacity)
+        });
+        let hover_energy = 545.8 * 60.0 / 1000.0;
+        assert!(approx_eq!(
+            f64,
+            moving.0 + hover_energy,
+            energy,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(approx_eq!(
+            f64,
+            moving.1 + 60.0,
+            time,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        let hover_capacity = hover_energy * 1000.0 / (22.8 * 3600.0);
+        assert!(approx_eq!(
+            f64,
+            moving.2 + hover_capacity,
+            capacity,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(approx_eq!(f64, 246.363, energy, epsilon = 0.001, ulps = 0));
+    }
+
+    #[test]
+    fn verify_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.wind = Some(cost_generator::Wind { x: 4.0, y: -2.0 });
+        let file = std::env::temp_dir().join("navigation_verify_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--verify"]);
+        let verify = |params_file: &Path, optimize: Optimize| {
+            solve_params(params_file, &optimize, &config, |data| {
+                Ok(data.verification.unwrap())
+            })
+        };
+        let distance = verify(Path::new("params.json"), Optimize::Intuitive);
+        let wind = verify(&file, Optimize::Time);
+        std::fs::remove_file(&file).unwrap();
+
+        let distance = distance.unwrap();
+        assert!(approx_eq!(
+            f64,
+            distance.forward_cost,
+            distance.reverse_cost,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(!distance.asymmetric);
+        let wind = wind.unwrap();
+        assert!((wind.forward_cost - wind.reverse_cost).abs() > 1.0);
+        assert!(wind.asymmetric);
+    }
+
+    #[test]
+    fn recenter_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        // UTM-like magnitudes
+        for c in &mut params.coords {
+            c.x += 500_000.0;
+            c.y += 6_000_000.0;
+        }
+        let file = std::env::temp_dir().join("navigation_recenter_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let solve = |params_file: &Path, args: &[&str]| {
+            let config = Config::from_iter(["navigation", "params.json", "-e"].iter().chain(args));
+            solve_params(params_file, &Optimize::Energy, &config, |data| {
+                Ok(data
+                    .path
+                    .iter()
+                    .map(|p| (p.point_index, p.x, p.y))
+                    .collect::<Vec<_>>())
+            })
+            .unwrap()
+        };
+        let sample = solve(Path::new("params.json"), &[]);
+        let recentered = solve(Path::new("params.json"), &["--recenter"]);
+        let shifted = solve(&file, &["--recenter"]);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(sample, recentered);
+        let order = |path: &[(usize, f64, f64)]| path.iter().map(|p| p.0).collect::<Vec<_>>();
+        assert_eq!(order(&sample), order(&shifted));
+        // The output points are absolute
+        for &(i, x, y) in &shifted {
+            assert_eq!(params.coords[i].x, x);
+            assert_eq!(params.coords[i].y, y);
+        }
+    }
+
+    #[test]
+    fn free_return_test() {
+        let solve = |args: &[&str]| {
+            let config = Config::from_iter(["navigation", "params.json", "-e"].iter().chain(args));
+            solve_params(
+                Path::new("params.json"),
+                &Optimize::Energy,
+                &config,
+                |data| {
+                    Ok((
+                        data.energy_cost,
+                        data.path.iter().map(|p| p.point_index).collect::<Vec<_>>(),
+                        data.edges.last().map(|e| (e.to, e.energy)),
+                    ))
+                },
+            )
+            .unwrap()
+        };
+        let (closed_cost, closed_path, closed_return) = solve(&[]);
+        let (free_cost, free_path, free_return) = solve(&["--free-return"]);
+
+        // The tour is still closed, but its return edge is free
+        assert_eq!(closed_path.len(), free_path.len());
+        assert_eq!(Some(&0), free_path.last());
+        let (to, return_cost) = free_return.unwrap();
+        assert_eq!(0, to);
+        assert!(return_cost > 0.0);
+        assert!(free_cost < closed_cost);
+        // The open path is optimized, so it's no worse than the closed one without its return
+        assert!(free_cost <= closed_cost - closed_return.unwrap().1 + 1e-9);
+
+        // A blocked return edge stays blocked
+        let inf = f64::INFINITY;
+        let cost = DMatrix::from_row_slice(3, 3, &[inf, 1.0, 2.0, 3.0, inf, 4.0, inf, 5.0, inf]);
+        let free = super::free_return(&cost);
+        assert_eq!(0.0, free[(1, 0)]);
+        assert_eq!(inf, free[(2, 0)]);
+        assert_eq!(inf, free[(0, 0)]);
+        assert_eq!(cost.row(0), free.row(0));
+    }
+
+    #[test]
+    fn reserve_fraction_test() {
+        let file = std::env::temp_dir().join("navigation_reserve_fraction_test.json");
+        let config = Config::from_iter(["navigation", "params.json", "-e"]);
+        let legs = |reserve_fraction: f64| {
+            let mut params = Params::from_file("params.json").unwrap();
+            params.battery_capacity = Some(3.5);
+            params.reserve_fraction = reserve_fraction;
+            std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+            let legs = solve_params(&file, &Optimize::Energy, &config, |data| {
+                Ok(data.legs.len().max(1))
+            });
+            std::fs::remove_file(&file).unwrap();
+            legs.unwrap()
+        };
+
+        assert_eq!(1, legs(0.0));
+        assert!(legs(0.3) > 1);
+    }
+
+    #[test]
+    fn temperature_test() {
+        let file = std::env::temp_dir().join("navigation_temperature_test.json");
+        let config = Config::from_iter(["navigation", "params.json", "-e"]);
+        // Largest number of the waypoints covered by a single leg
+        let max_waypoints = |temperature_c: Option<f64>| {
+            let mut params = Params::from_file("params.json").unwrap();
+            params.battery_capacity = Some(3.0);
+            params.temperature_c = temperature_c;
+            std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+            let waypoints = solve_params(&file, &Optimize::Energy, &config, |data| {
+                Ok(match data.legs.iter().map(|leg| leg.len() - 2).max() {
+                    Some(n) => n,
+                    None => data.path.len() - 2,
+                })
+            });
+            std::fs::remove_file(&file).unwrap();
+            waypoints.unwrap()
+        };
+
+        let warm = max_waypoints(None);
+        assert_eq!(warm, max_waypoints(Some(25.0)));
+        assert!(max_waypoints(Some(-2.0)) < warm);
+    }
+
+    #[test]
+    fn depot_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.battery_capacity = Some(2.4);
+        let file = std::env::temp_dir().join("navigation_depot_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let legs = |depot: &str| {
+            let config = Config::from_iter(["navigation", "params.json", "-e", "--depot", depot]);
+            solve_params(&file, &Optimize::Energy, &config, |data| {
+                let index = |p: &formatter::Point| p.point_index;
+                assert_eq!(data.path.first().map(index), data.path.last().map(index));
+                Ok(data
+                    .legs
+                    .iter()
+                    .map(|leg| leg.iter().map(index).collect::<Vec<_>>())
+                    .collect::<Vec<_>>())
+            })
+        };
+        let from_first = legs("0");
+        let from_depot = legs("2");
+        let out_of_range = legs("6");
+        std::fs::remove_file(&file).unwrap();
+
+        let from_first = from_first.unwrap();
+        assert!(from_first.len() > 1);
+        for leg in from_first {
+            assert_eq!(Some(&0), leg.first());
+            assert_eq!(Some(&0), leg.last());
+        }
+        // Each leg starts at the depot and is closed by an edge back to it
+        let from_depot = from_depot.unwrap();
+        assert_eq!(vec![vec![2, 0, 1, 3, 2], vec![2, 5, 4, 2]], from_depot);
+        assert!(matches!(
+            out_of_range,
+            Err(Error::ParamsParse(
+                cost_generator::ParamsParseError::InvalidValue(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn battery_trace_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.battery_capacity = Some(2.8);
+        params.hover_times = Some(vec![10.0, 20.0, 0.0, 0.0, 0.0, 30.0]);
+        let file = std::env::temp_dir().join("navigation_battery_trace_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let trace = |args: &[&str]| {
+            let config = Config::from_iter(["navigation", "params.json", "-e"].iter().chain(args));
+            solve_params(&file, &Optimize::Energy, &config, |data| {
+                Ok((
+                    data.remaining_capacity.clone(),
+                    data.first_infeasible,
+                    data.capacity_cost,
+                    data.path.len(),
+                ))
+            })
+        };
+        let without = trace(&[]);
+        let with = trace(&["--battery-trace"]);
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(without.unwrap().0.is_empty());
+        let (remaining, first_infeasible, capacity_cost, len) = with.unwrap();
+        assert_eq!(len, remaining.len());
+        assert_eq!(Some(&2.8), remaining.first());
+        assert!(remaining.windows(2).all(|w| w[1] < w[0]));
+        assert!(approx_eq!(
+            f64,
+            2.8 - capacity_cost,
+            *remaining.last().unwrap(),
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        // The route takes more than the battery capacity
+        let first = first_infeasible.unwrap();
+        assert!(remaining[first] < 0.0);
+        assert!(remaining[..first].iter().all(|&c| c >= 0.0));
+    }
+
+    #[test]
+    fn free_return_battery_trace_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.battery_capacity = Some(2.8);
+        let file = std::env::temp_dir().join("navigation_free_return_battery_trace_test.json");
+        std::fs::write(&file, serde_json::to_string(&params).unwrap()).unwrap();
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "-e",
+            "--free-return",
+            "--battery-trace",
+        ]);
+        let trace = solve_params(&file, &Optimize::Energy, &config, |data| {
+            Ok((data.remaining_capacity.clone(), data.capacity_cost))
+        });
+        std::fs::remove_file(&file).unwrap();
+
+        // The trace ends with the capacity left by the totals without the return edge
+        let (remaining, capacity_cost) = trace.unwrap();
+        let last = remaining.len() - 1;
+        assert!(approx_eq!(
+            f64,
+            2.8 - capacity_cost,
+            remaining[last],
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(approx_eq!(
+            f64,
+            remaining[last - 1] - params.hover_capacity_at(0),
+            remaining[last],
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+    }
+
+    #[test]
+    fn trace_test() {
+        let trace = |args: &[&str]| {
+            let config = Config::from_iter(["navigation", "params.json", "-e"].iter().chain(args));
+            solve_params(
+                Path::new("params.json"),
+                &Optimize::Energy,
+                &config,
+                |data| {
+                    let edges: Vec<(usize, usize)> =
+                        data.edges.iter().map(|e| (e.from, e.to)).collect();
+                    Ok((data.trace.clone(), edges, data.energy_cost))
+                },
+            )
+            .unwrap()
+        };
+        assert!(trace(&[]).0.is_empty());
+
+        for depot in ["0", "3"].iter() {
+            let (steps, edges, cost) = trace(&["--trace", "--depot", depot]);
+            assert_eq!(6, steps.len());
+            assert_eq!(None, steps[0].edge);
+            for (step, edge) in steps.iter().skip(1).zip(&edges) {
+                assert_eq!(Some(*edge), step.edge);
+                // The row of the departure point is crossed out
+                assert!(step
+                    .reduced_matrix
+                    .row(edge.0)
+                    .iter()
+                    .all(|c| c.is_infinite()));
+            }
+            assert!(approx_eq!(
+                f64,
+                cost,
+                steps[5].lower_bound,
+                epsilon = 1e-9,
+                ulps = 0
+            ));
+        }
+    }
+
+    #[test]
+    fn plan_serialize_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.hover_time = 5.0;
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        let json = serde_json::to_string(&plan).unwrap();
+        let restored: Plan = serde_json::from_str(&json).unwrap();
+        assert_eq!(formatter::SCHEMA_VERSION, restored.schema_version);
+        assert_eq!(plan.path, restored.path);
+        assert_eq!(plan.edges, restored.edges);
+        assert_eq!(
+            (
+                plan.energy_cost,
+                plan.time_cost,
+                plan.capacity_cost,
+                plan.distance_cost
+            ),
+            (
+                restored.energy_cost,
+                restored.time_cost,
+                restored.capacity_cost,
+                restored.distance_cost
+            )
+        );
+        assert_eq!(plan.stats, restored.stats);
+        assert_eq!(Optimality::Exact, restored.optimality);
+        assert!(restored.result.get_path().is_empty());
+        assert_eq!(
+            plan.path.iter().collect::<Vec<_>>(),
+            (&restored).into_iter().collect::<Vec<_>>()
+        );
+
+        // The JSON output of the command line tool is a plan as well
+        let mut buf = Vec::new();
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--format", "json"]);
+        run_to_writer(config, &mut buf).unwrap();
+        let output: Plan = serde_json::from_slice(&buf).unwrap();
+        let sample = crate::plan(&Params::from_file("params.json").unwrap(), Optimize::Energy);
+        let sample = sample.unwrap();
+        assert_eq!(sample.path, output.path);
+        assert_eq!(sample.edges, output.edges);
+        assert_eq!(sample.energy_cost, output.energy_cost);
+        assert_eq!(None, output.stats);
+    }
+
+    #[test]
+    fn costs_against_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.hover_time = 5.0;
+        let plan = plan(&params, Optimize::Time).unwrap();
+        let (energy, time, capacity, distance) = plan.result.costs_against(&plan.cost_matrices);
+        let data = path_output(&params, &plan, false).unwrap();
+
+        // The output totals add hovering to the edge costs
+        let stops = || plan.path.iter().skip(1).map(|p| p.point_index);
+        let hover_energy: f64 = stops().map(|i| params.hover_energy_at(i)).sum();
+        let hover_time: f64 = stops().map(|i| params.hover_time_at(i)).sum();
+        let hover_capacity: f64 = stops().map(|i| params.hover_capacity_at(i)).sum();
+        assert!(hover_energy > 0.0);
+        assert!(approx_eq!(
+            f64,
+            data.energy_cost,
+            energy + hover_energy,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(approx_eq!(
+            f64,
+            data.time_cost,
+            time + hover_time,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert!(approx_eq!(
+            f64,
+            data.capacity_cost,
+            capacity + hover_capacity,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+        assert_eq!(data.distance_cost, distance);
+        let energy_edges: f64 = data.edges.iter().map(|e| e.energy).sum();
+        assert!(approx_eq!(
+            f64,
+            energy_edges,
+            energy,
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+    }
+
+    #[test]
+    fn dry_run_test() {
+        let config = Config::from_iter(["navigation", "params.json", "-e", "--dry-run"]);
+        let size = problem_size(Path::new("params.json"), Optimize::Energy, &config).unwrap();
+        assert_eq!(6, size.n);
+        assert_eq!(30, size.finite_edges);
+        assert!(size.isolated.is_empty());
+        let bound = size.nn_bound.unwrap();
+        assert!(bound.is_finite());
+        let optimal = plan(&Params::from_file("params.json").unwrap(), Optimize::Energy).unwrap();
+        assert!(bound >= optimal.result.get_cost());
+
+        let out = std::env::temp_dir().join("navigation_dry_run_test.json");
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "-e",
+            "--dry-run",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+        run(config).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(6, json[0]["n"]);
+        assert!(json[0].get("path").is_none());
+    }
+
+    #[test]
+    fn drones_command_test() {
+        let out = std::env::temp_dir().join("navigation_drones_test.json");
+        let args = [
+            "navigation",
+            "params.json",
+            "-e",
+            "--drones",
+            "2",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ];
+        run(Config::from_iter(args)).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+
+        let drones = json["drones"].as_array().unwrap();
+        assert_eq!(2, drones.len());
+        let costs: Vec<f64> = drones
+            .iter()
+            .map(|d| d["energy_cost"].as_f64().unwrap())
+            .collect();
+        assert_eq!(costs.iter().cloned().fold(0.0, f64::max), json["makespan"]);
+
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "params.json",
+            "-e",
+            "--drones",
+            "2",
+        ]);
+        assert!(matches!(run(config), Err(Error::DronesWithMultipleFiles)));
+    }
+
+    #[test]
+    fn generate_command_test() {
+        let out = std::env::temp_dir().join("navigation_generate_test.json");
+        let config = Config::from_iter([
+            "navigation",
+            "generate",
+            "params.json",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+        run(config).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+
+        let n = Params::from_file("params.json").unwrap().coords.len();
+        for key in &["energy", "time", "capacity", "distance"] {
+            assert_eq!(n, json[key].as_array().unwrap().len());
+        }
+        assert!(json.get("path").is_none());
+    }
+
+    #[test]
+    fn validate_command_test() {
+        run(Config::from_iter(["navigation", "validate", "params.json"])).unwrap();
+
+        match run(Config::from_iter([
+            "navigation",
+            "validate",
+            "missing_params.json",
+        ])) {
+            Err(Error::ParamsParse(cost_generator::ParamsParseError::IoWithPath { .. })) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn required_test() {
+        let s = r#"
+        {
+            "battery_voltage": 22.8,
+            "speed_horizontal": 12.5,
+            "speed_up": 3.1,
+            "speed_down": 3,
+            "power_horizontal": 486.2,
+            "power_up": 899.04,
+            "power_down": 309.17,
+            "power_hover": 545.8,
+            "coords": [
+                {"x": 0, "y": 0, "z": 0},
+                {"x": 100, "y": 0, "z": 0},
+                {"x": 100, "y": 100, "z": 0},
+                {"x": 5000, "y": 5000, "z": 0}
+            ]
+        }
+        "#;
+        let mut params: Params = s.parse().unwrap();
+        let all = plan(&params, Optimize::Energy).unwrap();
+
+        params.required = Some(vec![1, 2]);
+        let required = plan(&params, Optimize::Energy).unwrap();
+        assert!(required.path.iter().all(|p| p.point_index != 3));
+        assert_eq!(4, required.path.len());
+        assert!(required.result.get_cost() < all.result.get_cost());
+
+        let mut config = Config::from_iter(["navigation", "params.json", "-e", "--all"]);
+        config.heuristic = Some(Heuristic::NearestNeighbor);
+        let constraints = search_constraints(&params);
+        let cost = CostMatrices::generate(&params).energy;
+        match find_path(&cost, &constraints, &config) {
+            Err(Error::UnsupportedConstraints) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match optimal_tours(&cost, &constraints, &config) {
+            Err(Error::OptionalPointsWithMultiplePaths) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_coords_test() {
+        let params_file = std::env::temp_dir().join("navigation_duplicate_coords_test.json");
+        let out = std::env::temp_dir().join("navigation_duplicate_coords_test.txt");
+        std::fs::write(
+            &params_file,
+            r#"
+            {
+                "battery_voltage": 22.8,
+                "speed_horizontal": 12.5,
+                "speed_up": 3.1,
+                "speed_down": 3,
+                "power_horizontal": 486.2,
+                "power_up": 899.04,
+                "power_down": 309.17,
+                "power_hover": 545.8,
+                "coords": [
+                    {"x": 0, "y": 0, "z": 0},
+                    {"x": 10, "y": 200, "z": 300},
+                    {"x": 10, "y": 200, "z": 300},
+                    {"x": 200, "y": 450, "z": 12}
+                ]
+            }
+            "#,
+        )
+        .unwrap();
+        let args = [
+            "navigation",
+            params_file.to_str().unwrap(),
+            "-e",
+            "--out",
+            out.to_str().unwrap(),
+        ];
+
+        run(Config::from_iter(args)).unwrap();
+        match run(Config::from_iter(args.iter().chain(&["--strict"]))) {
+            Err(e @ Error::DuplicateCoords(_)) => {
+                assert_eq!("Coinciding points: 2 and 3", e.to_string());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        std::fs::remove_file(&params_file).unwrap();
+        std::fs::remove_file(&out).unwrap();
+    }
+
+    #[test]
+    fn nan_costs_test() {
+        // Points 2 and 3 differ only in altitude and the airspeed is zero
+        let args = ["navigation", "nan_params.json", "-e", "--format", "json"];
+
+        let mut out = Vec::new();
+        let solved = run_to_writer(Config::from_iter(args), &mut out);
+        let strict = run(Config::from_iter(args.iter().chain(&["--strict"])));
+        solved.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(json["energy_cost"].as_f64().unwrap().is_finite());
+        match strict {
+            Err(e @ Error::NanCost { from: 1, to: 2 }) => assert_eq!(
+                "Cost of the edge from point 2 to point 3 is not a number",
+                e.to_string()
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn base_params_test() {
+        let mission = std::env::temp_dir().join("navigation_base_params_test.json");
+        std::fs::write(&mission, r#"{"payload_kg": 1.5, "power_per_kg": 100}"#).unwrap();
+        let config = Config::from_iter([
+            "navigation",
+            mission.to_str().unwrap(),
+            "-e",
+            "--base",
+            "params.json",
+        ]);
+        let params = load_params(&mission, &config);
+        std::fs::remove_file(&mission).unwrap();
+
+        let params = params.unwrap();
+        let base = Params::from_file("params.json").unwrap();
+        assert_eq!(1.5, params.payload_kg);
+        assert_eq!(base.coords, params.coords);
+        assert_eq!(base.power_hover, params.power_hover);
+    }
+
+    #[test]
+    fn interactive_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let mut heavy = params.clone();
+        heavy.payload_kg = 2.0;
+        heavy.power_per_kg = 50.0;
+        let input = [
+            serde_json::to_string(&params).unwrap(),
+            String::from("{\"battery_voltage\": }"),
+            String::new(),
+            serde_json::to_string(&heavy).unwrap(),
+        ]
+        .join("\n");
+        let config = Config::from_iter([
+            "navigation",
+            "--interactive",
+            "-e",
+            "--format",
+            "json",
+            "--compact",
+        ]);
+
+        let mut out = Vec::new();
+        match run_interactive(input.as_bytes(), &Optimize::Energy, &config, &mut out) {
+            Err(Error::BatchFailed {
+                failed: 1,
+                total: 3,
+            }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let results: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(2, results.len());
+        let cost = |i: usize| results[i]["energy_cost"].as_f64().unwrap();
+        assert!(cost(0) < cost(1));
+    }
+
+    #[test]
+    fn golden_json_output_test() {
+        // Rounding hides the platform-dependent last digits of the costs
+        let config = Config::from_iter([
+            "navigation",
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--deterministic",
+            "--round",
+            "6",
+        ]);
+        let mut out = Vec::new();
+        run_to_writer(config, &mut out).unwrap();
+
+        // Compared as text to catch the reordered and renamed keys
+        assert_eq!(
+            include_str!("../params.golden.json").trim_end(),
+            String::from_utf8(out).unwrap().trim_end(),
+            "JSON output changed, update params.golden.json and SCHEMA_VERSION if it's incompatible"
+        );
+    }
+
+    #[test]
+    fn elapsed_ms_test() {
+        let out = std::env::temp_dir().join("navigation_elapsed_ms_test.json");
+        let args = [
+            "navigation",
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--out",
+            out.to_str().unwrap(),
+        ];
+        run(Config::from_iter(args)).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        assert!(json["elapsed_ms"].as_f64().unwrap() >= 0.0);
+
+        run(Config::from_iter(args.iter().chain(&["--deterministic"]))).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(File::open(&out).unwrap()).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(json.get("elapsed_ms").is_none());
+    }
+}
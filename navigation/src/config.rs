@@ -1,12 +1,24 @@
+#[cfg(feature = "cli")]
 extern crate clap;
 
+#[cfg(feature = "cli")]
 use self::clap::{ArgGroup, ArgMatches};
-use clap::{App, AppSettings, Arg};
-use std::ffi::OsString;
+#[cfg(feature = "cli")]
+use crate::path_finder;
+#[cfg(feature = "cli")]
+use crate::path_finder::SearchOrder;
+#[cfg(feature = "cli")]
+use clap::{value_t, App, AppSettings, Arg, ErrorKind, Shell, SubCommand};
+#[cfg(feature = "cli")]
+use std::ffi::{OsStr, OsString};
+#[cfg(feature = "cli")]
+use std::io::{stdout, Write};
 use std::path::PathBuf;
+#[cfg(feature = "cli")]
+use std::time::Duration;
 
 /// Defines the system parameter to be optimized.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Optimize {
     /// Optimize by intuitive flight **(m)**.
     Intuitive,
@@ -21,36 +33,416 @@ pub enum Optimize {
     Energy,
 }
 
+/// Defines the format of the result output.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Text,
+
+    /// JSON document.
+    Json,
+
+    /// GitHub-flavored Markdown report.
+    Markdown,
+
+    /// Graphviz digraph of the path.
+    Dot,
+
+    /// SVG plot of the path.
+    Svg,
+}
+
+/// Defines the cost matrix included into the output.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MatrixKind {
+    Energy,
+    Time,
+    Capacity,
+    Distance,
+}
+
+impl MatrixKind {
+    /// Matrices output by default: energy, capacity, time
+    /// and the matrix of the `optimize` parameter.
+    pub fn defaults(optimize: Optimize) -> Vec<MatrixKind> {
+        let mut matrices = vec![MatrixKind::Energy, MatrixKind::Capacity, MatrixKind::Time];
+        if optimize == Optimize::Intuitive {
+            matrices.push(MatrixKind::Distance);
+        }
+        matrices
+    }
+}
+
+/// Defines the path search algorithm.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Solver {
+    /// Branch and bound with the reduced cost matrix.
+    BranchAndBound,
+
+    /// Held–Karp dynamic programming, suitable for up to 20 points.
+    HeldKarp,
+
+    /// Nearest neighbor with 2-opt from several random start points,
+    /// the path may be not optimal.
+    RandomRestart,
+
+    /// Branch and bound keeping a limited number of nodes at each level,
+    /// the path may be not optimal.
+    Beam,
+}
+
+/// Defines the heuristic used instead of the exact path search.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Heuristic {
+    /// Greedily visit the nearest unvisited point.
+    NearestNeighbor,
+
+    /// Improve the nearest neighbor path with 2-opt moves.
+    NearestNeighborTwoOpt,
+}
+
+/// Defines where the cost matrix to be solved comes from.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// Generate cost matrices from the drone parameters file
+    /// and optimize by the specified system parameter.
+    /// Multiple files are solved one by one.
+    Params {
+        files: Vec<PathBuf>,
+        optimize: Optimize,
+    },
+
+    /// Read a precomputed `N x N` cost matrix from the JSON file.
+    Matrix(PathBuf),
+}
+
+/// Defines what the tool does.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Find the optimal path, the default subcommand.
+    Solve(Input),
+
+    /// Output the cost matrices generated from the drone parameters file.
+    Generate(PathBuf),
+
+    /// Parse and validate the drone parameters file.
+    Validate(PathBuf),
+}
+
+/// Command line options, requires the `cli` feature.
+#[cfg(feature = "cli")]
 #[derive(Debug)]
 pub struct Config {
-    pub params_file: PathBuf,
+    pub command: Command,
     pub out_filename: Option<PathBuf>,
-    pub out_as_json: bool,
-    pub optimize: Optimize,
+    pub format: OutputFormat,
+    /// Write JSON without indentation and line breaks.
+    pub compact: bool,
+    /// Number of digits after the decimal point in JSON output, full precision by default.
+    pub round: Option<usize>,
+    /// Include the drone parameters into JSON output.
+    pub echo_params: bool,
+    /// Cost matrices to output, [`MatrixKind::defaults`] if not specified.
+    /// Empty if the matrices are omitted.
+    pub show_matrices: Option<Vec<MatrixKind>>,
+    /// Include the remaining battery capacity at each point of the path.
+    pub battery_trace: bool,
+    /// Load the cost matrices from this file, or save them if it's absent or stale.
+    pub cache: Option<PathBuf>,
+    /// Snap the coordinates to the grid with this step, overrides `snap_grid` of the params.
+    pub snap: Option<f64>,
+    /// Generate the costs with the coordinates translated so that the depot
+    /// is at the origin, see [`crate::Params::recentered`].
+    pub recenter: bool,
+    /// Fail instead of warning about the coinciding points
+    /// or the extreme edges of the found path.
+    pub strict: bool,
+    /// Warn about the edges of the found path costing more than this.
+    /// The practically infinite edges are reported anyway.
+    pub max_edge_cost: Option<f64>,
+    /// Omit the computation time from output, so it's the same between runs.
+    pub deterministic: bool,
+    /// Only report the problem size and the nearest neighbor bound, without solving.
+    pub dry_run: bool,
+    /// Read the params from stdin, one compact JSON object per line,
+    /// and solve each until the end of the input.
+    pub interactive: bool,
+    /// Don't count the return to the start point, e.g. for the drones
+    /// recovered by a vehicle. The tour is still closed and ends with
+    /// the return edge, only its costs are excluded from the search
+    /// and from the totals.
+    pub free_return: bool,
+    /// Split the points among this number of drones starting from the depot.
+    pub drones: Option<usize>,
+    /// CSV or WKT file with the coordinates, overrides `coords` of the params.
+    pub coords: Option<PathBuf>,
+    /// Params file of the drone profile, the params files specify only
+    /// the fields overriding it, see [`crate::Params::merge`].
+    pub base: Option<PathBuf>,
+    /// Zero-based index of the depot point, overrides `depot` of the params.
+    pub depot: Option<usize>,
+    /// Path search algorithm.
+    pub solver: Solver,
+    /// Number of the runs of the random restart solver.
+    pub restarts: usize,
+    /// Seed of the random restart solver, the same seed gives the same path.
+    pub seed: u64,
+    /// Number of the nodes kept at each level by the beam solver.
+    pub width: usize,
+    /// Find an approximate path with the heuristic
+    /// instead of the exact one, if specified.
+    pub heuristic: Option<Heuristic>,
+    /// Parameters breaking the ties of the optimized one among the optimal
+    /// paths, in order of priority. Empty unless the lexicographic
+    /// optimization is requested.
+    pub tie_breaks: Vec<Optimize>,
+    /// Maximum number of nodes kept in the search queue, unlimited if not specified.
+    pub queue_cap: Option<usize>,
+    /// Keep the search nodes in single precision to save memory,
+    /// see [`crate::path_finder::SearchOptions::single_precision`].
+    pub single_precision: bool,
+    /// Order of the branch-and-bound search.
+    pub search_order: SearchOrder,
+    /// Search time limit, unlimited if not specified.
+    pub timeout: Option<Duration>,
+    /// Output path search statistics.
+    pub stats: bool,
+    /// Output the reduced cost matrix and the lower bound of each
+    /// branch-and-bound node on the way to the found path.
+    pub trace: bool,
+    /// Output the cost of the found tour traversed in reverse
+    /// as a sanity check of the matrix symmetry.
+    pub verify: bool,
+    /// Print the search progress to stderr.
+    pub progress: bool,
+    /// Output all the paths of the minimum cost.
+    pub all: bool,
+    /// Output the specified number of the cheapest paths.
+    pub k_best: Option<usize>,
+    /// Number of digits after the decimal point in text and Markdown output.
+    pub precision: usize,
 }
 
+#[cfg(feature = "cli")]
 impl Config {
     pub fn from_args() -> Self {
-        let matches = Self::make_app().get_matches();
-
-        Self::make_config(matches)
+        Self::from_iter(std::env::args_os())
     }
 
+    /// Parses the arguments, the `solve` subcommand
+    /// is used if no subcommand is specified.
     pub fn from_iter<I, T>(itr: I) -> Self
     where
         I: IntoIterator<Item = T>,
         T: Into<OsString> + Clone,
     {
-        let matches = Self::make_app().get_matches_from(itr);
+        let matches = Self::make_app().get_matches_from(Self::with_default_subcommand(itr));
 
         Self::make_config(matches)
     }
 
+    fn with_default_subcommand<I, T>(itr: I) -> Vec<OsString>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        const KNOWN: [&str; 9] = [
+            "solve",
+            "generate",
+            "validate",
+            "help",
+            "-h",
+            "--help",
+            "-V",
+            "--version",
+            "--completions",
+        ];
+
+        let mut args: Vec<OsString> = itr.into_iter().map(Into::into).collect();
+        let is_known = |arg: &OsString| {
+            KNOWN.iter().any(|&k| arg == OsStr::new(k))
+                || arg.to_string_lossy().starts_with("--completions=")
+        };
+        match args.get(1) {
+            Some(arg) if !is_known(arg) => {
+                args.insert(1, OsString::from("solve"));
+            }
+            _ => (),
+        }
+
+        args
+    }
+
+    /// Writes the completion script for the `shell` to the `writer`.
+    pub fn write_completions<W: Write>(shell: Shell, writer: &mut W) {
+        Self::make_app().gen_completions_to(clap::crate_name!(), shell, writer);
+    }
+
     fn make_config(matches: ArgMatches) -> Self {
-        let params_file = PathBuf::from(matches.value_of("params_file").unwrap());
+        if let Some(shell) = matches.value_of("completions") {
+            Self::write_completions(shell.parse().unwrap(), &mut stdout());
+            std::process::exit(0);
+        }
+
+        let (name, matches) = matches.subcommand();
+        let matches = matches.unwrap_or_else(|| {
+            clap::Error::with_description("A subcommand is required", ErrorKind::MissingSubcommand)
+                .exit()
+        });
         let out_filename = matches.value_of("out").map(PathBuf::from);
-        let out_as_json = matches.is_present("json");
-        let optimize = if matches.is_present("intuitive") {
+        // --json is a deprecated alias of --format json
+        let format = match (matches.value_of("format"), matches.is_present("json")) {
+            (Some("json"), _) | (None, true) => OutputFormat::Json,
+            (Some("md"), _) => OutputFormat::Markdown,
+            (Some("dot"), _) => OutputFormat::Dot,
+            (Some("svg"), _) => OutputFormat::Svg,
+            _ => OutputFormat::Text,
+        };
+        let compact = matches.is_present("compact");
+        let round = matches
+            .value_of("round")
+            .map(|_| value_t!(matches, "round", usize).unwrap_or_else(|e| e.exit()));
+        let echo_params = matches.is_present("echo_params");
+        let show_matrices = match matches.values_of("show_matrices") {
+            _ if matches.is_present("no_matrices") => Some(Vec::new()),
+            Some(names) => Some(
+                names
+                    .map(|name| match name {
+                        "energy" => MatrixKind::Energy,
+                        "time" => MatrixKind::Time,
+                        "capacity" => MatrixKind::Capacity,
+                        _ => MatrixKind::Distance,
+                    })
+                    .collect(),
+            ),
+            None => None,
+        };
+        let battery_trace = matches.is_present("battery_trace");
+        let cache = matches.value_of("cache").map(PathBuf::from);
+        let strict = matches.is_present("strict");
+        let max_edge_cost = matches
+            .value_of("max_edge_cost")
+            .map(|c| c.parse().unwrap());
+        let deterministic = matches.is_present("deterministic");
+        let dry_run = matches.is_present("dry_run");
+        let interactive = matches.is_present("interactive");
+        let free_return = matches.is_present("free_return");
+        let drones = matches.value_of("drones").map(|k| k.parse().unwrap());
+        let coords = matches.value_of("coords").map(PathBuf::from);
+        let base = matches.value_of("base").map(PathBuf::from);
+        let depot = matches.value_of("depot").map(|i| i.parse().unwrap());
+        let snap = matches.value_of("snap").map(|g| g.parse().unwrap());
+        let recenter = matches.is_present("recenter");
+        let solver = match matches.value_of("solver") {
+            Some("held-karp") => Solver::HeldKarp,
+            Some("rand-restart") => Solver::RandomRestart,
+            Some("beam") => Solver::Beam,
+            _ => Solver::BranchAndBound,
+        };
+        let restarts = matches
+            .value_of("restarts")
+            .map_or(path_finder::RESTARTS, |_| {
+                value_t!(matches, "restarts", usize).unwrap_or_else(|e| e.exit())
+            });
+        let seed = matches.value_of("seed").map_or(0, |_| {
+            value_t!(matches, "seed", u64).unwrap_or_else(|e| e.exit())
+        });
+        let width = matches
+            .value_of("width")
+            .map_or(path_finder::BEAM_WIDTH, |_| {
+                value_t!(matches, "width", usize).unwrap_or_else(|e| e.exit())
+            });
+        let heuristic = matches.value_of("heuristic").map(|h| match h {
+            "nn" => Heuristic::NearestNeighbor,
+            "nn+2opt" => Heuristic::NearestNeighborTwoOpt,
+            _ => unreachable!(),
+        });
+        let tie_breaks = matches.values_of("lex").map_or_else(Vec::new, |lex| {
+            lex.skip(1).map(Self::parse_optimize).collect()
+        });
+        let single_precision = matches.is_present("single_precision");
+        let search_order = match matches.value_of("search") {
+            Some("dfs") => SearchOrder::DepthFirst,
+            _ => SearchOrder::BestFirst,
+        };
+        let queue_cap = matches
+            .value_of("queue_cap")
+            .map(|_| value_t!(matches, "queue_cap", usize).unwrap_or_else(|e| e.exit()));
+        let timeout = matches
+            .value_of("timeout")
+            .map(|t| Duration::from_secs_f64(t.parse().unwrap()));
+        let stats = matches.is_present("stats");
+        let trace = matches.is_present("trace");
+        let verify = matches.is_present("verify");
+        let progress = matches.is_present("progress");
+        let all = matches.is_present("all");
+        let k_best = matches
+            .value_of("k")
+            .map(|_| value_t!(matches, "k", usize).unwrap_or_else(|e| e.exit()));
+        let precision = matches.value_of("precision").map_or(3, |_| {
+            value_t!(matches, "precision", usize).unwrap_or_else(|e| e.exit())
+        });
+        let params_file = || PathBuf::from(matches.value_of("params_file").unwrap());
+        let command = match name {
+            "generate" => Command::Generate(params_file()),
+            "validate" => Command::Validate(params_file()),
+            _ => Command::Solve(match matches.value_of("matrix") {
+                Some(matrix_file) => Input::Matrix(PathBuf::from(matrix_file)),
+                None => Input::Params {
+                    files: matches
+                        .values_of("params_file")
+                        .map_or_else(Vec::new, |files| files.map(PathBuf::from).collect()),
+                    optimize: Self::make_optimize(matches),
+                },
+            }),
+        };
+
+        Config {
+            command,
+            out_filename,
+            format,
+            compact,
+            round,
+            echo_params,
+            show_matrices,
+            battery_trace,
+            cache,
+            snap,
+            recenter,
+            strict,
+            max_edge_cost,
+            deterministic,
+            dry_run,
+            interactive,
+            free_return,
+            drones,
+            coords,
+            base,
+            depot,
+            solver,
+            restarts,
+            seed,
+            width,
+            heuristic,
+            tie_breaks,
+            queue_cap,
+            single_precision,
+            search_order,
+            timeout,
+            stats,
+            trace,
+            verify,
+            progress,
+            all,
+            k_best,
+            precision,
+        }
+    }
+
+    fn make_optimize(matches: &ArgMatches) -> Optimize {
+        if let Some(mut lex) = matches.values_of("lex") {
+            Self::parse_optimize(lex.next().unwrap())
+        } else if matches.is_present("intuitive") {
             Optimize::Intuitive
         } else if matches.is_present("time") {
             Optimize::Time
@@ -60,13 +452,16 @@ impl Config {
             Optimize::Energy
         } else {
             unreachable!();
-        };
+        }
+    }
 
-        Config {
-            params_file,
-            out_filename,
-            out_as_json,
-            optimize,
+    fn parse_optimize(name: &str) -> Optimize {
+        match name {
+            "intuitive" => Optimize::Intuitive,
+            "time" => Optimize::Time,
+            "battery" => Optimize::Battery,
+            "energy" => Optimize::Energy,
+            _ => unreachable!(),
         }
     }
 
@@ -74,26 +469,326 @@ impl Config {
         App::new(clap::crate_name!())
             .version(clap::crate_version!())
             .setting(AppSettings::ArgRequiredElseHelp)
+            .after_help(
+                "EXIT CODES:\n    0    Success\n    1    Invalid arguments or some params files failed\n    \
+                 2    Invalid params, matrix or options\n    3    No path can be found\n    \
+                 4    Reading or writing the files failed",
+            )
+            .arg(
+                Arg::with_name("completions")
+                    .long("completions")
+                    .value_name("shell")
+                    .help("Print the shell completion script")
+                    .takes_value(true)
+                    .possible_values(&["bash", "zsh", "fish"])
+                    .hidden(true),
+            )
+            .subcommand(Self::make_solve_app())
+            .subcommand(
+                SubCommand::with_name("generate")
+                    .about("Output the cost matrices generated from the drone parameters")
+                    .arg(
+                        Arg::with_name("params_file")
+                            .help("Drone parameters file")
+                            .required(true),
+                    )
+                    .args(&Self::output_args()),
+            )
+            .subcommand(
+                SubCommand::with_name("validate")
+                    .about("Check the drone parameters file")
+                    .arg(
+                        Arg::with_name("params_file")
+                            .help("Drone parameters file")
+                            .required(true),
+                    ),
+            )
+    }
+
+    fn make_solve_app<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name("solve")
+            .about("Find the optimal path, the default subcommand")
+            .setting(AppSettings::ArgRequiredElseHelp)
             .arg(
                 Arg::with_name("params_file")
-                    .help("Drone parameters file")
-                    .required(true),
+                    .help("Drone parameters files")
+                    .multiple(true)
+                    .required_unless_one(&["matrix", "interactive"])
+                    .conflicts_with("matrix"),
             )
             .arg(
-                Arg::with_name("out")
-                    .long("out")
+                Arg::with_name("matrix")
+                    .long("matrix")
                     .value_name("filename")
-                    .help("Write result to the specified file")
+                    .help("Solve a precomputed N x N cost matrix from the JSON file")
                     .takes_value(true),
             )
+            .args(&Self::output_args())
+            .arg(
+                Arg::with_name("solver")
+                    .long("solver")
+                    .value_name("name")
+                    .help("Path search algorithm, rand-restart and beam find an approximate path")
+                    .takes_value(true)
+                    .possible_values(&["bnb", "held-karp", "rand-restart", "beam"])
+                    .default_value("bnb"),
+            )
+            .arg(
+                Arg::with_name("restarts")
+                    .long("restarts")
+                    .value_name("n")
+                    .help("Number of the random start points of the rand-restart solver [default: 10]")
+                    .takes_value(true)
+                    .validator(|n| match n.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(String::from("must be a positive integer")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .value_name("n")
+                    .help("Seed of the rand-restart solver [default: 0]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("width")
+                    .long("width")
+                    .value_name("n")
+                    .help("Number of the nodes kept at each level by the beam solver, larger is slower but closer to optimal [default: 100]")
+                    .takes_value(true)
+                    .validator(|n| match n.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(String::from("must be a positive integer")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("heuristic")
+                    .long("heuristic")
+                    .value_name("name")
+                    .help("Find an approximate path quickly instead of the exact one")
+                    .takes_value(true)
+                    .possible_values(&["nn", "nn+2opt"])
+                    .conflicts_with("lex"),
+            )
+            .arg(
+                Arg::with_name("queue_cap")
+                    .long("queue-cap")
+                    .value_name("n")
+                    .help("Maximum number of nodes kept in the search queue (the path may be not optimal)")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("single_precision")
+                    .long("single-precision")
+                    .help("Keep the search nodes in f32 to save memory (costs differing by less than 1e-5 may be ranked wrongly)"),
+            )
+            .arg(
+                Arg::with_name("search")
+                    .long("search")
+                    .value_name("order")
+                    .help("Order of the bnb search, dfs keeps much less nodes in memory but may be slower")
+                    .takes_value(true)
+                    .possible_values(&["best", "dfs"])
+                    .default_value("best"),
+            )
+            .arg(
+                Arg::with_name("timeout")
+                    .long("timeout")
+                    .value_name("seconds")
+                    .help("Stop the search after the timeout and output the best path found so far")
+                    .takes_value(true)
+                    .validator(|t| match t.parse::<f64>() {
+                        Ok(t) if t >= 0.0 && t.is_finite() => Ok(()),
+                        _ => Err(String::from("must be a non-negative number of seconds")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .long("stats")
+                    .help("Output path search statistics"),
+            )
+            .arg(
+                Arg::with_name("trace")
+                    .long("trace")
+                    .help("Output the reduced cost matrix and the lower bound at each step of the found path"),
+            )
+            .arg(
+                Arg::with_name("verify")
+                    .long("verify")
+                    .help("Output the cost of the reverse tour, it differs for an asymmetric cost matrix"),
+            )
+            .arg(
+                Arg::with_name("progress")
+                    .long("progress")
+                    .help("Print the search progress to stderr"),
+            )
+            .arg(
+                Arg::with_name("all")
+                    .long("all")
+                    .help("Output all the paths of the minimum cost")
+                    .conflicts_with("heuristic"),
+            )
+            .arg(
+                Arg::with_name("k")
+                    .long("k")
+                    .value_name("n")
+                    .help("Output the n cheapest distinct paths")
+                    .takes_value(true)
+                    .conflicts_with("heuristic")
+                    .validator(|n| match n.parse::<usize>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(String::from("must be a positive integer")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("echo_params")
+                    .long("echo-params")
+                    .help("Include the drone parameters into JSON output"),
+            )
+            .arg(
+                Arg::with_name("no_matrices")
+                    .long("no-matrices")
+                    .help("Omit the cost matrices from output"),
+            )
+            .arg(
+                Arg::with_name("show_matrices")
+                    .long("show-matrices")
+                    .value_name("list")
+                    .help(
+                        "Comma-separated cost matrices to output [default: energy, capacity, \
+                         time and the optimized one]",
+                    )
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .possible_values(&["energy", "time", "capacity", "distance"])
+                    .conflicts_with_all(&["no_matrices", "matrix"]),
+            )
+            .arg(
+                Arg::with_name("battery_trace")
+                    .long("battery-trace")
+                    .help("Output the remaining battery capacity at each point of the path")
+                    .conflicts_with("matrix"),
+            )
+            .arg(
+                Arg::with_name("cache")
+                    .long("cache")
+                    .value_name("file")
+                    .help("Load the cost matrices from the file, or generate and save them there")
+                    .takes_value(true)
+                    .conflicts_with("matrix"),
+            )
+            .arg(
+                Arg::with_name("deterministic")
+                    .long("deterministic")
+                    .help("Omit the computation time from output, e.g. for golden-file tests"),
+            )
+            .arg(
+                Arg::with_name("free_return")
+                    .long("free-return")
+                    .help(
+                        "Don't count the return to the start point into the costs, e.g. for \
+                         one-way deliveries. The path still ends with the return edge",
+                    ),
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("dry-run")
+                    .help("Report the problem size, isolated points and the nearest neighbor bound without solving")
+                    .conflicts_with("matrix"),
+            )
+            .arg(
+                Arg::with_name("interactive")
+                    .long("interactive")
+                    .help("Read the params from stdin, one JSON object per line, and solve each until the end of input")
+                    .conflicts_with_all(&["params_file", "matrix", "dry_run", "drones"]),
+            )
+            .arg(
+                Arg::with_name("drones")
+                    .long("drones")
+                    .value_name("k")
+                    .help("Split the points among k drones starting from the depot, minimizing the longest route")
+                    .takes_value(true)
+                    .conflicts_with_all(&["matrix", "all", "k", "lex"])
+                    .validator(|k| match k.parse::<usize>() {
+                        Ok(k) if k > 0 => Ok(()),
+                        _ => Err(String::from("must be a positive integer")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("coords")
+                    .long("coords")
+                    .value_name("file")
+                    .help("Read the coordinates from the CSV file of x,y,z rows or the WKT file of points instead of the params")
+                    .takes_value(true)
+                    .conflicts_with("matrix"),
+            )
+            .arg(
+                Arg::with_name("base")
+                    .long("base")
+                    .value_name("file")
+                    .help("Read the base drone profile from the params file, the params files override only the specified fields")
+                    .takes_value(true)
+                    .conflicts_with("matrix"),
+            )
+            .arg(
+                Arg::with_name("depot")
+                    .long("depot")
+                    .value_name("index")
+                    .help("Zero-based index of the point where the route starts and each leg returns [default: 0]")
+                    .takes_value(true)
+                    .conflicts_with("matrix")
+                    .validator(|i| match i.parse::<usize>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err(String::from("must be a non-negative integer")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("strict")
+                    .help("Fail if any points coincide or the path has extreme edges instead of warning"),
+            )
+            .arg(
+                Arg::with_name("max_edge_cost")
+                    .long("max-edge-cost")
+                    .value_name("cost")
+                    .help("Warn if the path has an edge costing more than this")
+                    .takes_value(true)
+                    .validator(|c| match c.parse::<f64>() {
+                        Ok(c) if c >= 0.0 => Ok(()),
+                        _ => Err(String::from("must be a non-negative number")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("snap")
+                    .long("snap")
+                    .value_name("grid")
+                    .help("Round the coordinates to the grid and merge the points in the same cell")
+                    .takes_value(true)
+                    .conflicts_with("matrix")
+                    .validator(|g| match g.parse::<f64>() {
+                        Ok(g) if g > 0.0 && g.is_finite() => Ok(()),
+                        _ => Err(String::from("must be a positive number")),
+                    }),
+            )
+            .arg(
+                Arg::with_name("recenter")
+                    .long("recenter")
+                    .help(
+                        "Move the depot to the origin when computing the costs, \
+                         improves precision for large coordinates",
+                    )
+                    .conflicts_with("matrix"),
+            )
             .arg(
                 Arg::with_name("json")
                     .long("json")
-                    .help("Output result as JSON"),
+                    .help("Output result as JSON (deprecated, use --format json)")
+                    .conflicts_with("format"),
             )
             .group(
                 ArgGroup::with_name("optimize")
-                    .args(&["intuitive", "time", "battery", "energy"])
+                    .args(&["intuitive", "time", "battery", "energy", "lex", "matrix"])
                     .required(true),
             )
             .arg(
@@ -120,23 +815,68 @@ impl Config {
                     .long("energy")
                     .short("e"),
             )
+            .arg(
+                Arg::with_name("lex")
+                    .long("lex")
+                    .value_name("list")
+                    .help(
+                        "Optimize by the first of the comma-separated parameters, \
+                         breaking the ties by the next ones in order",
+                    )
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .possible_values(&["intuitive", "time", "battery", "energy"]),
+            )
+    }
+
+    /// Output arguments shared by the subcommands.
+    fn output_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+        vec![
+            Arg::with_name("out")
+                .long("out")
+                .value_name("filename")
+                .help("Write result to the specified file")
+                .takes_value(true),
+            Arg::with_name("precision")
+                .long("precision")
+                .value_name("n")
+                .help("Number of digits after the decimal point in text and Markdown output")
+                .takes_value(true)
+                .default_value("3"),
+            Arg::with_name("format")
+                .long("format")
+                .value_name("name")
+                .help("Output format [default: text]")
+                .takes_value(true)
+                .possible_values(&["text", "json", "md", "dot", "svg"]),
+            Arg::with_name("compact")
+                .long("compact")
+                .help("Output JSON without indentation and line breaks"),
+            Arg::with_name("round")
+                .long("round")
+                .value_name("n")
+                .help("Round the numbers in JSON output to n digits after the decimal point")
+                .takes_value(true),
+        ]
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "cli"))]
 mod tests {
     use super::*;
 
     #[test]
     fn parse_args_test() {
-        let matches = Config::make_app().get_matches_from(&[
+        let matches = Config::make_app().get_matches_from([
             clap::crate_name!(),
+            "solve",
             "params.json",
             "--out",
             "result.json",
             "--json",
             "-e",
         ]);
+        let matches = matches.subcommand_matches("solve").unwrap();
         assert_eq!(Some("params.json"), matches.value_of("params_file"));
         assert_eq!(Some("result.json"), matches.value_of("out"));
         assert!(matches.is_present("json"));
@@ -148,7 +888,7 @@ mod tests {
 
     #[test]
     fn make_config_test() {
-        let config = Config::from_iter(&[
+        let config = Config::from_iter([
             clap::crate_name!(),
             "params.json",
             "--out",
@@ -156,9 +896,491 @@ mod tests {
             "--json",
             "-e",
         ]);
-        assert_eq!(Some("params.json"), config.params_file.to_str());
+        assert_eq!(
+            Command::Solve(Input::Params {
+                files: vec![PathBuf::from("params.json")],
+                optimize: Optimize::Energy,
+            }),
+            config.command
+        );
         assert_eq!(Some("result.json"), config.out_filename.unwrap().to_str());
-        assert!(config.out_as_json);
-        assert_eq!(Optimize::Energy, config.optimize);
+        assert_eq!(OutputFormat::Json, config.format);
+        assert!(!config.compact);
+        assert!(!config.echo_params);
+        assert_eq!(None, config.show_matrices);
+        assert!(!config.battery_trace);
+        assert!(!config.trace);
+        assert!(!config.verify);
+        assert_eq!(None, config.cache);
+        assert_eq!(None, config.snap);
+        assert!(!config.recenter);
+        assert!(!config.strict);
+        assert_eq!(None, config.max_edge_cost);
+        assert!(!config.deterministic);
+        assert!(!config.dry_run);
+        assert!(!config.interactive);
+        assert_eq!(None, config.drones);
+        assert_eq!(None, config.coords);
+        assert_eq!(None, config.base);
+        assert_eq!(None, config.depot);
+        assert_eq!(3, config.precision);
+        assert_eq!(Solver::BranchAndBound, config.solver);
+        assert_eq!(path_finder::RESTARTS, config.restarts);
+        assert_eq!(0, config.seed);
+        assert_eq!(path_finder::BEAM_WIDTH, config.width);
+        assert_eq!(None, config.heuristic);
+        assert_eq!(None, config.queue_cap);
+        assert_eq!(None, config.timeout);
+        assert!(!config.stats);
+        assert!(!config.progress);
+        assert!(!config.all);
+        assert_eq!(None, config.k_best);
+    }
+
+    #[test]
+    fn make_config_k_best_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--k", "3"]);
+        assert_eq!(Some(3), config.k_best);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--k",
+            "0",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_all_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--all"]);
+        assert!(config.all);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--all",
+            "--heuristic",
+            "nn",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_batch_test() {
+        let config = Config::from_iter([clap::crate_name!(), "a.json", "b.json", "-e"]);
+        assert_eq!(
+            Command::Solve(Input::Params {
+                files: vec![PathBuf::from("a.json"), PathBuf::from("b.json")],
+                optimize: Optimize::Energy,
+            }),
+            config.command
+        );
+    }
+
+    #[test]
+    fn make_config_timeout_test() {
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--timeout", "1.5"]);
+        assert_eq!(Some(Duration::from_millis(1500)), config.timeout);
+    }
+
+    #[test]
+    fn make_config_queue_cap_test() {
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--queue-cap",
+            "1000",
+        ]);
+        assert_eq!(Some(1000), config.queue_cap);
+        assert!(!config.single_precision);
+        assert_eq!(SearchOrder::BestFirst, config.search_order);
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--single-precision",
+            "--search",
+            "dfs",
+        ]);
+        assert!(config.single_precision);
+        assert_eq!(SearchOrder::DepthFirst, config.search_order);
+    }
+
+    #[test]
+    fn make_config_solver_test() {
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--solver",
+            "held-karp",
+        ]);
+        assert_eq!(Solver::HeldKarp, config.solver);
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--solver",
+            "rand-restart",
+            "--restarts",
+            "25",
+            "--seed",
+            "7",
+        ]);
+        assert_eq!(Solver::RandomRestart, config.solver);
+        assert_eq!(25, config.restarts);
+        assert_eq!(7, config.seed);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--solver",
+            "rand-restart",
+            "--restarts",
+            "0",
+        ]));
+        assert!(matches.is_err());
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--solver",
+            "beam",
+            "--width",
+            "8",
+        ]);
+        assert_eq!(Solver::Beam, config.solver);
+        assert_eq!(8, config.width);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--solver",
+            "beam",
+            "--width",
+            "0",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_heuristic_test() {
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--heuristic",
+            "nn",
+        ]);
+        assert_eq!(Some(Heuristic::NearestNeighbor), config.heuristic);
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--heuristic",
+            "nn+2opt",
+        ]);
+        assert_eq!(Some(Heuristic::NearestNeighborTwoOpt), config.heuristic);
+    }
+
+    #[test]
+    fn make_config_lex_test() {
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "--lex", "energy,time"]);
+        assert_eq!(
+            Command::Solve(Input::Params {
+                files: vec![PathBuf::from("params.json")],
+                optimize: Optimize::Energy,
+            }),
+            config.command
+        );
+        assert_eq!(vec![Optimize::Time], config.tie_breaks);
+
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-t"]);
+        assert!(config.tie_breaks.is_empty());
+
+        for args in [
+            &["--lex", "energy", "-t"][..],
+            &["--lex", "energy", "--heuristic", "nn"],
+            &["--lex", "energy", "--drones", "2"],
+        ]
+        .iter()
+        {
+            let matches =
+                Config::make_app().get_matches_from_safe(Config::with_default_subcommand(
+                    [clap::crate_name!(), "params.json"]
+                        .iter()
+                        .chain(args.iter()),
+                ));
+            assert!(matches.is_err());
+        }
+    }
+
+    #[test]
+    fn make_config_format_test() {
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--format", "json"]);
+        assert_eq!(OutputFormat::Json, config.format);
+
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--format", "text"]);
+        assert_eq!(OutputFormat::Text, config.format);
+
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--format", "md"]);
+        assert_eq!(OutputFormat::Markdown, config.format);
+
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--format", "dot"]);
+        assert_eq!(OutputFormat::Dot, config.format);
+
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--format", "svg"]);
+        assert_eq!(OutputFormat::Svg, config.format);
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--compact",
+        ]);
+        assert!(config.compact);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--format",
+            "text",
+            "--json",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_precision_test() {
+        let config =
+            Config::from_iter([clap::crate_name!(), "params.json", "-e", "--precision", "6"]);
+        assert_eq!(6, config.precision);
+        assert_eq!(None, config.round);
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--format",
+            "json",
+            "--round",
+            "2",
+        ]);
+        assert_eq!(Some(2), config.round);
+    }
+
+    #[test]
+    fn make_config_cache_test() {
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--cache",
+            "matrices.json",
+        ]);
+        assert_eq!(Some(PathBuf::from("matrices.json")), config.cache);
+    }
+
+    #[test]
+    fn make_config_snap_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--snap", "0.5"]);
+        assert_eq!(Some(0.5), config.snap);
+
+        for invalid in ["0", "-1", "inf"].iter() {
+            let matches =
+                Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+                    clap::crate_name!(),
+                    "params.json",
+                    "-e",
+                    "--snap",
+                    invalid,
+                ]));
+            assert!(matches.is_err());
+        }
+    }
+
+    #[test]
+    fn make_config_interactive_test() {
+        let config = Config::from_iter([clap::crate_name!(), "--interactive", "-t"]);
+        assert!(config.interactive);
+        assert_eq!(
+            Command::Solve(Input::Params {
+                files: Vec::new(),
+                optimize: Optimize::Time,
+            }),
+            config.command
+        );
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--interactive",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_dry_run_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--dry-run"]);
+        assert!(config.dry_run);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "--matrix",
+            "matrix.json",
+            "--dry-run",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_drones_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--drones", "3"]);
+        assert_eq!(Some(3), config.drones);
+
+        let invalid: [&[&str]; 3] = [
+            &["--drones", "0"],
+            &["--drones", "x"],
+            &["--drones", "2", "--all"],
+        ];
+        for args in invalid.iter() {
+            let mut itr = vec![clap::crate_name!(), "params.json", "-e"];
+            itr.extend_from_slice(args);
+            let matches =
+                Config::make_app().get_matches_from_safe(Config::with_default_subcommand(itr));
+            assert!(matches.is_err());
+        }
+    }
+
+    #[test]
+    fn make_config_show_matrices_test() {
+        let config = |args: &[&str]| {
+            Config::from_iter(
+                [clap::crate_name!(), "params.json", "-e"]
+                    .iter()
+                    .chain(args),
+            )
+        };
+        assert_eq!(
+            Some(vec![MatrixKind::Energy, MatrixKind::Distance]),
+            config(&["--show-matrices", "energy,distance"]).show_matrices
+        );
+        assert_eq!(Some(Vec::new()), config(&["--no-matrices"]).show_matrices);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--show-matrices",
+            "speed",
+        ]));
+        assert!(matches.is_err());
+        assert_eq!(4, MatrixKind::defaults(Optimize::Intuitive).len());
+        assert!(!MatrixKind::defaults(Optimize::Time).contains(&MatrixKind::Distance));
+    }
+
+    #[test]
+    fn make_config_depot_test() {
+        let config = Config::from_iter([clap::crate_name!(), "params.json", "-e", "--depot", "2"]);
+        assert_eq!(Some(2), config.depot);
+        assert!(!config.free_return);
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "params.json",
+            "-e",
+            "--depot",
+            "-1",
+        ]));
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn make_config_matrix_test() {
+        let config = Config::from_iter([clap::crate_name!(), "--matrix", "matrix.json"]);
+        assert_eq!(
+            Command::Solve(Input::Matrix(PathBuf::from("matrix.json"))),
+            config.command
+        );
+        assert_eq!(None, config.out_filename);
+        assert_eq!(OutputFormat::Text, config.format);
+    }
+
+    #[test]
+    fn make_config_subcommand_test() {
+        let config = Config::from_iter([clap::crate_name!(), "solve", "params.json", "-t"]);
+        assert_eq!(
+            Command::Solve(Input::Params {
+                files: vec![PathBuf::from("params.json")],
+                optimize: Optimize::Time,
+            }),
+            config.command
+        );
+
+        let config = Config::from_iter([
+            clap::crate_name!(),
+            "generate",
+            "params.json",
+            "--format",
+            "json",
+        ]);
+        assert_eq!(
+            Command::Generate(PathBuf::from("params.json")),
+            config.command
+        );
+        assert_eq!(OutputFormat::Json, config.format);
+
+        let config = Config::from_iter([clap::crate_name!(), "validate", "params.json"]);
+        assert_eq!(
+            Command::Validate(PathBuf::from("params.json")),
+            config.command
+        );
+        assert_eq!(3, config.precision);
+
+        // The optimized parameter is required only to solve
+        let matches = Config::make_app().get_matches_from_safe([
+            clap::crate_name!(),
+            "generate",
+            "params.json",
+            "-e",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn completions_test() {
+        let mut out = Vec::new();
+        Config::write_completions(Shell::Bash, &mut out);
+        let script = String::from_utf8(out).unwrap();
+        assert!(!script.is_empty());
+        assert!(script.contains("--energy"));
+
+        let matches = Config::make_app().get_matches_from_safe(Config::with_default_subcommand([
+            clap::crate_name!(),
+            "--completions",
+            "zsh",
+        ]));
+        assert_eq!(Some("zsh"), matches.unwrap().value_of("completions"));
     }
 }
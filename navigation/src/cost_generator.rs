@@ -1,20 +1,52 @@
 extern crate nalgebra;
 extern crate serde;
+#[cfg(feature = "json")]
 extern crate serde_json;
 extern crate thiserror;
 
+use crate::config::Optimize;
 use nalgebra::DMatrix;
-use serde::Deserialize;
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(feature = "json")]
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+#[cfg(feature = "json")]
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "json")]
+use std::str::FromStr;
 use thiserror::Error;
 
-/// Drone parameters and coordinates to be visited
-#[derive(Debug, Deserialize)]
+/// Maximum difference of the coordinates on each axis
+/// at which two points are considered the same.
+pub const DUPLICATE_EPSILON: f64 = 1e-6;
+
+/// Drone parameters and coordinates to be visited.
+/// Only the battery voltage, speeds, powers and coordinates are required,
+/// the rest of the fields have defaults. Unknown fields are ignored.
+/// [`Default`] leaves the required fields zero, e.g. to fill in the rest of
+/// a struct literal with `..Params::default()`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Params {
     pub battery_voltage: f64,
 
+    /// Usable battery capacity in **[Ah]**. If the route doesn't fit
+    /// into it, the route is split into legs returning to the start point
+    #[serde(default)]
+    pub battery_capacity: Option<f64>,
+
+    /// Ambient temperature in **[°C]**. If specified, the usable battery
+    /// capacity is derated in the cold, see [`Params::temperature_factor`]
+    #[serde(default)]
+    pub temperature_c: Option<f64>,
+
+    /// Fraction of the battery capacity kept in reserve at landing,
+    /// e.g. 0.2 to land with 20% of the battery. No reserve by default
+    #[serde(default)]
+    pub reserve_fraction: f64,
+
     /// Drone horizontal speed in **[m/s]**
     pub speed_horizontal: f64,
 
@@ -27,157 +59,2652 @@ pub struct Params {
     /// Drone horizontal power consumption in **[W]**
     pub power_horizontal: f64,
 
-    /// Drone up power consumption in **[W]**
-    pub power_up: f64,
+    /// Drone up power consumption in **[W]**
+    pub power_up: f64,
+
+    /// Drone down power consumption in **[W]**
+    pub power_down: f64,
+
+    /// Drone hover power consumption in **[W]**
+    pub power_hover: f64,
+
+    /// Payload mass in **[kg]**
+    #[serde(default)]
+    pub payload_kg: f64,
+
+    /// Additional hover power consumption per payload mass in **[W/kg]**
+    #[serde(default)]
+    pub power_per_kg: f64,
+
+    /// Required drone hovering time in **[s]**, may be fractional
+    #[serde(default)]
+    pub hover_time: f64,
+
+    /// Required drone hovering time at each coordinate in **[s]**,
+    /// overrides `hover_time` if specified
+    #[serde(default)]
+    pub hover_times: Option<Vec<f64>>,
+
+    /// Coordinates that need to visit
+    pub coords: Vec<Point>,
+
+    /// Human readable names of the coordinates, e.g. "Tower A"
+    #[serde(default)]
+    pub labels: Option<Vec<String>>,
+
+    /// Horizontal distance model of the coordinates
+    #[serde(default)]
+    pub distance_model: DistanceModel,
+
+    /// Horizontal distance metric for the planar coordinates
+    #[serde(default)]
+    pub metric: Metric,
+
+    /// Include the altitude difference into the intuitive flight distance
+    #[serde(default)]
+    pub distance_3d: bool,
+
+    /// Defines how horizontal and vertical motion are combined
+    #[serde(default)]
+    pub motion_model: MotionModel,
+
+    /// Wind velocity in the ground frame
+    #[serde(default)]
+    pub wind: Option<Wind>,
+
+    /// Minimum flight altitude in **[m]** between the coordinates.
+    /// Transit is performed at this altitude if any of the coordinates is below it
+    #[serde(default)]
+    pub min_altitude: Option<f64>,
+
+    /// Horizontal distance in **[m]** covered per meter of descent while gliding.
+    /// If specified, the descending moves glide: the first
+    /// `min(glide_ratio * descent, horizontal distance)` meters are flown
+    /// at the ground speed with `power_down`, descending `1 / glide_ratio` m
+    /// per meter. The rest of the horizontal distance and of the descent is flown
+    /// as without gliding, see [`MotionModel`]. No gliding by default
+    #[serde(default)]
+    pub glide_ratio: Option<f64>,
+
+    /// Restricted areas that the straight path between two points must not cross
+    #[serde(default)]
+    pub no_fly_zones: Vec<Rect>,
+
+    /// Circular restricted areas (e.g. around radio towers) that the
+    /// straight path between two points must not cross
+    #[serde(default)]
+    pub keep_out: Vec<Circle>,
+
+    /// Measured costs of specific edges (e.g. one-way corridors) that replace
+    /// the generated ones, including the edges blocked by the restricted areas
+    #[serde(default)]
+    pub edge_overrides: Vec<EdgeOverride>,
+
+    /// Zero-based index of the depot, the coordinate where the route starts
+    /// and where each battery leg and each drone returns. The first coordinate
+    /// by default
+    #[serde(default)]
+    pub depot: usize,
+
+    /// Visiting order constraints, each pair `[a, b]` of zero-based
+    /// coordinate indices means that `a` must be visited before `b`.
+    /// The depot is the start, so it can't follow any other
+    #[serde(default)]
+    pub precedences: Vec<(usize, usize)>,
+
+    /// Zero-based indices of the coordinates that must be visited,
+    /// the rest may be skipped if it makes the route cheaper.
+    /// The depot is always visited. All the coordinates
+    /// are required if not specified
+    #[serde(default)]
+    pub required: Option<Vec<usize>>,
+
+    /// Grid step in the units of the coordinates. If specified, the coordinates
+    /// are rounded to the grid before planning and the points in the same cell
+    /// are merged into one by [`crate::plan`] and the command line tool,
+    /// see [`Params::snap_to_grid`]
+    #[serde(default)]
+    pub snap_grid: Option<f64>,
+
+    /// Number of the source points merged into each coordinate by snapping,
+    /// empty if the coordinates weren't snapped
+    #[serde(skip)]
+    pub(crate) merged: Vec<usize>,
+}
+
+/// [`Params`] with all the fields optional, e.g. a mission file overriding
+/// a few fields of the shared drone profile, see [`Params::merge`].
+/// The fields that are optional in [`Params`] can't be reset by the overrides.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PartialParams {
+    pub battery_voltage: Option<f64>,
+    pub battery_capacity: Option<f64>,
+    pub temperature_c: Option<f64>,
+    pub reserve_fraction: Option<f64>,
+    pub speed_horizontal: Option<f64>,
+    pub speed_up: Option<f64>,
+    pub speed_down: Option<f64>,
+    pub power_horizontal: Option<f64>,
+    pub power_up: Option<f64>,
+    pub power_down: Option<f64>,
+    pub power_hover: Option<f64>,
+    pub payload_kg: Option<f64>,
+    pub power_per_kg: Option<f64>,
+    pub hover_time: Option<f64>,
+    pub hover_times: Option<Vec<f64>>,
+    pub coords: Option<Vec<Point>>,
+    pub labels: Option<Vec<String>>,
+    pub distance_model: Option<DistanceModel>,
+    pub metric: Option<Metric>,
+    pub distance_3d: Option<bool>,
+    pub motion_model: Option<MotionModel>,
+    pub wind: Option<Wind>,
+    pub min_altitude: Option<f64>,
+    pub glide_ratio: Option<f64>,
+    pub no_fly_zones: Option<Vec<Rect>>,
+    pub keep_out: Option<Vec<Circle>>,
+    pub edge_overrides: Option<Vec<EdgeOverride>>,
+    pub depot: Option<usize>,
+    pub precedences: Option<Vec<(usize, usize)>>,
+    pub required: Option<Vec<usize>>,
+    pub snap_grid: Option<f64>,
+}
+
+/// Axis-aligned rectangle in the horizontal (x, y) plane.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x_min: f64,
+    pub y_min: f64,
+    pub x_max: f64,
+    pub y_max: f64,
+}
+
+impl Rect {
+    /// Checks whether the segment from `a` to `b` crosses or touches the rectangle
+    /// (Liang-Barsky clipping of the segment by the rectangle).
+    pub fn intersects_segment(&self, a: &Point, b: &Point) -> bool {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let mut t_min: f64 = 0.0;
+        let mut t_max: f64 = 1.0;
+
+        // Distances to the rectangle sides along each direction
+        let sides = [
+            (-dx, a.x - self.x_min),
+            (dx, self.x_max - a.x),
+            (-dy, a.y - self.y_min),
+            (dy, self.y_max - a.y),
+        ];
+        for &(p, q) in sides.iter() {
+            if p == 0.0 {
+                // Parallel to the side and outside of it
+                if q < 0.0 {
+                    return false;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    t_min = t_min.max(t);
+                } else {
+                    t_max = t_max.min(t);
+                }
+            }
+        }
+
+        t_min <= t_max
+    }
+}
+
+/// Costs of the edge from the coordinate `from` to `to` replacing
+/// the generated ones, the costs that aren't specified are kept.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeOverride {
+    pub from: usize,
+    pub to: usize,
+    /// Energy consumption in **kJ**.
+    #[serde(default)]
+    pub energy: Option<f64>,
+    /// Flying time in seconds.
+    #[serde(default)]
+    pub time: Option<f64>,
+    /// Used battery capacity in **Ah**.
+    #[serde(default)]
+    pub capacity: Option<f64>,
+    /// Distance in **m**.
+    #[serde(default)]
+    pub distance: Option<f64>,
+}
+
+impl EdgeOverride {
+    fn costs(&self) -> [Option<f64>; 4] {
+        [self.energy, self.time, self.capacity, self.distance]
+    }
+}
+
+/// Circle in the horizontal (x, y) plane, the altitude isn't limited.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Circle {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+impl Circle {
+    /// Checks whether the segment from `a` to `b` passes within the radius of the center.
+    pub fn intersects_segment(&self, a: &Point, b: &Point) -> bool {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let len_sq = dx * dx + dy * dy;
+        // Projection of the center onto the segment
+        let t = if len_sq == 0.0 {
+            0.0
+        } else {
+            (((self.x - a.x) * dx + (self.y - a.y) * dy) / len_sq).clamp(0.0, 1.0)
+        };
+        let (px, py) = (a.x + t * dx, a.y + t * dy);
+
+        (self.x - px).hypot(self.y - py) <= self.radius
+    }
+}
+
+/// Wind velocity in **[m/s]**, the vector points to where the air moves
+/// (e.g. the west wind has positive `x`).
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Defines how horizontal and vertical motion between two points
+/// with different altitude are combined.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionModel {
+    /// The drone changes altitude and then flies horizontally (or vice versa).
+    /// Flying time is `t_hor + t_ver`, energy is `t_hor * P_hor + t_ver * P_ver`.
+    #[default]
+    Sequential,
+
+    /// The drone changes altitude while flying horizontally.
+    /// Flying time is `max(t_hor, t_ver)`. While both motions overlap, the power
+    /// is `P_hor + P_ver - P_hover` (the excess over hovering of both motions
+    /// adds up), the rest of the time only the longer motion consumes power.
+    Simultaneous,
+}
+
+/// Defines the horizontal distance metric between two points
+/// with planar Cartesian coordinates.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Straight line flight, `sqrt(dx^2 + dy^2)`.
+    #[default]
+    Euclidean,
+
+    /// Flight along axis-aligned corridors, `|dx| + |dy|`.
+    Manhattan,
+
+    /// Flight where both axes are traveled simultaneously, `max(|dx|, |dy|)`.
+    Chebyshev,
+}
+
+/// Defines how the horizontal distance between two points is calculated.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceModel {
+    /// `x` and `y` are planar Cartesian coordinates in **m**.
+    #[default]
+    Euclidean,
+
+    /// `x` is a longitude and `y` is a latitude in degrees,
+    /// the distance is measured along a great circle in **m**.
+    Haversine,
+}
+
+/// Coordinate of the point. Deserialized either from the `{"x": .., "y": .., "z": ..}`
+/// object or from the more compact `[x, y, z]` array, serialized as the object.
+/// The `z` axis increases upward; negative values are below the origin
+/// and are allowed, the climb and descent are chosen by the sign of the `z` difference.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "PointRepr")]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PointRepr {
+    Object { x: f64, y: f64, z: f64 },
+    Array([f64; 3]),
+}
+
+impl From<PointRepr> for Point {
+    fn from(repr: PointRepr) -> Point {
+        match repr {
+            PointRepr::Object { x, y, z } => Point { x, y, z },
+            PointRepr::Array([x, y, z]) => Point { x, y, z },
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParamsParseError {
+    #[error("Cannot open config file: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("Cannot open config file '{}': {source}", .path.display())]
+    IoWithPath {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// The `excerpt` is the offending line of the source with the error
+    /// position marked, empty if the error has no position.
+    #[cfg(feature = "json")]
+    #[error("Cannot parse config file: {source}{excerpt}")]
+    Deserialize {
+        source: serde_json::Error,
+        excerpt: String,
+    },
+
+    #[cfg(feature = "toml")]
+    #[error("Cannot parse TOML config file: {0}")]
+    DeserializeToml(#[from] toml::de::Error),
+
+    #[error("Invalid config file: {actual} hover times are specified for {expected} coords")]
+    HoverTimesLength { expected: usize, actual: usize },
+
+    #[error("Invalid config file: {actual} labels are specified for {expected} coords")]
+    LabelsLength { expected: usize, actual: usize },
+
+    #[error("Invalid config file: {0}")]
+    InvalidValue(String),
+
+    #[error("Cannot open coords file '{}': {source}", .path.display())]
+    CoordsIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid coords file, line {line}: {message}")]
+    CoordsLine { line: usize, message: String },
+}
+
+#[allow(dead_code)]
+impl Params {
+    /// Temperature in **[°C]** below which the battery capacity is derated.
+    pub const DERATING_START_C: f64 = 20.0;
+
+    /// Capacity fraction lost per degree below [`Params::DERATING_START_C`].
+    pub const DERATING_PER_C: f64 = 0.01;
+
+    /// Minimum fraction of the capacity that is usable at any temperature.
+    pub const DERATING_MIN: f64 = 0.5;
+
+    /// Reads the parameters from a TOML file if it has the `.toml` extension
+    /// (requires the `toml` feature), from a JSON file otherwise.
+    /// Files with the `.gz` extension are decompressed first (requires
+    /// the `gzip` feature), the format is defined by the inner extension,
+    /// e.g. `params.json.gz`.
+    #[cfg(feature = "json")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Params, ParamsParseError> {
+        read_params_file::<Params>(path.as_ref())?.validate()
+    }
+
+    /// Factor by which the payload scales all drone power consumptions:
+    /// `1 + payload_kg * power_per_kg / power_hover`, i.e. the relative increase
+    /// of hover power caused by the payload is applied to every motion.
+    pub fn payload_factor(&self) -> f64 {
+        let extra_power = self.payload_kg * self.power_per_kg;
+        if extra_power == 0.0 {
+            1.0
+        } else {
+            1.0 + extra_power / self.power_hover
+        }
+    }
+
+    /// Fraction of the battery capacity that is usable at `temperature_c`.
+    /// The capacity is full at [`Params::DERATING_START_C`] and above, below it
+    /// drops linearly by [`Params::DERATING_PER_C`] per degree and is
+    /// limited by [`Params::DERATING_MIN`], e.g. 0.8 at 0 °C and 0.6 at -20 °C.
+    /// No derating if the temperature isn't specified.
+    pub fn temperature_factor(&self) -> f64 {
+        match self.temperature_c {
+            Some(t) if t < Self::DERATING_START_C => {
+                (1.0 - (Self::DERATING_START_C - t) * Self::DERATING_PER_C).max(Self::DERATING_MIN)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Battery capacity derated by the temperature, see [`Params::temperature_factor`],
+    /// without the reserve in **[Ah]**.
+    pub fn usable_battery_capacity(&self) -> Option<f64> {
+        self.battery_capacity
+            .map(|capacity| capacity * self.temperature_factor() * (1.0 - self.reserve_fraction))
+    }
+
+    /// Required hovering time at the coordinate with index `i` in **[s]**.
+    pub fn hover_time_at(&self, i: usize) -> f64 {
+        self.hover_times.as_ref().map_or(self.hover_time, |t| t[i])
+    }
+
+    /// Energy consumption of hovering at the coordinate with index `i` in **[kJ]**.
+    /// Hovering is a cost of the visited point, it isn't included into the cost matrices.
+    pub fn hover_energy_at(&self, i: usize) -> f64 {
+        self.power_hover * self.payload_factor() * self.hover_time_at(i) / 1000.0
+    }
+
+    /// Used battery capacity of hovering at the coordinate with index `i` in **[Ah]**.
+    pub fn hover_capacity_at(&self, i: usize) -> f64 {
+        self.hover_energy_at(i) * 1000.0 / (self.battery_voltage * CostMatrices::SEC_PER_H as f64)
+    }
+
+    /// Returns the pairs of coordinate indices *(i, j)*, *i < j*,
+    /// that coincide within [`DUPLICATE_EPSILON`] on each axis.
+    pub fn find_duplicates(&self) -> Vec<(usize, usize)> {
+        let close = |a: f64, b: f64| (a - b).abs() <= DUPLICATE_EPSILON;
+        let coords = &self.coords;
+
+        (0..coords.len())
+            .flat_map(|i| (i + 1..coords.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| {
+                close(coords[i].x, coords[j].x)
+                    && close(coords[i].y, coords[j].y)
+                    && close(coords[i].z, coords[j].z)
+            })
+            .collect()
+    }
+
+    /// Checks whether the coordinates are already snapped to the grid.
+    pub fn is_snapped(&self) -> bool {
+        !self.merged.is_empty()
+    }
+
+    /// Number of the source points merged into the coordinate with index `i`.
+    pub fn merged_at(&self, i: usize) -> usize {
+        self.merged.get(i).copied().unwrap_or(1)
+    }
+
+    /// Generates `n` uniformly random coordinates in the 1000 x 1000 x 300 m
+    /// box with fixed drone parameters. The same `seed` gives the same params.
+    #[cfg(feature = "bench")]
+    pub fn random(n: usize, seed: u64) -> Params {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let coords = (0..n)
+            .map(|_| Point {
+                x: rng.gen_range(0.0, 1000.0),
+                y: rng.gen_range(0.0, 1000.0),
+                z: rng.gen_range(0.0, 300.0),
+            })
+            .collect();
+
+        Params {
+            battery_voltage: 22.8,
+            battery_capacity: None,
+            temperature_c: None,
+            reserve_fraction: 0.0,
+            speed_horizontal: 12.5,
+            speed_up: 3.1,
+            speed_down: 3.0,
+            power_horizontal: 486.2,
+            power_up: 899.04,
+            power_down: 309.17,
+            power_hover: 545.8,
+            payload_kg: 0.0,
+            power_per_kg: 0.0,
+            hover_time: 0.0,
+            hover_times: None,
+            coords,
+            labels: None,
+            distance_model: DistanceModel::default(),
+            metric: Metric::default(),
+            distance_3d: false,
+            motion_model: MotionModel::default(),
+            wind: None,
+            min_altitude: None,
+            glide_ratio: None,
+            no_fly_zones: Vec::new(),
+            keep_out: Vec::new(),
+            edge_overrides: Vec::new(),
+            depot: 0,
+            precedences: Vec::new(),
+            required: None,
+            snap_grid: None,
+            merged: Vec::new(),
+        }
+    }
+
+    /// Copy of the params with the coordinates and the restricted areas translated
+    /// horizontally so that the depot is at the origin. The costs don't depend
+    /// on the translation, but the large coordinates (e.g. UTM) lose precision
+    /// in the distance computations. The altitudes are kept for `min_altitude`,
+    /// the geographic coordinates aren't translated.
+    pub fn recentered(&self) -> Params {
+        let mut params = self.clone();
+        if params.distance_model == DistanceModel::Haversine {
+            return params;
+        }
+        let Point { x: dx, y: dy, .. } = self.coords[self.depot];
+        for c in &mut params.coords {
+            c.x -= dx;
+            c.y -= dy;
+        }
+        for r in &mut params.no_fly_zones {
+            r.x_min -= dx;
+            r.x_max -= dx;
+            r.y_min -= dy;
+            r.y_max -= dy;
+        }
+        for c in &mut params.keep_out {
+            c.x -= dx;
+            c.y -= dy;
+        }
+        params
+    }
+
+    /// Rounds each coordinate to the nearest multiple of `grid` and merges
+    /// the points that snap to the same cell into the first of them.
+    /// The merged point keeps the first label and the longest hover time,
+    /// the depot, precedences and required points refer to the merged points.
+    pub fn snap_to_grid(mut self, grid: f64) -> Result<Params, ParamsParseError> {
+        check_snap_grid(grid)?;
+
+        // Index of the merged point for each source point
+        let mut cells = HashMap::new();
+        let mut index = Vec::with_capacity(self.coords.len());
+        let mut coords: Vec<Point> = Vec::new();
+        let mut merged: Vec<usize> = Vec::new();
+        for c in &self.coords {
+            let cell = [c.x, c.y, c.z].map(|v| (v / grid).round() as i64);
+            let i = *cells.entry(cell).or_insert_with(|| {
+                let [x, y, z] = cell.map(|v| v as f64 * grid);
+                coords.push(Point { x, y, z });
+                merged.push(0);
+                coords.len() - 1
+            });
+            merged[i] += 1;
+            index.push(i);
+        }
+
+        let n = coords.len();
+        if let Some(hover_times) = &self.hover_times {
+            let mut snapped = vec![0.0; n];
+            for (&i, &t) in index.iter().zip(hover_times) {
+                snapped[i] = f64::max(snapped[i], t);
+            }
+            self.hover_times = Some(snapped);
+        }
+        if let Some(labels) = self.labels.take() {
+            let mut snapped: Vec<Option<String>> = vec![None; n];
+            for (&i, label) in index.iter().zip(labels) {
+                snapped[i].get_or_insert(label);
+            }
+            self.labels = Some(snapped.into_iter().flatten().collect());
+        }
+        self.depot = index[self.depot];
+        self.edge_overrides = self
+            .edge_overrides
+            .iter()
+            .map(|&o| EdgeOverride {
+                from: index[o.from],
+                to: index[o.to],
+                ..o
+            })
+            .filter(|o| o.from != o.to)
+            .collect();
+        self.precedences = self
+            .precedences
+            .iter()
+            .map(|&(a, b)| (index[a], index[b]))
+            .filter(|(a, b)| a != b)
+            .collect();
+        if let Some(required) = &self.required {
+            let mut snapped: Vec<usize> = required.iter().map(|&i| index[i]).collect();
+            snapped.sort_unstable();
+            snapped.dedup();
+            self.required = Some(snapped);
+        }
+        self.coords = coords;
+        self.merged = merged;
+        self.snap_grid = Some(grid);
+
+        // Merging may leave too few points or make the precedences cyclic
+        self.validate()
+    }
+
+    /// Makes the coordinate with index `depot` the depot, see [`Params::depot`].
+    pub fn with_depot(mut self, depot: usize) -> Result<Params, ParamsParseError> {
+        self.depot = depot;
+
+        self.validate()
+    }
+
+    /// Replaces the coordinates, e.g. with the ones read by [`coords_from_file`].
+    pub fn with_coords(mut self, coords: Vec<Point>) -> Result<Params, ParamsParseError> {
+        self.coords = coords;
+
+        self.validate()
+    }
+
+    /// Layers the `overrides` over the `base` params: each specified field
+    /// replaces the base one entirely. Overridden coordinates keep the base
+    /// labels and hover times, so they must be overridden together if the
+    /// number of the coordinates changes. The result isn't snapped,
+    /// see [`Params::snap_to_grid`].
+    pub fn merge(base: Params, overrides: PartialParams) -> Result<Params, ParamsParseError> {
+        Params {
+            battery_voltage: overrides.battery_voltage.unwrap_or(base.battery_voltage),
+            battery_capacity: overrides.battery_capacity.or(base.battery_capacity),
+            temperature_c: overrides.temperature_c.or(base.temperature_c),
+            reserve_fraction: overrides.reserve_fraction.unwrap_or(base.reserve_fraction),
+            speed_horizontal: overrides.speed_horizontal.unwrap_or(base.speed_horizontal),
+            speed_up: overrides.speed_up.unwrap_or(base.speed_up),
+            speed_down: overrides.speed_down.unwrap_or(base.speed_down),
+            power_horizontal: overrides.power_horizontal.unwrap_or(base.power_horizontal),
+            power_up: overrides.power_up.unwrap_or(base.power_up),
+            power_down: overrides.power_down.unwrap_or(base.power_down),
+            power_hover: overrides.power_hover.unwrap_or(base.power_hover),
+            payload_kg: overrides.payload_kg.unwrap_or(base.payload_kg),
+            power_per_kg: overrides.power_per_kg.unwrap_or(base.power_per_kg),
+            hover_time: overrides.hover_time.unwrap_or(base.hover_time),
+            hover_times: overrides.hover_times.or(base.hover_times),
+            coords: overrides.coords.unwrap_or(base.coords),
+            labels: overrides.labels.or(base.labels),
+            distance_model: overrides.distance_model.unwrap_or(base.distance_model),
+            metric: overrides.metric.unwrap_or(base.metric),
+            distance_3d: overrides.distance_3d.unwrap_or(base.distance_3d),
+            motion_model: overrides.motion_model.unwrap_or(base.motion_model),
+            wind: overrides.wind.or(base.wind),
+            min_altitude: overrides.min_altitude.or(base.min_altitude),
+            glide_ratio: overrides.glide_ratio.or(base.glide_ratio),
+            no_fly_zones: overrides.no_fly_zones.unwrap_or(base.no_fly_zones),
+            keep_out: overrides.keep_out.unwrap_or(base.keep_out),
+            edge_overrides: overrides.edge_overrides.unwrap_or(base.edge_overrides),
+            depot: overrides.depot.unwrap_or(base.depot),
+            precedences: overrides.precedences.unwrap_or(base.precedences),
+            required: overrides.required.or(base.required),
+            snap_grid: overrides.snap_grid.or(base.snap_grid),
+            merged: Vec::new(),
+        }
+        .validate()
+    }
+
+    /// Name of the coordinate with index `i`, if labels are specified.
+    pub fn label_at(&self, i: usize) -> Option<&str> {
+        self.labels.as_ref().map(|l| l[i].as_str())
+    }
+
+    /// Checks whether the straight path between the points `i` and `j`
+    /// crosses any of the no-fly or keep-out zones.
+    pub fn crosses_no_fly_zone(&self, i: usize, j: usize) -> bool {
+        let (a, b) = (&self.coords[i], &self.coords[j]);
+
+        self.no_fly_zones.iter().any(|r| r.intersects_segment(a, b))
+            || self.keep_out.iter().any(|c| c.intersects_segment(a, b))
+    }
+
+    /// Checks whether the coordinate `i` must be visited.
+    pub fn is_required(&self, i: usize) -> bool {
+        i == self.depot || self.required.iter().all(|r| r.contains(&i))
+    }
+
+    /// Checks whether any no-fly or keep-out zone is specified.
+    pub fn has_no_fly_zones(&self) -> bool {
+        !(self.no_fly_zones.is_empty() && self.keep_out.is_empty())
+    }
+
+    /// Checks that the override indices are in range, each override joins
+    /// two distinct points and its costs are finite and non-negative.
+    fn validate_edge_overrides(&self) -> Result<(), ParamsParseError> {
+        let n = self.coords.len();
+        for o in &self.edge_overrides {
+            if o.from >= n || o.to >= n {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "edge override [{}, {}] is out of range of {} coords",
+                    o.from, o.to, n
+                )));
+            }
+            if o.from == o.to {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "edge override [{}, {}] is a loop",
+                    o.from, o.to
+                )));
+            }
+            if o.costs()
+                .iter()
+                .flatten()
+                .any(|c| !(c.is_finite() && *c >= 0.0))
+            {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "edge override [{}, {}] must have non-negative finite costs",
+                    o.from, o.to
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the precedence indices are in range
+    /// and the constraints have no cycles (Kahn's algorithm).
+    fn validate_precedences(&self) -> Result<(), ParamsParseError> {
+        let n = self.coords.len();
+        for &(a, b) in &self.precedences {
+            if a >= n || b >= n {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "precedence [{}, {}] is out of range of {} coords",
+                    a, b, n
+                )));
+            }
+            if b == self.depot {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "precedence [{}, {}]: the depot can't follow other points",
+                    a, b
+                )));
+            }
+        }
+
+        let mut in_degree = vec![0; n];
+        for &(_, b) in &self.precedences {
+            in_degree[b] += 1;
+        }
+        let mut ready: Vec<usize> = (0..n).filter(|&v| in_degree[v] == 0).collect();
+        let mut sorted = 0;
+        while let Some(v) = ready.pop() {
+            sorted += 1;
+            for &(_, b) in self.precedences.iter().filter(|&&(a, _)| a == v) {
+                in_degree[b] -= 1;
+                if in_degree[b] == 0 {
+                    ready.push(b);
+                }
+            }
+        }
+        if sorted < n {
+            return Err(ParamsParseError::InvalidValue(String::from(
+                "precedences contain a cycle",
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn validate(self) -> Result<Params, ParamsParseError> {
+        if let Some(grid) = self.snap_grid {
+            check_snap_grid(grid)?;
+        }
+        if self.coords.len() < 2 {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "at least 2 coords are required, got {}",
+                self.coords.len()
+            )));
+        }
+        if self.depot >= self.coords.len() {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "depot {} is out of range of {} coords",
+                self.depot,
+                self.coords.len()
+            )));
+        }
+        let non_finite = self
+            .coords
+            .iter()
+            .position(|c| !(c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+        if let Some(i) = non_finite {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "coord {} is not finite",
+                i + 1
+            )));
+        }
+        if !(0.0..1.0).contains(&self.reserve_fraction) {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "reserve fraction {} is out of range [0, 1)",
+                self.reserve_fraction
+            )));
+        }
+        if let Some(ratio) = self.glide_ratio {
+            if !(ratio.is_finite() && ratio > 0.0) {
+                return Err(ParamsParseError::InvalidValue(String::from(
+                    "glide ratio must be a positive number",
+                )));
+            }
+        }
+        if let Some(t) = self.temperature_c {
+            if !t.is_finite() {
+                return Err(ParamsParseError::InvalidValue(String::from(
+                    "temperature is not finite",
+                )));
+            }
+        }
+        if !(self.hover_time.is_finite() && self.hover_time >= 0.0) {
+            return Err(ParamsParseError::InvalidValue(String::from(
+                "hover time must be a non-negative number",
+            )));
+        }
+        if let Some(hover_times) = &self.hover_times {
+            if hover_times.len() != self.coords.len() {
+                return Err(ParamsParseError::HoverTimesLength {
+                    expected: self.coords.len(),
+                    actual: hover_times.len(),
+                });
+            }
+            if let Some(i) = hover_times
+                .iter()
+                .position(|t| !(t.is_finite() && *t >= 0.0))
+            {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "hover time of coord {} must be a non-negative number",
+                    i + 1
+                )));
+            }
+        }
+        let invalid_zone = self
+            .no_fly_zones
+            .iter()
+            .position(|r| !(r.x_min <= r.x_max && r.y_min <= r.y_max));
+        if let Some(i) = invalid_zone {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "no-fly zone {} has min bound greater than max",
+                i + 1
+            )));
+        }
+        let invalid_circle = self
+            .keep_out
+            .iter()
+            .position(|c| !(c.radius.is_finite() && c.radius >= 0.0));
+        if let Some(i) = invalid_circle {
+            return Err(ParamsParseError::InvalidValue(format!(
+                "keep-out zone {} has invalid radius",
+                i + 1
+            )));
+        }
+        self.validate_precedences()?;
+        self.validate_edge_overrides()?;
+        if let Some(required) = &self.required {
+            if let Some(&i) = required.iter().find(|&&i| i >= self.coords.len()) {
+                return Err(ParamsParseError::InvalidValue(format!(
+                    "required coord {} is out of range of {} coords",
+                    i,
+                    self.coords.len()
+                )));
+            }
+        }
+        if let Some(labels) = &self.labels {
+            if labels.len() != self.coords.len() {
+                return Err(ParamsParseError::LabelsLength {
+                    expected: self.coords.len(),
+                    actual: labels.len(),
+                });
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MatrixParseError {
+    #[error("Cannot open matrix file: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[cfg(feature = "json")]
+    #[error("Cannot parse matrix file: {0}")]
+    Deserialize(#[from] serde_json::Error),
+
+    #[error("Cost matrix must be square: row {row} has {len} elements, expected {n}")]
+    NotSquare { row: usize, len: usize, n: usize },
+}
+
+/// Reads a precomputed *N x N* cost matrix stored as JSON array of rows.
+/// `null` and `"inf"` entries are treated as infinity (edge can't be used),
+/// see [`MatrixCell`].
+#[cfg(feature = "json")]
+pub fn matrix_from_file<P: AsRef<Path>>(path: P) -> Result<DMatrix<f64>, MatrixParseError> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    matrix_from_rows(serde_json::from_reader(reader)?)
+}
+
+#[cfg(feature = "json")]
+pub fn matrix_from_str(s: &str) -> Result<DMatrix<f64>, MatrixParseError> {
+    matrix_from_rows(serde_json::from_str(s)?)
+}
+
+#[cfg(feature = "json")]
+fn matrix_from_rows(rows: Vec<Vec<MatrixCell>>) -> Result<DMatrix<f64>, MatrixParseError> {
+    let n = rows.len();
+    if let Some((row, len)) = rows
+        .iter()
+        .map(Vec::len)
+        .enumerate()
+        .find(|&(_, len)| len != n)
+    {
+        return Err(MatrixParseError::NotSquare { row, len, n });
+    }
+
+    Ok(DMatrix::from_fn(n, n, |i, j| rows[i][j].0))
+}
+
+/// Reads the coordinates from a WKT file if it has the `.wkt` extension,
+/// see [`parse_wkt_coords`], from a CSV file otherwise, see [`parse_csv_coords`].
+pub fn coords_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Point>, ParamsParseError> {
+    let path = path.as_ref();
+    let s = std::fs::read_to_string(path).map_err(|source| ParamsParseError::CoordsIo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    if has_extension(path, "wkt") {
+        parse_wkt_coords(&s)
+    } else {
+        parse_csv_coords(&s)
+    }
+}
+
+/// Parses the coordinates from `x,y,z` rows. The first row is skipped
+/// if it isn't numeric, e.g. the `x,y,z` header. Blank lines are ignored.
+pub fn parse_csv_coords(s: &str) -> Result<Vec<Point>, ParamsParseError> {
+    let mut coords = Vec::new();
+    let mut first = true;
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let is_header = fields.iter().all(|f| f.parse::<f64>().is_err());
+        if std::mem::replace(&mut first, false) && is_header {
+            continue;
+        }
+        coords.push(parse_point(&fields, i + 1)?);
+    }
+
+    Ok(coords)
+}
+
+/// Parses the coordinates from WKT `POINT Z (x y z)` geometries, one per line,
+/// or `MULTIPOINT Z ((x y z), ...)` ones. The `Z` tag may be omitted,
+/// but each point must have all 3 coordinates. Blank lines are ignored.
+pub fn parse_wkt_coords(s: &str) -> Result<Vec<Point>, ParamsParseError> {
+    let mut coords = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| ParamsParseError::CoordsLine {
+            line: i + 1,
+            message,
+        };
+        let upper = line.to_ascii_uppercase();
+        let (multi, body) = if let Some(body) = upper.strip_prefix("MULTIPOINT") {
+            (true, body)
+        } else if let Some(body) = upper.strip_prefix("POINT") {
+            (false, body)
+        } else {
+            return Err(error(String::from("expected POINT or MULTIPOINT")));
+        };
+        let body = body.trim_start();
+        let body = body.strip_prefix('Z').unwrap_or(body).trim();
+        let body = body
+            .strip_prefix('(')
+            .and_then(|b| b.strip_suffix(')'))
+            .ok_or_else(|| error(String::from("coordinates must be in parentheses")))?;
+        let points: Vec<&str> = if multi {
+            body.split(',').collect()
+        } else {
+            vec![body]
+        };
+        for point in points {
+            let point = point.trim();
+            let point = point
+                .strip_prefix('(')
+                .and_then(|p| p.strip_suffix(')'))
+                .unwrap_or(point);
+            let fields: Vec<&str> = point.split_whitespace().collect();
+            coords.push(parse_point(&fields, i + 1)?);
+        }
+    }
+
+    Ok(coords)
+}
+
+fn parse_point(fields: &[&str], line: usize) -> Result<Point, ParamsParseError> {
+    let error = |message: String| ParamsParseError::CoordsLine { line, message };
+    if fields.len() != 3 {
+        return Err(error(format!(
+            "expected 3 values x, y, z, got {}",
+            fields.len()
+        )));
+    }
+    let mut values = [0.0; 3];
+    for (value, field) in values.iter_mut().zip(fields) {
+        *value = field
+            .parse()
+            .map_err(|_| error(format!("'{}' is not a number", field)))?;
+    }
+
+    Ok(Point {
+        x: values[0],
+        y: values[1],
+        z: values[2],
+    })
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+fn check_snap_grid(grid: f64) -> Result<(), ParamsParseError> {
+    if grid > 0.0 && grid.is_finite() {
+        Ok(())
+    } else {
+        Err(ParamsParseError::InvalidValue(format!(
+            "snap grid must be positive, got {}",
+            grid
+        )))
+    }
+}
+
+/// Version of the cost model, must be bumped whenever the generated costs
+/// change for the same params, so the matrices cached before are regenerated.
+pub const COST_MODEL_VERSION: u32 = 1;
+
+/// 64-bit FNV-1a hash of the serialized params and the cost model version,
+/// stable between runs.
+#[cfg(feature = "json")]
+fn params_hash(params: &Params) -> Result<String, serde_json::Error> {
+    let version = format!("{}/{}", env!("CARGO_PKG_VERSION"), COST_MODEL_VERSION);
+    let hash = version
+        .bytes()
+        .chain(serde_json::to_vec(params)?)
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    Ok(format!("{:016x}", hash))
+}
+
+#[cfg(feature = "json")]
+impl FromStr for Params {
+    type Err = ParamsParseError;
+
+    fn from_str(s: &str) -> Result<Params, ParamsParseError> {
+        parse_json::<Params>(s)?.validate()
+    }
+}
+
+#[cfg(feature = "json")]
+impl PartialParams {
+    /// Reads the overrides from a file in any of the [`Params::from_file`] formats.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<PartialParams, ParamsParseError> {
+        read_params_file(path.as_ref())
+    }
+}
+
+#[cfg(feature = "json")]
+impl FromStr for PartialParams {
+    type Err = ParamsParseError;
+
+    fn from_str(s: &str) -> Result<PartialParams, ParamsParseError> {
+        parse_json(s)
+    }
+}
+
+/// Reads the params file, see [`Params::from_file`], without validation.
+#[cfg(feature = "json")]
+fn read_params_file<T: DeserializeOwned>(path: &Path) -> Result<T, ParamsParseError> {
+    let file = File::open(path).map_err(|source| ParamsParseError::IoWithPath {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    #[cfg(feature = "gzip")]
+    {
+        if has_extension(path, "gz") {
+            let inner = Path::new(path.file_stem().unwrap_or_default());
+            return read_params(flate2::read::GzDecoder::new(file), inner);
+        }
+    }
+
+    read_params(file, path)
+}
+
+/// Reads the params in the format defined by the extension of `path`.
+#[cfg(feature = "json")]
+#[cfg_attr(not(feature = "toml"), allow(unused_variables))]
+fn read_params<T: DeserializeOwned, R: Read>(
+    reader: R,
+    path: &Path,
+) -> Result<T, ParamsParseError> {
+    let mut s = String::new();
+    BufReader::new(reader).read_to_string(&mut s)?;
+    #[cfg(feature = "toml")]
+    {
+        if has_extension(path, "toml") {
+            return Ok(toml::from_str(&s)?);
+        }
+    }
+
+    parse_json(&s)
+}
+
+#[cfg(feature = "json")]
+fn parse_json<T: DeserializeOwned>(s: &str) -> Result<T, ParamsParseError> {
+    serde_json::from_str(s).map_err(|source| ParamsParseError::Deserialize {
+        excerpt: source_excerpt(s, source.line(), source.column()),
+        source,
+    })
+}
+
+/// Maximum number of characters of the source line shown in the excerpt.
+#[cfg(feature = "json")]
+const EXCERPT_WIDTH: usize = 60;
+
+/// The `line` of the `source` with the `column` marked below it, both are
+/// one-based. Long lines are cut around the column. Empty if `line` is 0,
+/// i.e. the error has no position.
+#[cfg(feature = "json")]
+fn source_excerpt(source: &str, line: usize, column: usize) -> String {
+    let text: Vec<char> = match line.checked_sub(1).and_then(|i| source.lines().nth(i)) {
+        Some(text) => text.chars().collect(),
+        None => return String::new(),
+    };
+    let column = column.max(1).min(text.len().max(1));
+    let start = column
+        .saturating_sub(EXCERPT_WIDTH / 2)
+        .min(text.len().saturating_sub(EXCERPT_WIDTH));
+    let end = (start + EXCERPT_WIDTH).min(text.len());
+    let text: String = text[start..end].iter().collect();
+    let number = line.to_string();
+
+    format!(
+        "\n{} | {}\n{} | {}^",
+        number,
+        text.trim_end(),
+        " ".repeat(number.len()),
+        " ".repeat(column - 1 - start)
+    )
+}
+
+#[cfg(feature = "json")]
+fn matrix_to_rows(matrix: &DMatrix<f64>) -> Vec<Vec<MatrixCell>> {
+    matrix
+        .row_iter()
+        .map(|row| row.iter().map(|&c| MatrixCell(c)).collect())
+        .collect()
+}
+
+/// Cost matrix entry in JSON. Non-finite values have no JSON number
+/// representation and are written as the `"inf"`, `"-inf"` and `"nan"` strings.
+/// Besides them, `null` is read as infinity.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatrixCell(pub f64);
+
+impl Serialize for MatrixCell {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            c if c.is_finite() => serializer.serialize_f64(c),
+            c if c.is_nan() => serializer.serialize_str("nan"),
+            c if c > 0.0 => serializer.serialize_str("inf"),
+            _ => serializer.serialize_str("-inf"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixCell {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MatrixCellVisitor)
+    }
+}
+
+struct MatrixCellVisitor;
+
+impl<'de> serde::de::Visitor<'de> for MatrixCellVisitor {
+    type Value = MatrixCell;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(r#"a number, null, "inf", "-inf" or "nan""#)
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<MatrixCell, E> {
+        Ok(MatrixCell(v))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<MatrixCell, E> {
+        Ok(MatrixCell(v as f64))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<MatrixCell, E> {
+        Ok(MatrixCell(v as f64))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<MatrixCell, E> {
+        match v {
+            "inf" => Ok(MatrixCell(f64::INFINITY)),
+            "-inf" => Ok(MatrixCell(f64::NEG_INFINITY)),
+            "nan" => Ok(MatrixCell(f64::NAN)),
+            _ => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<MatrixCell, E> {
+        Ok(MatrixCell(f64::INFINITY))
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<MatrixCell, E> {
+        self.visit_unit()
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("Cannot access cost matrices cache: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("Invalid cost matrices cache: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid cost matrices cache: {0}")]
+    Matrix(#[from] MatrixParseError),
+}
+
+/// Cost matrices file, see [`MatrixCell`] for the entries.
+#[cfg(feature = "json")]
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    /// Hash of the params the matrices were generated from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params_hash: Option<String>,
+    energy: Vec<Vec<MatrixCell>>,
+    time: Vec<Vec<MatrixCell>>,
+    capacity: Vec<Vec<MatrixCell>>,
+    distance: Vec<Vec<MatrixCell>>,
+}
+
+/// Cost matrices (size *N x N*) for each pair of vertices from *i* to *j*.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostMatrices {
+    /// Energy consumption in **kJ**.
+    pub energy: DMatrix<f64>,
+
+    /// Flying time in seconds.
+    pub time: DMatrix<f64>,
+
+    /// Used battery capacity in **Ah**.
+    pub capacity: DMatrix<f64>,
+
+    /// Distance between the visited points for
+    /// an intuitive visit (in a circle) in **m**.
+    pub distance: DMatrix<f64>,
+}
+
+/// Empty matrices of zero points.
+impl Default for CostMatrices {
+    fn default() -> CostMatrices {
+        CostMatrices {
+            energy: DMatrix::zeros(0, 0),
+            time: DMatrix::zeros(0, 0),
+            capacity: DMatrix::zeros(0, 0),
+            distance: DMatrix::zeros(0, 0),
+        }
+    }
+}
+
+impl CostMatrices {
+    const SEC_PER_H: i32 = 3600;
+    /// Mean Earth radius in **m**.
+    const EARTH_RADIUS: f64 = 6_371_008.8;
+    /// Minimum horizontal groundspeed in **m/s** against a strong headwind.
+    const MIN_GROUND_SPEED: f64 = 0.1;
+
+    /// Matrix of the optimized parameter.
+    pub fn get(&self, optimize: Optimize) -> &DMatrix<f64> {
+        match optimize {
+            Optimize::Intuitive => &self.distance,
+            Optimize::Time => &self.time,
+            Optimize::Battery => &self.capacity,
+            Optimize::Energy => &self.energy,
+        }
+    }
+
+    /// Saves the matrices to the JSON file.
+    #[cfg(feature = "json")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CacheError> {
+        self.save_with_hash(path, None)
+    }
+
+    /// Loads the matrices saved by [`CostMatrices::save`].
+    #[cfg(feature = "json")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CostMatrices, CacheError> {
+        Self::read_cache(path).map(|(_, matrices)| matrices)
+    }
+
+    /// Loads the matrices from the cache file if they were generated from
+    /// the same `params` by the same [`COST_MODEL_VERSION`], otherwise generates
+    /// them and saves to the file. A cache file that can't be read is overwritten.
+    #[cfg(feature = "json")]
+    pub fn generate_cached<P: AsRef<Path>>(
+        params: &Params,
+        path: P,
+    ) -> Result<CostMatrices, CacheError> {
+        Self::generate_cached_checked(params, path).map(|(matrices, _)| matrices)
+    }
+
+    /// Same as [`CostMatrices::generate_cached`], but also returns the replaced
+    /// NaN costs, see [`CostMatrices::generate_checked`]. The cache keeps them
+    /// as is, so they are reported on the cache hit too.
+    #[cfg(feature = "json")]
+    pub fn generate_cached_checked<P: AsRef<Path>>(
+        params: &Params,
+        path: P,
+    ) -> Result<(CostMatrices, Vec<(usize, usize)>), CacheError> {
+        let hash = params_hash(params)?;
+        let cached = match Self::read_cache(&path) {
+            Ok((Some(cached_hash), cached)) if cached_hash == hash => Some(cached),
+            _ => None,
+        };
+        let mut matrices = match cached {
+            Some(matrices) => matrices,
+            None => {
+                let matrices = Self::calc_matrices(params);
+                matrices.save_with_hash(path, Some(hash))?;
+                matrices
+            }
+        };
+        let cells = matrices.replace_nan();
+
+        Ok((matrices, cells))
+    }
+
+    #[cfg(feature = "json")]
+    fn save_with_hash<P: AsRef<Path>>(
+        &self,
+        path: P,
+        params_hash: Option<String>,
+    ) -> Result<(), CacheError> {
+        let cache = CacheFile {
+            params_hash,
+            energy: matrix_to_rows(&self.energy),
+            time: matrix_to_rows(&self.time),
+            capacity: matrix_to_rows(&self.capacity),
+            distance: matrix_to_rows(&self.distance),
+        };
+        let writer = BufWriter::new(File::create(path)?);
+
+        Ok(serde_json::to_writer(writer, &cache)?)
+    }
+
+    #[cfg(feature = "json")]
+    fn read_cache<P: AsRef<Path>>(path: P) -> Result<(Option<String>, CostMatrices), CacheError> {
+        let reader = BufReader::new(File::open(path)?);
+        let cache: CacheFile = serde_json::from_reader(reader)?;
+        let matrices = CostMatrices {
+            energy: matrix_from_rows(cache.energy)?,
+            time: matrix_from_rows(cache.time)?,
+            capacity: matrix_from_rows(cache.capacity)?,
+            distance: matrix_from_rows(cache.distance)?,
+        };
+
+        Ok((cache.params_hash, matrices))
+    }
+
+    /// Generates the costs of moving between the coordinates. Hovering at the
+    /// visited points is excluded, it's paid once per point regardless of the
+    /// edges, see [`Params::hover_energy_at`] and [`Params::hover_capacity_at`].
+    /// The costs that aren't a number are replaced with infinity,
+    /// see [`CostMatrices::replace_nan`].
+    pub fn generate(params: &Params) -> CostMatrices {
+        Self::generate_checked(params).0
+    }
+
+    /// Same as [`CostMatrices::generate`], but also returns the edges *(from, to)*
+    /// whose costs weren't a number and were replaced with infinity.
+    pub fn generate_checked(params: &Params) -> (CostMatrices, Vec<(usize, usize)>) {
+        let mut matrices = Self::calc_matrices(params);
+        let cells = matrices.replace_nan();
+
+        (matrices, cells)
+    }
+
+    fn calc_matrices(params: &Params) -> CostMatrices {
+        let n = params.coords.len();
+
+        let mut matrices = CostMatrices {
+            energy: DMatrix::repeat(n, n, f64::INFINITY),
+            time: DMatrix::repeat(n, n, f64::INFINITY),
+            capacity: DMatrix::repeat(n, n, f64::INFINITY),
+            distance: DMatrix::repeat(n, n, f64::INFINITY),
+        };
+
+        for i in 0..n {
+            for j in 0..n {
+                if i == j || params.crosses_no_fly_zone(i, j) {
+                    continue;
+                }
+
+                Self::calc_costs(i, j, &mut matrices, params);
+                Self::calc_distance(i, j, &mut matrices, params);
+            }
+        }
+        for o in &params.edge_overrides {
+            let mut cells = [
+                &mut matrices.energy,
+                &mut matrices.time,
+                &mut matrices.capacity,
+                &mut matrices.distance,
+            ];
+            for (matrix, cost) in cells.iter_mut().zip(o.costs().iter()) {
+                if let Some(cost) = cost {
+                    matrix[(o.from, o.to)] = *cost;
+                }
+            }
+        }
+
+        matrices
+    }
+
+    /// Replaces the NaN costs, e.g. of the coinciding points with zero speed,
+    /// with infinity, so the edges can't be used and the search stays well-defined.
+    /// Returns the replaced cells *(from, to)* in any of the matrices.
+    pub fn replace_nan(&mut self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for matrix in [
+            &mut self.energy,
+            &mut self.time,
+            &mut self.capacity,
+            &mut self.distance,
+        ] {
+            let n = matrix.nrows();
+            for (i, j) in (0..n).flat_map(|i| (0..n).map(move |j| (i, j))) {
+                if matrix[(i, j)].is_nan() {
+                    matrix[(i, j)] = f64::INFINITY;
+                    cells.push((i, j));
+                }
+            }
+        }
+        cells.sort_unstable();
+        cells.dedup();
+
+        cells
+    }
+
+    fn calc_costs(i: usize, j: usize, matrices: &mut CostMatrices, params: &Params) {
+        let payload_factor = params.payload_factor();
+        let power_horizontal = params.power_horizontal * payload_factor;
+        let power_hover = params.power_hover * payload_factor;
+
+        let (up, down) = Self::vertical_distances(i, j, params);
+        let horizontal = Self::horizontal_distance(i, j, params);
+        let ground_speed = Self::ground_speed(i, j, params);
+        // Gliding replaces a part of the horizontal flight and of the descent
+        let (glide, down) = match params.glide_ratio {
+            Some(ratio) => {
+                let glide = (ratio * down).min(horizontal);
+                (glide, down - glide / ratio)
+            }
+            None => (0.0, down),
+        };
+        let t_glide = glide / ground_speed;
+        let t_up = up / params.speed_up;
+        let t_down = down / params.speed_down;
+        let t_ver = t_up + t_down;
+        let energy_ver = (t_up * params.power_up + t_down * params.power_down) * payload_factor;
+        // Mean vertical power, equals to the climb or descent power if only one of them
+        let power_ver = if t_ver > 0.0 { energy_ver / t_ver } else { 0.0 };
+        let t_hor = (horizontal - glide) / ground_speed;
+        let (t_move, energy) = match params.motion_model {
+            MotionModel::Sequential => (t_hor + t_ver, energy_ver + t_hor * power_horizontal),
+            MotionModel::Simultaneous => {
+                let t_overlap = t_hor.min(t_ver);
+                let power_overlap = (power_horizontal + power_ver - power_hover).max(0.0);
+                (
+                    t_hor.max(t_ver),
+                    t_overlap * power_overlap
+                        + (t_hor - t_overlap) * power_horizontal
+                        + (t_ver - t_overlap) * power_ver,
+                )
+            }
+        };
+        let t_move = t_move + t_glide;
+        let energy = energy + t_glide * params.power_down * payload_factor;
+        let capacity = energy / (params.battery_voltage * Self::SEC_PER_H as f64);
+
+        matrices.energy[(i, j)] = energy / 1000.0;
+        matrices.time[(i, j)] = t_move;
+        matrices.capacity[(i, j)] = capacity;
+    }
+
+    /// Climb and descent distances from the point `i` to `j`. Without
+    /// the altitude floor the drone moves straight to the destination altitude.
+    /// If the floor is above any of the points, the drone climbs to the floor,
+    /// flies horizontally at it and then descends to the destination;
+    /// points below the floor are still departed from and arrived at their own altitude.
+    fn vertical_distances(i: usize, j: usize, params: &Params) -> (f64, f64) {
+        let (z_from, z_to) = (params.coords[i].z, params.coords[j].z);
+        let cruise = params
+            .min_altitude
+            .unwrap_or(f64::NEG_INFINITY)
+            .max(z_from.min(z_to));
+        let up = (cruise - z_from).max(0.0) + (z_to - cruise).max(0.0);
+        let down = (z_from - cruise).max(0.0) + (cruise - z_to).max(0.0);
+
+        (up, down)
+    }
+
+    /// Horizontal speed relative to the ground, taking into
+    /// account the wind projection onto the travel direction.
+    fn ground_speed(i: usize, j: usize, params: &Params) -> f64 {
+        let wind = match params.wind {
+            Some(wind) => wind,
+            None => return params.speed_horizontal,
+        };
+
+        let mut dx = params.coords[j].x - params.coords[i].x;
+        let dy = params.coords[j].y - params.coords[i].y;
+        if params.distance_model == DistanceModel::Haversine {
+            // Shrink longitude degrees to the latitude
+            let lat = ((params.coords[i].y + params.coords[j].y) / 2.0).to_radians();
+            dx *= lat.cos();
+        }
+        let len = dx.hypot(dy);
+        if len == 0.0 {
+            return params.speed_horizontal;
+        }
+        let tailwind = (wind.x * dx + wind.y * dy) / len;
+
+        (params.speed_horizontal + tailwind).max(Self::MIN_GROUND_SPEED)
+    }
+
+    fn calc_distance(i: usize, j: usize, matrices: &mut CostMatrices, params: &Params) {
+        let horizontal = Self::horizontal_distance(i, j, params);
+        matrices.distance[(i, j)] = if params.distance_3d {
+            let dz = (params.coords[i].z - params.coords[j].z).abs();
+            match (params.distance_model, params.metric) {
+                (DistanceModel::Euclidean, Metric::Manhattan) => horizontal + dz,
+                (DistanceModel::Euclidean, Metric::Chebyshev) => horizontal.max(dz),
+                _ => horizontal.hypot(dz),
+            }
+        } else {
+            horizontal
+        };
+    }
+
+    fn horizontal_distance(i: usize, j: usize, params: &Params) -> f64 {
+        let x_i = params.coords[i].x;
+        let x_j = params.coords[j].x;
+        let y_i = params.coords[i].y;
+        let y_j = params.coords[j].y;
+
+        match params.distance_model {
+            DistanceModel::Euclidean => {
+                let dx = (x_i - x_j).abs();
+                let dy = (y_i - y_j).abs();
+                match params.metric {
+                    Metric::Euclidean => (dx.powi(2) + dy.powi(2)).sqrt(),
+                    Metric::Manhattan => dx + dy,
+                    Metric::Chebyshev => dx.max(dy),
+                }
+            }
+            DistanceModel::Haversine => {
+                let (lat_i, lat_j) = (y_i.to_radians(), y_j.to_radians());
+                let d_lat = lat_j - lat_i;
+                let d_lon = (x_j - x_i).to_radians();
+                let a = (d_lat / 2.0).sin().powi(2)
+                    + lat_i.cos() * lat_j.cos() * (d_lon / 2.0).sin().powi(2);
+
+                2.0 * Self::EARTH_RADIUS * a.sqrt().asin()
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+#[rustfmt::skip]
+mod tests {
+    use super::*;
+    use float_cmp::{ApproxEq, F64Margin};
+
+    #[test]
+    fn parse_params_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 10,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 200, "z": 300},
+			           {"x": 200, "y": 450, "z": 12},
+			           {"x": 400, "y": 460, "z": 350},
+			           {"x": 350, "y": 240, "z": 14},
+                       {"x": 450, "y": 100, "z": 200}
+            ]
+        }
+        "#;
+        let params = Params::from_str(s).unwrap();
+        assert_eq!(DistanceModel::Euclidean, params.distance_model);
+        assert_eq!(22.8, params.battery_voltage);
+        assert_eq!(12.5, params.speed_horizontal);
+        assert_eq!(3.1, params.speed_up);
+        assert_eq!(3.0, params.speed_down);
+        assert_eq!(486.2, params.power_horizontal);
+        assert_eq!(899.04, params.power_up);
+        assert_eq!(309.17, params.power_down);
+        assert_eq!(545.8, params.power_hover);
+        assert_eq!(10.0, params.hover_time);
+        assert_eq!(vec!(
+            Point { x: 0.0, y: 0.0, z: 0.0 },
+            Point { x: 10.0, y: 200.0, z: 300.0 },
+            Point { x: 200.0, y: 450.0, z: 12.0 },
+            Point { x: 400.0, y: 460.0, z: 350.0 },
+            Point { x: 350.0, y: 240.0, z: 14.0 },
+            Point { x: 450.0, y: 100.0, z: 200.0 },
+        ),
+        params.coords
+        );
+    }
+
+    #[test]
+    fn recentered_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.depot = 2;
+        params.keep_out = vec![Circle {
+            x: 300.0,
+            y: 300.0,
+            radius: 10.0,
+        }];
+        let recentered = params.recentered();
+        assert_eq!(Point { x: 0.0, y: 0.0, z: 12.0 }, recentered.coords[2]);
+        assert_eq!(Point { x: -200.0, y: -450.0, z: 0.0 }, recentered.coords[0]);
+        assert_eq!((100.0, -150.0), (recentered.keep_out[0].x, recentered.keep_out[0].y));
+
+        let matrices = CostMatrices::generate(&params);
+        let recentered_matrices = CostMatrices::generate(&recentered);
+        for (a, b) in matrices.energy.iter().zip(recentered_matrices.energy.iter()) {
+            assert!(a == b || (a - b).abs() < 1e-9);
+        }
+
+        params.distance_model = DistanceModel::Haversine;
+        assert_eq!(params.coords, params.recentered().coords);
+    }
+
+    #[test]
+    fn parse_array_coords_test() {
+        let params = |coords: &str| {
+            Params::from_str(&format!(
+                r#"
+                {{
+                    "battery_voltage": 22.8,
+                    "speed_horizontal": 12.5,
+                    "speed_up": 3.1,
+                    "speed_down": 3,
+                    "power_horizontal": 486.2,
+                    "power_up": 899.04,
+                    "power_down": 309.17,
+                    "power_hover": 545.8,
+                    "coords": {}
+                }}
+                "#,
+                coords
+            ))
+        };
+        let objects = params(r#"[{"x": 0, "y": 0, "z": 0}, {"x": 10, "y": 200, "z": 300}]"#);
+        let arrays = params("[[0, 0, 0], [10, 200, 300]]");
+        let mixed = params(r#"[[0, 0, 0], {"x": 10, "y": 200, "z": 300}]"#);
+        assert_eq!(objects.unwrap(), arrays.unwrap());
+        assert_eq!(
+            vec![
+                Point { x: 0.0, y: 0.0, z: 0.0 },
+                Point { x: 10.0, y: 200.0, z: 300.0 },
+            ],
+            mixed.unwrap().coords
+        );
+        match params("[[0, 0], [10, 200, 300]]") {
+            Err(ParamsParseError::Deserialize { .. }) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_error_excerpt_test() {
+        let s = std::fs::read_to_string("params.json").unwrap();
+        // Trailing comma after the last point
+        let last = s.rfind('}').unwrap();
+        let coords_end = s[..last].rfind('}').unwrap() + 1;
+        let invalid = format!("{},{}", &s[..coords_end], &s[coords_end..]);
+        let line = invalid[..coords_end].lines().count() + 1;
+
+        let message = Params::from_str(&invalid).unwrap_err().to_string();
+        assert!(message.contains(&format!("line {}", line)), "{}", message);
+        let excerpt: Vec<&str> = message.lines().skip(1).collect();
+        assert_eq!(2, excerpt.len(), "{}", message);
+        assert!(excerpt[0].starts_with(&format!("{} | ", line)));
+        assert!(excerpt[1].ends_with('^'));
+
+        let long = format!("{{\"coords\": [{}[1, 2, 3],]}}", "[0, 0, 0], ".repeat(50));
+        let message = Params::from_str(&long).unwrap_err().to_string();
+        let excerpt: Vec<&str> = message.lines().skip(1).collect();
+        assert!(excerpt[0].len() <= EXCERPT_WIDTH + "1 | ".len());
+        let caret = excerpt[1].len() - 1;
+        assert_eq!(Some(']'), excerpt[0].chars().nth(caret));
+
+        assert_eq!("", source_excerpt("{}", 0, 0));
+    }
+
+    #[test]
+    fn merge_params_test() {
+        let base = Params::from_file("params.json").unwrap();
+        let overrides = PartialParams::from_str(r#"
+        {
+            "coords": [[0, 0, 0], [100, 0, 10], [0, 100, 20]],
+            "labels": ["Base", "North", "East"]
+        }
+        "#).unwrap();
+        assert_eq!(None, overrides.speed_horizontal);
+
+        let merged = Params::merge(base.clone(), overrides.clone()).unwrap();
+        assert_eq!(3, merged.coords.len());
+        assert_eq!(Point { x: 100.0, y: 0.0, z: 10.0 }, merged.coords[1]);
+        assert_eq!(Some("East"), merged.label_at(2));
+        assert_eq!(base.battery_voltage, merged.battery_voltage);
+        assert_eq!(base.speed_horizontal, merged.speed_horizontal);
+        assert_eq!(base.power_down, merged.power_down);
+        assert_eq!(base.hover_time, merged.hover_time);
+
+        let merged = Params::merge(base.clone(), PartialParams::default()).unwrap();
+        assert_eq!(base, merged);
+
+        // The base depot is out of range of the overridden coords
+        let overrides = PartialParams { coords: overrides.coords, ..Default::default() };
+        let base = base.with_depot(4).unwrap();
+        assert!(matches!(
+            Params::merge(base, overrides),
+            Err(ParamsParseError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn parse_toml_params_test() {
+        let json = Params::from_file("params.json").unwrap();
+        let toml = Params::from_file("params.toml").unwrap();
+        assert_eq!(json, toml);
+
+        let path = std::env::temp_dir().join("navigation_parse_toml_params_test.toml");
+        std::fs::write(&path, "battery_voltage = \"high\"").unwrap();
+        let result = Params::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ParamsParseError::DeserializeToml(_))));
+    }
+
+    #[test]
+    fn csv_coords_test() {
+        let json = Params::from_file("params.json").unwrap();
+        let csv = "x, y, z\n0,0,0\n10,200,300\n200,450,12\n\n400,460,350\n350,240,14\n450,100,200\n";
+        let coords = parse_csv_coords(csv).unwrap();
+        assert_eq!(json.coords, coords);
+        // The header is optional
+        assert_eq!(coords, parse_csv_coords(&csv[8..]).unwrap());
+
+        let path = std::env::temp_dir().join("navigation_csv_coords_test.csv");
+        std::fs::write(&path, csv).unwrap();
+        let mut params = json.clone();
+        params.coords.reverse();
+        let params = params.with_coords(coords_from_file(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json, params.unwrap());
+
+        for (invalid, line) in [("0,0,0\n1,2\n", 2), ("x,y,z\n0,0,0\n1,two,3\n", 3)].iter() {
+            match parse_csv_coords(invalid) {
+                Err(ParamsParseError::CoordsLine { line: l, .. }) => assert_eq!(*line, l),
+                other => panic!("unexpected result for {:?}: {:?}", invalid, other),
+            }
+        }
+        let message = parse_csv_coords("0,0,0\n1,two,3").unwrap_err().to_string();
+        assert_eq!("Invalid coords file, line 2: 'two' is not a number", message);
+        // Too few coords for the params
+        assert!(json.with_coords(parse_csv_coords("1,2,3").unwrap()).is_err());
+    }
+
+    #[test]
+    fn wkt_coords_test() {
+        let json = Params::from_file("params.json").unwrap();
+        let wkt = "POINT Z (0 0 0)\npoint z (10 200 300)\nPOINT (200 450 12)\n\
+                   MULTIPOINT Z ((400 460 350), (350 240 14), 450 100 200)\n";
+        assert_eq!(json.coords, parse_wkt_coords(wkt).unwrap());
+
+        for invalid in ["LINESTRING (0 0 0, 1 1 1)", "POINT Z 0 0 0", "POINT (0 0)"].iter() {
+            match parse_wkt_coords(invalid) {
+                Err(ParamsParseError::CoordsLine { line: 1, .. }) => (),
+                other => panic!("unexpected result for {}: {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn edge_overrides_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let generated = CostMatrices::generate(&params);
+        let tour = |params: &Params| {
+            let plan = crate::plan(params, Optimize::Time).unwrap();
+            let edges: Vec<(usize, usize)> = plan
+                .result
+                .get_path()
+                .iter()
+                .map(|e| (e.from(), e.to()))
+                .collect();
+            edges
+        };
+        assert_eq!(vec![(0, 1), (1, 3), (3, 5), (5, 4), (4, 2), (2, 0)], tour(&params));
+
+        // A fast one-way corridor from point 3 back to the start
+        let mut overridden = params.clone();
+        overridden.edge_overrides = vec![EdgeOverride {
+            from: 3,
+            to: 0,
+            energy: None,
+            time: Some(1.0),
+            capacity: None,
+            distance: None,
+        }];
+        let matrices = CostMatrices::generate(&overridden);
+        assert_eq!(1.0, matrices.time[(3, 0)]);
+        assert_eq!(generated.time[(0, 3)], matrices.time[(0, 3)]);
+        let mut time = matrices.time.clone();
+        time[(3, 0)] = generated.time[(3, 0)];
+        assert_eq!(generated.time, time);
+        assert_eq!(generated.energy, matrices.energy);
+        assert_eq!(generated.distance, matrices.distance);
+        assert_eq!(vec![(0, 2), (2, 4), (4, 5), (5, 1), (1, 3), (3, 0)], tour(&overridden));
+
+        let json = serde_json::to_string(&params).unwrap();
+        let with_overrides = |overrides: &str| {
+            Params::from_str(&json.replace(
+                "\"edge_overrides\":[]",
+                &format!("\"edge_overrides\":{}", overrides),
+            ))
+        };
+        let parsed = with_overrides(r#"[{"from": 3, "to": 0, "time": 1.0}]"#).unwrap();
+        assert_eq!(overridden.edge_overrides, parsed.edge_overrides);
+        for invalid in [
+            r#"[{"from": 3, "to": 6, "time": 1.0}]"#,
+            r#"[{"from": 3, "to": 3, "time": 1.0}]"#,
+            r#"[{"from": 3, "to": 0, "energy": -1.0}]"#,
+        ]
+        .iter()
+        {
+            match with_overrides(invalid) {
+                Err(ParamsParseError::InvalidValue(_)) => (),
+                other => panic!("unexpected result for {}: {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_params_file_test() {
+        let error = Params::from_file("missing_params.json").unwrap_err();
+        assert!(matches!(
+            &error,
+            ParamsParseError::IoWithPath { path, .. } if path == Path::new("missing_params.json")
+        ));
+        let message = error.to_string();
+        assert!(message.starts_with("Cannot open config file 'missing_params.json': "));
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn parse_gzip_params_test() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let json = std::fs::read("params.json").unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+
+        let path = std::env::temp_dir().join("navigation_parse_gzip_params_test.json.gz");
+        std::fs::write(&path, &compressed).unwrap();
+        let result = Params::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Params::from_file("params.json").unwrap(), result.unwrap());
+    }
+
+    #[test]
+    fn save_load_matrices_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let matrices = CostMatrices::generate(&params);
+        let path = std::env::temp_dir().join("navigation_save_load_matrices_test.json");
+
+        matrices.save(&path).unwrap();
+        let loaded = CostMatrices::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(matrices, loaded);
+    }
+
+    #[test]
+    fn generate_cached_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        let path = std::env::temp_dir().join("navigation_generate_cached_test.json");
+        let _ = std::fs::remove_file(&path);
+
+        let generated = CostMatrices::generate_cached(&params, &path).unwrap();
+        assert_eq!(generated, CostMatrices::load(&path).unwrap());
+        assert_eq!(generated, CostMatrices::generate_cached(&params, &path).unwrap());
+
+        // Stale cache must be regenerated
+        params.coords[1].x += 100.0;
+        let regenerated = CostMatrices::generate_cached(&params, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(CostMatrices::generate(&params), regenerated);
+        assert_ne!(generated, regenerated);
+
+        // Corrupted cache is regenerated too
+        std::fs::write(&path, "{\"energy\": [[").unwrap();
+        let regenerated = CostMatrices::generate_cached(&params, &path).unwrap();
+        let loaded = CostMatrices::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(CostMatrices::generate(&params), regenerated);
+        assert_eq!(regenerated, loaded.unwrap());
+    }
+
+    #[test]
+    fn params_hash_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let plain = serde_json::to_vec(&params)
+            .unwrap()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        // The cost model version is a part of the key
+        assert_eq!(params_hash(&params).unwrap(), params_hash(&params).unwrap());
+        assert_ne!(format!("{:016x}", plain), params_hash(&params).unwrap());
+    }
+
+    #[test]
+    fn minimal_params_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 200, "z": 300}
+            ]
+        }
+        "#;
+        let params = Params::from_str(s).unwrap();
+        assert_eq!(0.0, params.hover_time);
+        assert_eq!(None, params.hover_times);
+        assert_eq!(None, params.battery_capacity);
+        assert_eq!(0.0, params.payload_kg);
+        assert_eq!(0.0, params.power_per_kg);
+        assert_eq!(None, params.labels);
+        assert_eq!(None, params.wind);
+        assert_eq!(DistanceModel::Euclidean, params.distance_model);
+        assert_eq!(Metric::Euclidean, params.metric);
+        assert_eq!(MotionModel::Sequential, params.motion_model);
+
+        let extra = s.replace("\"battery_voltage\"", "\"foo\": {\"bar\": 1}, \"battery_voltage\"");
+        let params = Params::from_str(&extra).unwrap();
+        assert_eq!(22.8, params.battery_voltage);
+
+        let missing = s.replace("\"speed_up\": 3.1,", "");
+        assert!(matches!(Params::from_str(&missing), Err(ParamsParseError::Deserialize { .. })));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_params_test() {
+        let s = r#"{ "foo": "bar" }"#;
+        Params::from_str(s).unwrap();
+    }
+
+    #[test]
+    fn get_matrix_test() {
+        let matrices = CostMatrices::generate(&Params::from_file("params.json").unwrap());
+        assert!(std::ptr::eq(&matrices.energy, matrices.get(Optimize::Energy)));
+        assert!(std::ptr::eq(&matrices.time, matrices.get(Optimize::Time)));
+        assert!(std::ptr::eq(&matrices.capacity, matrices.get(Optimize::Battery)));
+        assert!(std::ptr::eq(&matrices.distance, matrices.get(Optimize::Intuitive)));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn generate_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 0,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 200, "z": 300},
+			           {"x": 200, "y": 450, "z": 12},
+			           {"x": 400, "y": 460, "z": 350},
+			           {"x": 350, "y": 240, "z": 14},
+                       {"x": 450, "y": 100, "z": 200}
+            ]
+        }
+        "#;
+
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let time_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 112.794, 43.266, 161.670, 38.467, 101.394,
+            116.020, f64::INFINITY, 121.121, 53.627, 122.721, 69.431,
+            43.395, 118.024, f64::INFINITY, 125.052, 21.291, 95.054,
+            165.434, 54.164, 128.687, f64::INFINITY, 130.049, 79.076,
+            38.617, 119.646, 21.312, 126.436, f64::INFINITY, 73.764,
+            103.545, 68.356, 97.076, 77.464, 75.764, f64::INFINITY
+        ]).transpose();
+
+        let capacity_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 1.155, 0.276, 1.526, 0.251, 0.925,
+            0.472, f64::INFINITY, 0.510, 0.399, 0.521, 0.339,
+            0.248, 1.166, f64::INFINITY, 1.289, 0.129, 0.868,
+            0.728, 0.285, 0.519, f64::INFINITY, 0.529, 0.361,
+            0.219, 1.173, 0.125, 1.294, f64::INFINITY, 0.739,
+            0.470, 0.567, 0.440, 0.702, 0.315, f64::INFINITY
+        ]).transpose();
+
+        let params = Params::from_str(s).unwrap();
+        let matrices = CostMatrices::generate(&params);
+
+        assert!(approx_eq!(Matrix, Matrix(energy_cost), Matrix(matrices.energy)));
+        assert!(approx_eq!(Matrix, Matrix(time_cost), Matrix(matrices.time)));
+        assert!(approx_eq!(Matrix, Matrix(capacity_cost), Matrix(matrices.capacity)));
+    }
+
+    #[test]
+    fn parse_matrix_test() {
+        let s = r#"
+        [
+            [null, 1, 5],
+            [5, null, 1],
+            [1, 5.5, null]
+        ]
+        "#;
+        let matrix = matrix_from_str(s).unwrap();
+        assert_eq!(DMatrix::from_vec(3, 3, vec![
+            f64::INFINITY, 1.0, 5.0,
+            5.0, f64::INFINITY, 1.0,
+            1.0, 5.5, f64::INFINITY,
+        ]).transpose(), matrix);
+    }
+
+    #[test]
+    fn matrix_round_trip_test() {
+        let matrix = CostMatrices::generate(&Params::from_file("params.json").unwrap()).energy;
+        let json = serde_json::to_string(&matrix_to_rows(&matrix)).unwrap();
+        assert!(json.contains(r#""inf""#));
+        assert_eq!(matrix, matrix_from_str(&json).unwrap());
+
+        let matrix = matrix_from_str(r#"[["inf", "-inf"], ["nan", null]]"#).unwrap();
+        assert_eq!(f64::INFINITY, matrix[(0, 0)]);
+        assert_eq!(f64::NEG_INFINITY, matrix[(0, 1)]);
+        assert!(matrix[(1, 0)].is_nan());
+        assert_eq!(f64::INFINITY, matrix[(1, 1)]);
+
+        assert!(matrix_from_str(r#"[["infinity"]]"#).is_err());
+    }
+
+    #[test]
+    fn parse_non_square_matrix_test() {
+        let s = r#"[[null, 1, 5], [5, null], [1, 5, null]]"#;
+        match matrix_from_str(s) {
+            Err(MatrixParseError::NotSquare { row, len, n }) => {
+                assert_eq!((1, 2, 3), (row, len, n));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn haversine_distance_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 0,
+	        "distance_model": "haversine",
+	        "coords": [{"x": -0.1278, "y": 51.5074, "z": 0},
+			           {"x": 2.3522, "y": 48.8566, "z": 0}
+            ]
+        }
+        "#;
+
+        let params = Params::from_str(s).unwrap();
+        assert_eq!(DistanceModel::Haversine, params.distance_model);
+        let matrices = CostMatrices::generate(&params);
+
+        // London - Paris
+        let expected = 343_560.0;
+        assert!((matrices.distance[(0, 1)] - expected).abs() / expected < 0.005);
+        assert!((matrices.distance[(1, 0)] - expected).abs() / expected < 0.005);
+        let time = matrices.distance[(0, 1)] / params.speed_horizontal;
+        assert!(approx_eq!(f64, time, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+    }
+
+    #[test]
+    fn metric_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 10,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 0,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 30, "y": -40, "z": 0}
+            ]
+        }
+        "#;
+
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(Metric::Euclidean, params.metric);
+        for &(metric, distance) in &[
+            (Metric::Euclidean, 50.0),
+            (Metric::Manhattan, 70.0),
+            (Metric::Chebyshev, 40.0),
+        ] {
+            params.metric = metric;
+            let matrices = CostMatrices::generate(&params);
+            assert!(approx_eq!(f64, distance, matrices.distance[(0, 1)], epsilon = 0.001, ulps = 0));
+            assert!(approx_eq!(f64, distance, matrices.distance[(1, 0)], epsilon = 0.001, ulps = 0));
+            assert!(approx_eq!(f64, distance / 10.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        }
+
+        let params = Params::from_str(&s.replace("\"hover_time\": 0,", "\"hover_time\": 0, \"metric\": \"manhattan\","))
+            .unwrap();
+        assert_eq!(Metric::Manhattan, params.metric);
+    }
+
+    #[test]
+    fn distance_3d_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 10,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 0,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 30, "y": -40, "z": 120}
+            ]
+        }
+        "#;
+
+        let mut params = Params::from_str(s).unwrap();
+        assert!(!params.distance_3d);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 50.0, matrices.distance[(0, 1)], epsilon = 0.001, ulps = 0));
+
+        params.distance_3d = true;
+        let matrices_3d = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 130.0, matrices_3d.distance[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 130.0, matrices_3d.distance[(1, 0)], epsilon = 0.001, ulps = 0));
+        // Flight costs don't depend on the distance model
+        assert_eq!(matrices.energy, matrices_3d.energy);
+    }
+
+    #[test]
+    fn motion_model_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "hover_time": 0,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 100, "y": 0, "z": 40}
+            ]
+        }
+        "#;
+
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(MotionModel::Sequential, params.motion_model);
+
+        // Climb 20 s, fly 10 s; descend 10 s, fly 10 s
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 30.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.time[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 7.0, matrices.energy[(1, 0)], epsilon = 0.001, ulps = 0));
+
+        // Climb and fly 10 s at 700 W, then climb 10 s at 800 W;
+        // descend and fly 10 s at 200 W
+        params.motion_model = MotionModel::Simultaneous;
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 20.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 15.0, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 10.0, matrices.time[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 2.0, matrices.energy[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 0.75 / 3.6, matrices.capacity[(0, 1)], epsilon = 0.001, ulps = 0));
+    }
+
+    #[test]
+    fn min_altitude_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": 10},
+			           {"x": 100, "y": 0, "z": 20}
+            ]
+        }
+        "#;
+
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(None, params.min_altitude);
+
+        // Climb 5 s, fly 10 s
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 15.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 8.0, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+
+        // Climb 20 s to the floor, fly 10 s, descend 7.5 s
+        params.min_altitude = Some(50.0);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 37.5, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 22.25, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        // Climb 15 s to the floor, fly 10 s, descend 10 s
+        assert!(approx_eq!(f64, 35.0, matrices.time[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 19.0, matrices.energy[(1, 0)], epsilon = 0.001, ulps = 0));
+
+        // The floor below both points doesn't change anything
+        params.min_altitude = Some(5.0);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 8.0, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+    }
+
+    #[test]
+    fn negative_z_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": -20},
+			           {"x": 100, "y": 0, "z": 20},
+			           {"x": 200, "y": 0, "z": -20},
+			           {"x": 100, "y": 0, "z": -60}
+            ]
+        }
+        "#;
 
-    /// Drone down power consumption in **[W]**
-    pub power_down: f64,
+        let mut params = Params::from_str(s).unwrap();
+        let matrices = CostMatrices::generate(&params);
 
-    /// Drone hover power consumption in **[W]**
-    pub power_hover: f64,
+        // Climb 20 s across the origin, fly 10 s; descend 10 s, fly 10 s
+        assert!(approx_eq!(f64, 30.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.time[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 7.0, matrices.energy[(1, 0)], epsilon = 0.001, ulps = 0));
+        // Both below the origin: moving further down is a descent, not a climb
+        assert!(approx_eq!(f64, 20.0, matrices.time[(0, 3)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 7.0, matrices.energy[(0, 3)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 30.0, matrices.time[(3, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.energy[(3, 0)], epsilon = 0.001, ulps = 0));
+        // Equal negative altitudes spend nothing on the vertical movement
+        assert!(approx_eq!(f64, 20.0, matrices.time[(0, 2)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 8.0, matrices.energy[(0, 2)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 20.0, matrices.time[(2, 0)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 8.0, matrices.energy[(2, 0)], epsilon = 0.001, ulps = 0));
 
-    /// Required drone hovering time in **[s]**
-    pub hover_time: u32,
+        // Climb 10 s to the floor at the origin, fly 20 s, descend 5 s
+        params.min_altitude = Some(0.0);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 35.0, matrices.time[(0, 2)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 17.5, matrices.energy[(0, 2)], epsilon = 0.001, ulps = 0));
+    }
 
-    /// Coordinates that need to visit
-    pub coords: Vec<Point>,
-}
+    #[test]
+    fn glide_ratio_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": 100},
+			           {"x": 100, "y": 0, "z": 0}
+            ]
+        }
+        "#;
 
-#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
-}
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(None, params.glide_ratio);
 
-#[derive(Debug, Error)]
-pub enum ParamsParseError {
-    #[error("Cannot open config file: {0}")]
-    IO(#[from] std::io::Error),
+        // Descend 25 s, fly 10 s; climb 50 s, fly 10 s
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 35.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 11.5, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        let climb = matrices.energy[(1, 0)];
 
-    #[error("Cannot parse config file: {0}")]
-    Deserialize(#[from] serde_json::Error),
-}
+        // Glide 5 s over 50 m down to the ground, fly the remaining 50 m for 5 s
+        params.glide_ratio = Some(0.5);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 10.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 3.5, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert_eq!(climb, matrices.energy[(1, 0)]);
 
-#[allow(dead_code)]
-impl Params {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Params, ParamsParseError> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+        // Glide over all 100 m for 10 s down to 50 m, then descend 12.5 s
+        params.glide_ratio = Some(2.0);
+        let matrices = CostMatrices::generate(&params);
+        assert!(approx_eq!(f64, 22.5, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 6.75, matrices.energy[(0, 1)], epsilon = 0.001, ulps = 0));
 
-        Ok(serde_json::from_reader(reader)?)
+        params.glide_ratio = Some(0.0);
+        assert!(matches!(
+            params.validate(),
+            Err(ParamsParseError::InvalidValue(_))
+        ));
     }
 
-    pub fn from_str(s: &str) -> Result<Params, ParamsParseError> {
-        Ok(serde_json::from_str(s)?)
-    }
-}
+    #[test]
+    fn replace_nan_test() {
+        // Points 2 and 3 differ only in altitude, with zero airspeed the horizontal
+        // time between them is 0/0; the rest of the edges move with the wind
+        let params = Params::from_file("nan_params.json").unwrap();
+        let mut matrices = CostMatrices::calc_matrices(&params);
+        assert!(matrices.energy[(1, 2)].is_nan());
+        assert!(matrices.time[(2, 1)].is_nan());
 
-/// Cost matrices (size *N x N*) for each pair of vertices from *i* to *j*.
-#[derive(Debug)]
-pub struct CostMatrices {
-    /// Energy consumption in **kJ**.
-    pub energy: DMatrix<f64>,
+        assert_eq!(vec![(1, 2), (2, 1)], matrices.replace_nan());
+        assert_eq!((matrices.clone(), vec![(1, 2), (2, 1)]), CostMatrices::generate_checked(&params));
+        assert_eq!(f64::INFINITY, matrices.energy[(1, 2)]);
+        assert_eq!(f64::INFINITY, matrices.time[(2, 1)]);
+        assert!(matrices.energy[(0, 1)].is_finite());
+        assert!(matrices.replace_nan().is_empty());
 
-    /// Flying time in seconds.
-    pub time: DMatrix<f64>,
+        let result = crate::path_finder::find(&matrices.energy).unwrap();
+        assert!(result.get_cost().is_finite());
+    }
 
-    /// Used battery capacity in **Ah**.
-    pub capacity: DMatrix<f64>,
+    #[test]
+    fn no_fly_zones_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 100, "y": 0, "z": 0},
+			           {"x": 100, "y": 100, "z": 0},
+			           {"x": 0, "y": 100, "z": 0}
+            ],
+	        "no_fly_zones": [{"x_min": 40, "y_min": 40, "x_max": 60, "y_max": 60}]
+        }
+        "#;
 
-    /// Distance between the visited points for
-    /// an intuitive visit (in a circle) in **m**.
-    pub distance: DMatrix<f64>,
-}
+        let params = Params::from_str(s).unwrap();
+        let matrices = CostMatrices::generate(&params);
+        // Diagonals cross the zone, sides don't
+        for &(i, j) in [(0, 2), (2, 0), (1, 3), (3, 1)].iter() {
+            assert!(matrices.energy[(i, j)].is_infinite());
+            assert!(matrices.time[(i, j)].is_infinite());
+            assert!(matrices.capacity[(i, j)].is_infinite());
+            assert!(matrices.distance[(i, j)].is_infinite());
+        }
+        for &(i, j) in [(0, 1), (1, 2), (2, 3), (3, 0)].iter() {
+            assert!(matrices.energy[(i, j)].is_finite());
+            assert!(matrices.energy[(j, i)].is_finite());
+        }
 
-impl CostMatrices {
-    const SEC_PER_H: i32 = 3600;
+        let zone = params.no_fly_zones[0];
+        let p = |x, y| Point { x, y, z: 0.0 };
+        // Segment inside the zone
+        assert!(zone.intersects_segment(&p(45.0, 45.0), &p(55.0, 55.0)));
+        // Touches the corner
+        assert!(zone.intersects_segment(&p(0.0, 20.0), &p(40.0, 60.0)));
+        // Parallel to the side and outside of the zone
+        assert!(!zone.intersects_segment(&p(0.0, 70.0), &p(100.0, 70.0)));
+        // Ends before the zone
+        assert!(!zone.intersects_segment(&p(0.0, 50.0), &p(30.0, 50.0)));
 
-    pub fn generate(params: &Params) -> CostMatrices {
-        // Constants that represents the cost of
-        // hovering at each visited vertices
-        let energy_hover = params.power_hover * params.hover_time as f64;
-        let capacity_hover = energy_hover / (params.battery_voltage * Self::SEC_PER_H as f64);
+        let s = s.replace(r#""x_max": 60"#, r#""x_max": 30"#);
+        match Params::from_str(&s) {
+            Err(ParamsParseError::InvalidValue(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 
-        let n = params.coords.len();
-        let mut matrices = CostMatrices {
-            energy: DMatrix::repeat(n, n, f64::INFINITY),
-            time: DMatrix::repeat(n, n, f64::INFINITY),
-            capacity: DMatrix::repeat(n, n, f64::INFINITY),
-            distance: DMatrix::repeat(n, n, f64::INFINITY),
+    #[test]
+    fn keep_out_test() {
+        let circle = Circle {
+            x: 50.0,
+            y: 10.0,
+            radius: 10.0,
         };
+        let p = |x, y| Point { x, y, z: 0.0 };
+        // Straddles the segment
+        assert!(circle.intersects_segment(&p(0.0, 0.0), &p(100.0, 0.0)));
+        assert!(circle.intersects_segment(&p(100.0, 0.0), &p(0.0, 0.0)));
+        // Passes by
+        assert!(!circle.intersects_segment(&p(0.0, -1.0), &p(100.0, -1.0)));
+        // The closest point of the line is beyond the segment end
+        assert!(!circle.intersects_segment(&p(0.0, 10.0), &p(30.0, 10.0)));
+        assert!(circle.intersects_segment(&p(0.0, 10.0), &p(45.0, 10.0)));
+        // Degenerate segment
+        assert!(circle.intersects_segment(&p(50.0, 15.0), &p(50.0, 15.0)));
+    }
 
-        for i in 0..n {
-            for j in 0..n {
-                if i == j {
-                    continue;
-                }
+    #[test]
+    fn precedences_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 100, "y": 0, "z": 0},
+			           {"x": 100, "y": 100, "z": 0}
+            ],
+	        "precedences": PRECEDENCES
+        }
+        "#;
+        let parse = |precedences| Params::from_str(&s.replace("PRECEDENCES", precedences));
 
-                Self::calc_costs(i, j, &mut matrices, params, energy_hover, capacity_hover);
-                Self::calc_distance(i, j, &mut matrices, params);
+        assert_eq!(vec![(2, 1), (0, 2)], parse("[[2, 1], [0, 2]]").unwrap().precedences);
+        for invalid in ["[[1, 3]]", "[[1, 0]]", "[[1, 2], [2, 1]]", "[[1, 1]]"].iter() {
+            match parse(invalid) {
+                Err(ParamsParseError::InvalidValue(_)) => (),
+                other => panic!("unexpected result for {}: {:?}", invalid, other),
             }
         }
+    }
 
-        matrices
+    #[test]
+    fn depot_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.precedences = vec![(0, 2)];
+        assert_eq!(0, params.depot);
+
+        let params = params.with_depot(1).unwrap();
+        assert!(params.is_required(1));
+        // The depot can't follow other points
+        assert!(matches!(
+            params.clone().with_depot(2),
+            Err(ParamsParseError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            params.with_depot(6),
+            Err(ParamsParseError::InvalidValue(_))
+        ));
     }
 
-    fn calc_costs(
-        i: usize,
-        j: usize,
-        matrices: &mut CostMatrices,
-        params: &Params,
-        energy_hover: f64,
-        capacity_hover: f64,
-    ) {
-        let coords = &params.coords;
-
-        let (t_ver, power_ver) = if coords[i].z < coords[j].z {
-            (
-                (coords[j].z - coords[i].z) / params.speed_up,
-                params.power_up,
-            )
-        } else {
-            (
-                (coords[i].z - coords[j].z) / params.speed_down,
-                if coords[i].z == coords[j].z {
-                    0.0
-                } else {
-                    params.power_down
-                },
-            )
-        };
-        let t_hor = ((coords[i].x - coords[j].x).powi(2) + (coords[i].y - coords[j].y).powi(2))
-            .sqrt()
-            / params.speed_horizontal;
-        let energy = t_ver * power_ver + t_hor * params.power_horizontal;
-        let capacity = energy / (params.battery_voltage * Self::SEC_PER_H as f64);
+    #[test]
+    fn snap_to_grid_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "hover_times": [0, 10, 30, 20],
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 100.2, "y": 50, "z": 10},
+			           {"x": 100.3, "y": 49.9, "z": 10},
+			           {"x": 0, "y": 100.4, "z": 20.6}
+            ],
+	        "labels": ["Base", "A", "B", "C"],
+	        "precedences": [[2, 3], [1, 2]],
+	        "required": [2, 3]
+        }
+        "#;
+        let params = Params::from_str(s).unwrap();
+        assert!(!params.is_snapped());
 
-        let energy_val = (energy + energy_hover) / 1000.0;
-        let time_val = t_hor.max(t_ver) + params.hover_time as f64;
-        let capacity_val = capacity + capacity_hover;
+        let params = params.snap_to_grid(1.0).unwrap();
+        assert!(params.is_snapped());
+        assert_eq!(
+            vec![
+                Point { x: 0.0, y: 0.0, z: 0.0 },
+                Point { x: 100.0, y: 50.0, z: 10.0 },
+                Point { x: 0.0, y: 100.0, z: 21.0 },
+            ],
+            params.coords
+        );
+        assert_eq!((1, 2, 1), (params.merged_at(0), params.merged_at(1), params.merged_at(2)));
+        assert_eq!(Some(vec![0.0, 30.0, 20.0]), params.hover_times);
+        assert_eq!(Some(vec!["Base", "A", "C"]), params.labels.as_ref().map(|l| l.iter().map(String::as_str).collect()));
+        assert_eq!(vec![(1, 2)], params.precedences);
+        assert_eq!(Some(vec![1, 2]), params.required);
+        assert_eq!(Some(1.0), params.snap_grid);
 
-        matrices.energy[(i, j)] = energy_val;
-        matrices.time[(i, j)] = time_val;
-        matrices.capacity[(i, j)] = capacity_val;
+        // All the points are merged into one
+        match Params::from_str(s).unwrap().snap_to_grid(1000.0) {
+            Err(ParamsParseError::InvalidValue(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Params::from_str(s).unwrap().snap_to_grid(0.0) {
+            Err(ParamsParseError::InvalidValue(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 
-    fn calc_distance(i: usize, j: usize, matrices: &mut CostMatrices, params: &Params) {
-        let x_i = params.coords[i].x;
-        let x_j = params.coords[j].x;
-        let y_i = params.coords[i].y;
-        let y_j = params.coords[j].y;
-        matrices.distance[(i, j)] = ((x_i - x_j).powi(2) + (y_i - y_j).powi(2)).sqrt();
+    #[test]
+    fn find_duplicates_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 20, "z": 30},
+			           {"x": 0, "y": 0, "z": 0.0000001},
+			           {"x": 10, "y": 20, "z": 31},
+			           {"x": 10, "y": 20, "z": 30}
+            ]
+        }
+        "#;
+        let params = Params::from_str(s).unwrap();
+        assert_eq!(vec![(0, 2), (1, 4)], params.find_duplicates());
+
+        let params = params.snap_to_grid(1.0).unwrap();
+        assert!(params.find_duplicates().is_empty());
     }
-}
 
-#[cfg(test)]
-#[rustfmt::skip]
-mod tests {
-    use super::*;
-    use float_cmp::{ApproxEq, F64Margin};
+    #[test]
+    #[cfg(feature = "bench")]
+    fn random_params_test() {
+        let params = Params::random(10, 42);
+        assert_eq!(10, params.coords.len());
+        assert_eq!(params, Params::random(10, 42));
+        assert_ne!(params, Params::random(10, 43));
+        assert!(params.clone().validate().is_ok());
+    }
 
     #[test]
-    fn parse_params_test() {
+    fn hover_times_test() {
         let s = r#"
         {
 	        "battery_voltage": 22.8,
@@ -189,6 +2716,7 @@ mod tests {
 	        "power_down": 309.17,
 	        "power_hover": 545.8,
 	        "hover_time": 10,
+	        "hover_times": [0, 0, 0, 600, 0, 0],
 	        "coords": [{"x": 0, "y": 0, "z": 0},
 			           {"x": 10, "y": 200, "z": 300},
 			           {"x": 200, "y": 450, "z": 12},
@@ -199,37 +2727,77 @@ mod tests {
         }
         "#;
         let params = Params::from_str(s).unwrap();
-        assert_eq!(22.8, params.battery_voltage);
-        assert_eq!(12.5, params.speed_horizontal);
-        assert_eq!(3.1, params.speed_up);
-        assert_eq!(3.0, params.speed_down);
-        assert_eq!(486.2, params.power_horizontal);
-        assert_eq!(899.04, params.power_up);
-        assert_eq!(309.17, params.power_down);
-        assert_eq!(545.8, params.power_hover);
-        assert_eq!(10, params.hover_time);
-        assert_eq!(vec!(
-            Point { x: 0.0, y: 0.0, z: 0.0 },
-            Point { x: 10.0, y: 200.0, z: 300.0 },
-            Point { x: 200.0, y: 450.0, z: 12.0 },
-            Point { x: 400.0, y: 460.0, z: 350.0 },
-            Point { x: 350.0, y: 240.0, z: 14.0 },
-            Point { x: 450.0, y: 100.0, z: 200.0 },
-        ),
-        params.coords
-        );
+        assert_eq!(0.0, params.hover_time_at(0));
+        assert_eq!(600.0, params.hover_time_at(3));
+
+        assert_eq!(0.0, params.hover_energy_at(0));
+        assert!(approx_eq!(f64, 545.8 * 600.0 / 1000.0, params.hover_energy_at(3), epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 545.8 * 600.0 / 22.8 / 3600.0, params.hover_capacity_at(3), epsilon = 1e-9, ulps = 0));
+
+        // Hovering is paid per visited point, the edges don't include it
+        let no_hover = Params::from_str(&s.replace("[0, 0, 0, 600, 0, 0]", "[0, 0, 0, 0, 0, 0]")).unwrap();
+        assert_eq!(CostMatrices::generate(&no_hover), CostMatrices::generate(&params));
+
+        // Without hover_times the scalar value is used for all coords
+        let scalar = Params::from_str(&s.replace("\"hover_times\": [0, 0, 0, 600, 0, 0],", "")).unwrap();
+        assert_eq!(None, scalar.hover_times);
+        assert!((0..6).all(|i| scalar.hover_time_at(i) == 10.0));
     }
 
     #[test]
-    #[should_panic]
-    fn invalid_params_test() {
-        let s = r#"{ "foo": "bar" }"#;
-        Params::from_str(s).unwrap();
+    fn invalid_hover_times_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 10,
+	        "hover_times": [0, 600],
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 200, "z": 300},
+			           {"x": 200, "y": 450, "z": 12}
+            ]
+        }
+        "#;
+        match Params::from_str(s) {
+            Err(ParamsParseError::HoverTimesLength { expected, actual }) => {
+                assert_eq!((3, 2), (expected, actual));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let negative = s.replace("[0, 600]", "[0, -1, 600]");
+        match Params::from_str(&negative) {
+            Err(ParamsParseError::InvalidValue(msg)) => assert!(msg.contains("coord 2")),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 
     #[test]
-    #[rustfmt::skip]
-    fn generate_test() {
+    fn fractional_hover_time_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let mut fractional = params.clone();
+        fractional.hover_time = 2.5;
+        let fractional = Params::from_str(&serde_json::to_string(&fractional).unwrap()).unwrap();
+        assert_eq!(2.5, fractional.hover_time_at(1));
+        assert!(approx_eq!(f64, 545.8 * 2.5 / 1000.0, fractional.hover_energy_at(1), epsilon = 1e-9, ulps = 0));
+
+        // The time totals include the fractional dwell at each visited point
+        let path = [0, 1, 3, 5, 4, 2, 0];
+        let time = |params: &Params| {
+            let time = CostMatrices::generate(params).time;
+            path.windows(2).map(|e| time[(e[0], e[1])] + params.hover_time_at(e[1])).sum::<f64>()
+        };
+        assert!(approx_eq!(f64, time(&params) + 6.0 * 2.5, time(&fractional), epsilon = 1e-9, ulps = 0));
+    }
+
+    #[test]
+    fn invalid_coords_test() {
         let s = r#"
         {
 	        "battery_voltage": 22.8,
@@ -240,7 +2808,109 @@ mod tests {
 	        "power_up": 899.04,
 	        "power_down": 309.17,
 	        "power_hover": 545.8,
+	        "hover_time": 10,
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 10, "y": 200, "z": 300}
+            ]
+        }
+        "#;
+        let empty = s.replace(r#"{"x": 0, "y": 0, "z": 0},"#, "").replace(r#"{"x": 10, "y": 200, "z": 300}"#, "");
+        match Params::from_str(&empty) {
+            Err(ParamsParseError::InvalidValue(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // NaN can't be written in JSON, but the parameters may be built in code
+        let mut params = Params::from_str(s).unwrap();
+        params.coords[1].y = f64::NAN;
+        match params.validate() {
+            Err(ParamsParseError::InvalidValue(msg)) => assert!(msg.contains("coord 2")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wind_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 20,
+	        "speed_horizontal": 10,
+	        "speed_up": 2,
+	        "speed_down": 4,
+	        "power_horizontal": 400,
+	        "power_up": 800,
+	        "power_down": 300,
+	        "power_hover": 500,
 	        "hover_time": 0,
+	        "wind": {"x": 5, "y": 0},
+	        "coords": [{"x": 0, "y": 0, "z": 0},
+			           {"x": 300, "y": 0, "z": 0},
+			           {"x": 0, "y": 300, "z": 0}
+            ]
+        }
+        "#;
+
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(Some(Wind { x: 5.0, y: 0.0 }), params.wind);
+        let matrices = CostMatrices::generate(&params);
+
+        // West wind: eastbound 15 m/s, westbound 5 m/s
+        assert!(approx_eq!(f64, 20.0, matrices.time[(0, 1)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 60.0, matrices.time[(1, 0)], epsilon = 0.001, ulps = 0));
+        assert!(matrices.energy[(0, 1)] < matrices.energy[(1, 0)]);
+        assert!(matrices.capacity[(0, 1)] < matrices.capacity[(1, 0)]);
+        // Crosswind doesn't change the groundspeed
+        assert!(approx_eq!(f64, 30.0, matrices.time[(0, 2)], epsilon = 0.001, ulps = 0));
+        assert!(approx_eq!(f64, 30.0, matrices.time[(2, 0)], epsilon = 0.001, ulps = 0));
+        // Distance doesn't depend on the wind
+        assert!(approx_eq!(f64, 300.0, matrices.distance[(1, 0)], epsilon = 0.001, ulps = 0));
+
+        // Headwind is stronger than the drone
+        params.wind = Some(Wind { x: 20.0, y: 0.0 });
+        let matrices = CostMatrices::generate(&params);
+        assert!(matrices.time[(1, 0)].is_finite());
+        assert!(matrices.time[(1, 0)] > matrices.time[(0, 1)]);
+    }
+
+    #[test]
+    fn temperature_factor_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.battery_capacity = Some(4.0);
+        assert_eq!(1.0, params.temperature_factor());
+        for &(t, factor) in &[(30.0, 1.0), (20.0, 1.0), (0.0, 0.8), (-20.0, 0.6), (-40.0, 0.5)] {
+            params.temperature_c = Some(t);
+            assert!((params.temperature_factor() - factor).abs() < 1e-9);
+        }
+        assert_eq!(Some(2.0), params.usable_battery_capacity());
+        params.reserve_fraction = 0.25;
+        assert_eq!(Some(1.5), params.usable_battery_capacity());
+        params.reserve_fraction = 1.0;
+        assert!(matches!(
+            params.clone().validate(),
+            Err(ParamsParseError::InvalidValue(_))
+        ));
+        params.reserve_fraction = 0.0;
+
+        params.temperature_c = Some(f64::NAN);
+        match params.validate() {
+            Err(ParamsParseError::InvalidValue(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn payload_test() {
+        let s = r#"
+        {
+	        "battery_voltage": 22.8,
+	        "speed_horizontal": 12.5,
+	        "speed_up": 3.1,
+	        "speed_down": 3,
+	        "power_horizontal": 486.2,
+	        "power_up": 899.04,
+	        "power_down": 309.17,
+	        "power_hover": 545.8,
+	        "hover_time": 10,
 	        "coords": [{"x": 0, "y": 0, "z": 0},
 			           {"x": 10, "y": 200, "z": 300},
 			           {"x": 200, "y": 450, "z": 12},
@@ -250,40 +2920,27 @@ mod tests {
             ]
         }
         "#;
+        let mut params = Params::from_str(s).unwrap();
+        assert_eq!(0.0, params.payload_kg);
+        assert_eq!(0.0, params.power_per_kg);
+        assert_eq!(1.0, params.payload_factor());
 
-        let energy_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
-            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
-            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
-            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
-            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
-            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
-        ]).transpose();
-
-        let time_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 96.774, 39.395, 112.903, 33.951, 64.516,
-            100.000, f64::INFINITY, 96.000, 37.498, 95.333, 36.098,
-            39.395, 92.903, f64::INFINITY, 109.032, 20.646, 60.645,
-            116.667, 37.498, 112.667, f64::INFINITY, 112.000, 50.000,
-            33.951, 92.258, 20.646, 108.387, f64::INFINITY, 60.000,
-            66.667, 36.098, 62.667, 48.387, 62.000, f64::INFINITY
-        ]).transpose();
-
-        let capacity_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 1.155, 0.276, 1.526, 0.251, 0.925,
-            0.472, f64::INFINITY, 0.510, 0.399, 0.521, 0.339,
-            0.248, 1.166, f64::INFINITY, 1.289, 0.129, 0.868,
-            0.728, 0.285, 0.519, f64::INFINITY, 0.529, 0.361,
-            0.219, 1.173, 0.125, 1.294, f64::INFINITY, 0.739,
-            0.470, 0.567, 0.440, 0.702, 0.315, f64::INFINITY
-        ]).transpose();
-
-        let params = Params::from_str(s).unwrap();
-        let matrices = CostMatrices::generate(&params);
+        let mut prev = CostMatrices::generate(&params);
+        params.power_per_kg = 50.0;
+        for &payload_kg in &[0.5, 1.0, 2.5] {
+            params.payload_kg = payload_kg;
+            let matrices = CostMatrices::generate(&params);
+            for (e, prev_e) in matrices.energy.iter().zip(prev.energy.iter()) {
+                if e.is_finite() {
+                    assert!(e > prev_e);
+                }
+            }
+            assert_eq!(prev.time, matrices.time);
+            prev = matrices;
+        }
 
-        assert!(approx_eq!(Matrix, Matrix(energy_cost), Matrix(matrices.energy)));
-        assert!(approx_eq!(Matrix, Matrix(time_cost), Matrix(matrices.time)));
-        assert!(approx_eq!(Matrix, Matrix(capacity_cost), Matrix(matrices.capacity)));
+        // 2.5 kg * 50 W/kg = 125 W over 545.8 W of hover power
+        assert!(approx_eq!(f64, 1.0 + 125.0 / 545.8, params.payload_factor(), epsilon = 0.000001, ulps = 0));
     }
 
     pub struct Matrix(DMatrix<f64>);
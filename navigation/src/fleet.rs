@@ -0,0 +1,242 @@
+use crate::config::Optimize;
+use crate::cost_generator::{CostMatrices, Params};
+use crate::path_finder::{self, FindResult};
+use crate::{check_non_negative, hover_cost_at, Error, Plan};
+use nalgebra::DMatrix;
+
+/// Maximum number of the k-means iterations, usually it converges much earlier.
+const MAX_ITERATIONS: usize = 100;
+
+/// Routes of several drones sharing the start point (the depot).
+#[derive(Debug)]
+pub struct Fleet {
+    /// Route of each drone, the points of the routes don't overlap
+    /// except the start point.
+    pub plans: Vec<Plan>,
+
+    /// Maximum route cost by the optimized parameter, hovering included.
+    pub makespan: f64,
+}
+
+/// Plans the routes of `drones` drones starting from the depot,
+/// see [`plan_fleet_with`].
+pub fn plan_fleet(params: &Params, optimize: Optimize, drones: usize) -> Result<Fleet, Error> {
+    let snapped;
+    let params = match params.snap_grid {
+        Some(grid) if !params.is_snapped() => {
+            snapped = params.clone().snap_to_grid(grid)?;
+            &snapped
+        }
+        _ => params,
+    };
+
+    plan_fleet_with(
+        params,
+        CostMatrices::generate(params),
+        optimize,
+        drones,
+        |cost| path_finder::find(cost).ok_or(Error::UnableToFindPath),
+    )
+}
+
+/// Splits the points except the depot among at most `drones` drones
+/// with [`partition`] and finds the route of each drone from the depot
+/// with the `find` function. The drones left without points are omitted.
+pub fn plan_fleet_with<F>(
+    params: &Params,
+    cost_matrices: CostMatrices,
+    optimize: Optimize,
+    drones: usize,
+    mut find: F,
+) -> Result<Fleet, Error>
+where
+    F: FnMut(&DMatrix<f64>) -> Result<FindResult, Error>,
+{
+    if !params.precedences.is_empty() || params.required.is_some() {
+        return Err(Error::ConstraintsWithDrones);
+    }
+    let cost = cost_matrices.get(optimize);
+    check_non_negative(cost)?;
+    match path_finder::find_disconnected(cost) {
+        Some(vertex) if params.has_no_fly_zones() => {
+            return Err(Error::BlockedByNoFlyZones { vertex })
+        }
+        Some(vertex) => return Err(Error::Disconnected { vertex }),
+        None => (),
+    }
+
+    let mut plans = Vec::new();
+    let mut makespan: f64 = 0.0;
+    for group in partition(params, drones) {
+        let vertices: Vec<usize> = Some(params.depot).into_iter().chain(group).collect();
+        let m = vertices.len();
+        let sub_cost = DMatrix::from_fn(m, m, |i, j| cost[(vertices[i], vertices[j])]);
+        let result = find(&sub_cost)?.map_vertices(&vertices);
+        let plan = Plan::new(params, result, cost_matrices.clone());
+
+        // Hovering is paid once at each point, the depot included
+        let hover: f64 = plan
+            .path
+            .iter()
+            .skip(1)
+            .map(|p| hover_cost_at(params, optimize, p.point_index))
+            .sum();
+        makespan = makespan.max(plan.result.get_cost() + hover);
+        plans.push(plan);
+    }
+
+    Ok(Fleet { plans, makespan })
+}
+
+/// Splits the points except the depot into at most `k` groups of nearby
+/// points by k-means over the horizontal coordinates. The centroids start at
+/// the points farthest from the depot and from each other, so the result
+/// is deterministic. Each group is sorted, the groups are ordered by their
+/// first point; empty groups are omitted.
+pub fn partition(params: &Params, k: usize) -> Vec<Vec<usize>> {
+    let points: Vec<(f64, f64)> = params.coords.iter().map(|c| (c.x, c.y)).collect();
+    let depot = params.depot;
+    let others: Vec<usize> = (0..points.len()).filter(|&i| i != depot).collect();
+    let k = k.max(1).min(others.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    let dist2 = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+    // The first point with the maximum key, so the ties are broken by the index
+    let farthest = |key: &dyn Fn(usize) -> f64| {
+        others.iter().fold(
+            others[0],
+            |best, &i| if key(i) > key(best) { i } else { best },
+        )
+    };
+
+    let mut centroids = vec![points[farthest(&|i| dist2(points[i], points[depot]))]];
+    while centroids.len() < k {
+        let next = farthest(&|i| {
+            centroids
+                .iter()
+                .map(|&c| dist2(points[i], c))
+                .fold(f64::INFINITY, f64::min)
+        });
+        centroids.push(points[next]);
+    }
+
+    let nearest = |p: (f64, f64), centroids: &[(f64, f64)]| {
+        (1..centroids.len()).fold(0, |best, c| {
+            if dist2(p, centroids[c]) < dist2(p, centroids[best]) {
+                c
+            } else {
+                best
+            }
+        })
+    };
+    let assign = |centroids: &[(f64, f64)]| -> Vec<usize> {
+        others
+            .iter()
+            .map(|&i| nearest(points[i], centroids))
+            .collect()
+    };
+    let mut assignment = assign(&centroids);
+    for _ in 0..MAX_ITERATIONS {
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<(f64, f64)> = others
+                .iter()
+                .zip(&assignment)
+                .filter(|&(_, &a)| a == c)
+                .map(|(&i, _)| points[i])
+                .collect();
+            // An empty group keeps its centroid
+            if !members.is_empty() {
+                let len = members.len() as f64;
+                *centroid = (
+                    members.iter().map(|p| p.0).sum::<f64>() / len,
+                    members.iter().map(|p| p.1).sum::<f64>() / len,
+                );
+            }
+        }
+        let next = assign(&centroids);
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+    }
+
+    let mut groups = vec![Vec::new(); k];
+    for (&i, &a) in others.iter().zip(&assignment) {
+        groups[a].push(i);
+    }
+    groups.retain(|g| !g.is_empty());
+    groups.sort();
+
+    groups
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        assert_eq!(vec![vec![1, 2, 3, 4, 5]], partition(&params, 1));
+        // Point 1 (x = 10) is far in the west, the rest are to the east of it
+        assert_eq!(vec![vec![1], vec![2, 3, 4, 5]], partition(&params, 2));
+        // No more groups than points
+        assert_eq!(5, partition(&params, 10).len());
+
+        // The depot is left out of the groups
+        params.depot = 2;
+        assert_eq!(vec![vec![0, 1, 3, 4, 5]], partition(&params, 1));
+        for group in partition(&params, 3) {
+            assert!(!group.contains(&2));
+        }
+
+        params.depot = 0;
+        params.coords.truncate(1);
+        assert!(partition(&params, 2).is_empty());
+    }
+
+    #[test]
+    fn plan_fleet_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let fleet = plan_fleet(&params, Optimize::Energy, 2).unwrap();
+        assert_eq!(2, fleet.plans.len());
+
+        let mut visited = Vec::new();
+        for plan in &fleet.plans {
+            assert_eq!(Some(0), plan.path.first().map(|p| p.point_index));
+            assert_eq!(Some(0), plan.path.last().map(|p| p.point_index));
+            let inner = &plan.path[1..plan.path.len() - 1];
+            visited.extend(inner.iter().map(|p| p.point_index));
+            // The path edges are in the indices of the params coordinates
+            let edges = plan.result.get_path();
+            assert_eq!(plan.path.len() - 1, edges.len());
+            for (e, p) in edges.iter().zip(&plan.path) {
+                assert_eq!(p.point_index, e.from());
+            }
+        }
+        // The tours don't overlap and cover all the points
+        visited.sort_unstable();
+        assert_eq!(vec![1, 2, 3, 4, 5], visited);
+
+        let max_cost = fleet
+            .plans
+            .iter()
+            .map(|p| p.result.get_cost())
+            .fold(0.0, f64::max);
+        assert_eq!(max_cost, fleet.makespan);
+
+        let single = crate::plan(&params, Optimize::Energy).unwrap();
+        assert!(fleet.makespan <= single.result.get_cost());
+    }
+
+    #[test]
+    fn plan_fleet_constraints_test() {
+        let mut params = Params::from_file("params.json").unwrap();
+        params.precedences = vec![(1, 2)];
+        assert!(matches!(
+            plan_fleet(&params, Optimize::Energy, 2),
+            Err(Error::ConstraintsWithDrones)
+        ));
+    }
+}
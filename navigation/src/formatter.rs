@@ -1,125 +1,1853 @@
 extern crate nalgebra;
 extern crate serde;
+#[cfg(feature = "json")]
 extern crate serde_json;
 extern crate thiserror;
 use thiserror::Error;
 
+use crate::config::Optimize;
+use crate::cost_generator::{MatrixCell, Params};
+use crate::path_finder::Optimality;
 use nalgebra::DMatrix;
-use serde::ser::SerializeSeq;
 use serde::{Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::io::Write;
+use std::iter::once;
+
+pub use crate::route::{EdgeCost, Point, Stats, SCHEMA_VERSION};
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[cfg(feature = "json")]
     #[error("Cannot save result as JSON: {0}")]
     SerializeJson(#[from] serde_json::Error),
 
     #[error("Cannot save result: {0}")]
     IO(#[from] std::io::Error),
+
+    #[error("{0} output isn't supported for this command")]
+    Unsupported(&'static str),
 }
 
 type Result = std::result::Result<(), Error>;
 
+/// Result of solving the params. The path, the edge costs, the totals
+/// and the stats are serialized the same as [`crate::Plan`], so it can be
+/// deserialized from the JSON output. The JSON keys follow the declaration
+/// order, so the new fields go to the end.
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct OutputData<'a> {
-    pub energy: Matrix<'a>,
-    pub time: Matrix<'a>,
-    pub capacity: Matrix<'a>,
+    pub schema_version: u32,
+    /// Cost matrices, omitted if not requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<Matrix<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<Matrix<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<Matrix<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<Matrix<'a>>,
     pub path: Vec<Point>,
     pub energy_cost: f64,
     pub time_cost: f64,
     pub capacity_cost: f64,
+    pub distance_cost: f64,
+    /// Costs of each edge along the path.
+    pub edges: Vec<EdgeCost>,
+    /// Whether the path is provably optimal.
+    pub optimality: Optimality,
+    /// Whether the route fits into the battery capacity, if it's known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fits_battery: Option<bool>,
+    /// Legs of the route that doesn't fit into the battery capacity.
+    /// Each leg starts and ends at the start point.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub legs: Vec<Vec<Point>>,
+    /// Remaining battery capacity in Ah at each point of the path
+    /// without recharging, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remaining_capacity: Vec<f64>,
+    /// Position in the path of the first point reached with
+    /// the negative remaining capacity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_infeasible: Option<usize>,
+    /// Forward and reverse costs of the path, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+    /// All the paths of the minimum cost, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub optimal_paths: Vec<Vec<Point>>,
+    /// The cheapest paths in increasing cost order, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub best_paths: Vec<RankedPath<Point>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    /// Branch-and-bound nodes on the way to the path, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<TraceStep>,
+    /// Duration of the matrices generation and the path search
+    /// in milliseconds, omitted in the deterministic output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+    /// Drone parameters the result was produced from, if requested.
+    /// Isn't included in text output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<&'a Params>,
+    /// Indices of the points of the path in the visiting order.
+    /// Isn't included in text output.
+    pub order: Vec<usize>,
 }
 
-impl<'a> OutputData<'_> {
-    pub fn to_writer<W, F>(&self, writer: W, formatter: F) -> Result
-    where
-        W: Write,
-        F: OutputFormatter<W>,
-    {
-        formatter.fmt(self, writer)
+/// Cost matrices generated from the drone parameters, without the path.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MatricesOutputData<'a> {
+    pub energy: Matrix<'a>,
+    pub time: Matrix<'a>,
+    pub capacity: Matrix<'a>,
+    pub distance: Matrix<'a>,
+}
+
+/// Result of solving a precomputed cost matrix. Only the path
+/// vertex indices and its total cost are known in this case.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MatrixOutputData {
+    pub schema_version: u32,
+    pub path: Vec<usize>,
+    pub cost: f64,
+    /// Whether the path is provably optimal.
+    pub optimality: Optimality,
+    /// Forward and reverse costs of the path, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+    /// All the paths of the minimum cost, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub optimal_paths: Vec<Vec<usize>>,
+    /// The cheapest paths in increasing cost order, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub best_paths: Vec<RankedPath<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    /// Branch-and-bound nodes on the way to the path, if requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<TraceStep>,
+    /// Duration of the path search in milliseconds,
+    /// omitted in the deterministic output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+/// Routes of several drones from the same start point.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FleetOutputData<'a> {
+    pub schema_version: u32,
+    /// Route of each drone.
+    pub drones: Vec<OutputData<'a>>,
+    /// Maximum route cost by the optimized parameter.
+    pub makespan: f64,
+    /// Duration of the matrices generation and the path search
+    /// in milliseconds, omitted in the deterministic output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+/// Size of the problems of the params files, reported instead of the path
+/// by the dry run.
+#[derive(Debug, Serialize)]
+pub struct DryRunOutputData(pub Vec<ProblemSize>);
+
+/// Size and connectivity of the cost matrix of the optimized parameter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProblemSize {
+    pub file: String,
+    /// Number of the points.
+    pub n: usize,
+    /// Number of the finite edges between the distinct points.
+    pub finite_edges: usize,
+    /// 0-based indices of the points without finite incoming or outgoing edges.
+    pub isolated: Vec<usize>,
+    /// Cost of the nearest neighbor tour, the upper bound of the optimal cost.
+    /// Omitted if the greedy walk gets stuck.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nn_bound: Option<f64>,
+}
+
+impl ProblemSize {
+    fn isolated_text(&self) -> String {
+        if self.isolated.is_empty() {
+            return String::from("none");
+        }
+        self.isolated
+            .iter()
+            .map(|i| (i + 1).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn nn_bound_text(&self, precision: usize) -> String {
+        match self.nn_bound {
+            Some(bound) => format!("{:.*}", precision, bound),
+            None => String::from("not found"),
+        }
+    }
+}
+
+/// One of the cheapest paths with its cost by the optimized parameter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RankedPath<P> {
+    pub cost: f64,
+    pub path: Vec<P>,
+}
+
+impl Stats {
+    fn write_text<W: Write>(&self, writer: &mut W, precision: usize) -> Result {
+        writeln!(writer, "\nStats:")?;
+        writeln!(writer, "Nodes expanded: {}", self.nodes_expanded)?;
+        writeln!(writer, "Nodes pruned: {}", self.nodes_pruned)?;
+        writeln!(writer, "Peak queue length: {}", self.peak_queue_len)?;
+        writeln!(writer, "Mirror tours pruned: {}", self.mirrors_pruned)?;
+        writeln!(writer, "Restarts: {}", self.restarts)?;
+        writeln!(writer, "Elapsed: {:.*} s", precision, self.elapsed)?;
+
+        Ok(())
     }
 }
 
+/// Costs of the path by the optimized parameter traversed in both directions.
 #[derive(Debug, Copy, Clone, PartialEq, Serialize)]
-pub struct Point {
-    #[serde(skip_serializing)]
-    pub point_index: usize,
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+pub struct Verification {
+    pub forward_cost: f64,
+    pub reverse_cost: f64,
+    /// Whether the costs differ more than by the rounding errors,
+    /// i.e. the cost matrix is asymmetric.
+    pub asymmetric: bool,
 }
 
-impl Display for Point {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}: ({}, {}, {})",
-            self.point_index + 1,
-            self.x,
-            self.y,
-            self.z
-        )
+impl Verification {
+    fn note(&self) -> &'static str {
+        if self.asymmetric {
+            " (differs, the cost matrix is asymmetric)"
+        } else {
+            ""
+        }
+    }
+
+    fn write_text<W: Write>(&self, writer: &mut W, precision: usize) -> Result {
+        writeln!(
+            writer,
+            "
+Forward cost: {:.*}",
+            precision, self.forward_cost
+        )?;
+        writeln!(
+            writer,
+            "Reverse cost: {:.*}{}",
+            precision,
+            self.reverse_cost,
+            self.note()
+        )?;
+
+        Ok(())
     }
+
+    fn write_md<W: Write>(&self, writer: &mut W, precision: usize) -> Result {
+        writeln!(
+            writer,
+            "- **Forward cost:** {:.*}",
+            precision, self.forward_cost
+        )?;
+        writeln!(
+            writer,
+            "- **Reverse cost:** {:.*}{}",
+            precision,
+            self.reverse_cost,
+            self.note()
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Node of the branch-and-bound search on the way to the found path.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceStep {
+    /// Edge *(from, to)* leading to the node, the root node has none.
+    pub edge: Option<(usize, usize)>,
+    pub lower_bound: f64,
+    #[serde(serialize_with = "serialize_matrix")]
+    pub reduced_matrix: DMatrix<f64>,
+}
+
+fn serialize_matrix<S>(matrix: &DMatrix<f64>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    Matrix(matrix).serialize(serializer)
+}
+
+impl TraceStep {
+    /// Title of the step with the 1-based vertices of the edge.
+    fn title(&self, precision: usize) -> String {
+        match self.edge {
+            Some((from, to)) => format!(
+                "{} -> {}, lower bound {:.*}",
+                from + 1,
+                to + 1,
+                precision,
+                self.lower_bound
+            ),
+            None => format!("Start, lower bound {:.*}", precision, self.lower_bound),
+        }
+    }
+}
+
+/// Writes the reduced matrices of the trace steps.
+fn write_trace<W: Write>(writer: &mut W, trace: &[TraceStep], precision: usize) -> Result {
+    writeln!(writer, "\nTrace:")?;
+    for (i, step) in trace.iter().enumerate() {
+        writeln!(writer, "Step {}: {}", i + 1, step.title(precision))?;
+        writeln!(writer, "{:.*}", precision, Matrix(&step.reduced_matrix))?;
+    }
+
+    Ok(())
+}
+
+fn write_md_trace<W: Write>(writer: &mut W, trace: &[TraceStep], precision: usize) -> Result {
+    writeln!(writer, "\n## Trace")?;
+    for (i, step) in trace.iter().enumerate() {
+        writeln!(writer, "\n### Step {}: {}\n", i + 1, step.title(precision))?;
+        writeln!(
+            writer,
+            "```\n{:.*}```",
+            precision,
+            Matrix(&step.reduced_matrix)
+        )?;
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct Matrix<'a>(pub &'a DMatrix<f64>);
 
-impl<'a> Display for Matrix<'_> {
+impl Matrix<'_> {
+    /// Renders the matrix as a table with the header row of the destination
+    /// `names` and the leading column of the departure `names`. Each column
+    /// is as wide as its widest cell, the numbers are aligned to the right
+    /// and the infinite cells are marked as `-` in the middle.
+    fn table(&self, names: &[String], precision: usize) -> String {
+        let cells: Vec<Vec<Option<String>>> = self
+            .0
+            .row_iter()
+            .map(|row| {
+                row.iter()
+                    .map(|c| Some(format!("{:.*}", precision, c)).filter(|_| c.is_finite()))
+                    .collect()
+            })
+            .collect();
+        let len = |s: &String| s.chars().count();
+        let name_width = names.iter().map(len).max().unwrap_or(0);
+        let widths: Vec<usize> = names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| {
+                cells
+                    .iter()
+                    .filter_map(|row| row[j].as_ref())
+                    .map(len)
+                    .fold(len(name), usize::max)
+            })
+            .collect();
+
+        let mut table = format!("{:w$}", "", w = name_width);
+        for (name, w) in names.iter().zip(&widths) {
+            table += &format!(" {:>w$}", name, w = w);
+        }
+        for (name, row) in names.iter().zip(&cells) {
+            table += &format!("\n{:<w$}", name, w = name_width);
+            for (cell, w) in row.iter().zip(&widths) {
+                table += &match cell {
+                    Some(cell) => format!(" {:>w$}", cell, w = w),
+                    None => format!(" {:^w$}", "-", w = w),
+                };
+            }
+        }
+        table.push('\n');
+
+        table
+    }
+}
+
+impl Display for Matrix<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        let names: Vec<String> = (1..=self.0.nrows()).map(|i| i.to_string()).collect();
+        f.write_str(&self.table(&names, f.precision().unwrap_or(3)))
     }
 }
 
-impl<'a> Serialize for Matrix<'_> {
+impl Serialize for Matrix<'_> {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(self.0.nrows()))?;
-        for row in self.0.row_iter() {
-            let row: Vec<&f64> = row.iter().collect();
-            seq.serialize_element(&row)?
+        let matrix = self.0;
+        serializer.collect_seq((0..matrix.nrows()).map(|row| MatrixRow { matrix, row }))
+    }
+}
+
+/// Streams the matrix row elements without collecting them.
+struct MatrixRow<'a> {
+    matrix: &'a DMatrix<f64>,
+    row: usize,
+}
+
+impl Serialize for MatrixRow<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            (0..self.matrix.ncols()).map(|col| MatrixCell(self.matrix[(self.row, col)])),
+        )
+    }
+}
+
+pub trait OutputFormatter<W: Write, D> {
+    fn fmt(&self, data: &D, writer: W) -> Result;
+}
+
+pub struct TextFormatter {
+    /// Number of digits after the decimal point.
+    pub precision: usize,
+}
+
+impl Default for TextFormatter {
+    fn default() -> Self {
+        TextFormatter { precision: 3 }
+    }
+}
+
+fn write_edges<W: Write>(writer: &mut W, edges: &[EdgeCost], precision: usize) -> Result {
+    if edges.is_empty() {
+        return Ok(());
+    }
+
+    let header = ["From", "To", "Energy", "Time", "Capacity", "Distance"];
+    let rows: Vec<[String; 6]> = edges
+        .iter()
+        .map(|e| {
+            [
+                (e.from + 1).to_string(),
+                (e.to + 1).to_string(),
+                format!("{:.*}", precision, e.energy),
+                format!("{:.*}", precision, e.time),
+                format!("{:.*}", precision, e.capacity),
+                format!("{:.*}", precision, e.distance),
+            ]
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|r| r[i].len())
+                .chain(once(header[i].len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    writeln!(writer, "\nEdges:")?;
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in once(header.as_slice()).chain(rows.iter().map(|r| &r[..])) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &w)| format!("{:>w$}", cell, w = w))
+            .collect();
+        writeln!(writer, "{}", cells.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Names of the `n` vertices in the matrix tables: 1-based
+/// indices followed by the point labels, if they're known.
+fn vertex_names(path: &[Point], n: usize) -> Vec<String> {
+    let mut names: Vec<String> = (1..=n).map(|i| i.to_string()).collect();
+    for p in path {
+        if let Some(label) = &p.label {
+            names[p.point_index] = format!("{} {}", p.point_index + 1, label);
         }
+    }
 
-        seq.end()
+    names
+}
+
+impl<W: Write> OutputFormatter<W, OutputData<'_>> for TextFormatter {
+    fn fmt(&self, data: &OutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        let prec = self.precision;
+        let matrices = [
+            ("Energy", &data.energy),
+            ("Capacity", &data.capacity),
+            ("Time", &data.time),
+            ("Distance", &data.distance),
+        ];
+        let mut matrices = matrices
+            .iter()
+            .filter_map(|(name, m)| m.as_ref().map(|m| (name, m)))
+            .peekable();
+        if let Some((_, m)) = matrices.peek() {
+            writeln!(
+                writer,
+                "Row - departure point, column - destination point\n"
+            )?;
+            let names = vertex_names(&data.path, m.0.nrows());
+            for (name, m) in matrices {
+                writeln!(writer, "{}:\n{}", name, m.table(&names, prec))?;
+            }
+        }
+        writeln!(writer, "Path:")?;
+        for (i, p) in data.path.iter().enumerate() {
+            match data.remaining_capacity.get(i) {
+                Some(remaining) => {
+                    let mark = if data.first_infeasible == Some(i) {
+                        " (battery exhausted)"
+                    } else {
+                        ""
+                    };
+                    writeln!(writer, "{:.*}  {:.*} Ah{}", prec, p, prec, remaining, mark)?
+                }
+                None => writeln!(writer, "{:.*}", prec, p)?,
+            }
+        }
+        write_edges(&mut writer, &data.edges, prec)?;
+        writeln!(writer, "\nEnergy: {:.*} kJ", prec, data.energy_cost)?;
+        writeln!(writer, "Capacity: {:.*} Ah", prec, data.capacity_cost)?;
+        writeln!(writer, "Time: {:.*} s", prec, data.time_cost)?;
+        writeln!(writer, "Distance: {:.*} m", prec, data.distance_cost)?;
+        writeln!(writer, "Optimality: {}", data.optimality)?;
+        if let Some(verification) = &data.verification {
+            verification.write_text(&mut writer, prec)?;
+        }
+        match data.fits_battery {
+            Some(true) => writeln!(writer, "\nRoute fits into the battery capacity")?,
+            Some(false) => {
+                writeln!(
+                    writer,
+                    "\nRoute doesn't fit into the battery capacity, legs:"
+                )?;
+                for (i, leg) in data.legs.iter().enumerate() {
+                    writeln!(writer, "Leg {}:", i + 1)?;
+                    for p in leg {
+                        writeln!(writer, "{:.*}", prec, p)?;
+                    }
+                }
+            }
+            None => (),
+        }
+        if !data.optimal_paths.is_empty() {
+            writeln!(writer, "\nAll optimal paths:")?;
+            for (i, path) in data.optimal_paths.iter().enumerate() {
+                writeln!(writer, "Path {}:", i + 1)?;
+                for p in path {
+                    writeln!(writer, "{:.*}", prec, p)?;
+                }
+            }
+        }
+        if !data.best_paths.is_empty() {
+            writeln!(writer, "\nBest paths:")?;
+            for (i, ranked) in data.best_paths.iter().enumerate() {
+                writeln!(writer, "Path {} (cost {:.*}):", i + 1, prec, ranked.cost)?;
+                for p in &ranked.path {
+                    writeln!(writer, "{:.*}", prec, p)?;
+                }
+            }
+        }
+        if !data.trace.is_empty() {
+            write_trace(&mut writer, &data.trace, prec)?;
+        }
+        if let Some(stats) = &data.stats {
+            stats.write_text(&mut writer, prec)?;
+        }
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
     }
 }
 
-pub trait OutputFormatter<W: Write> {
-    fn fmt(&self, data: &OutputData<'_>, writer: W) -> Result;
+impl<W: Write> OutputFormatter<W, MatrixOutputData> for TextFormatter {
+    fn fmt(&self, data: &MatrixOutputData, writer: W) -> Result {
+        let mut writer = writer;
+        let prec = self.precision;
+        writeln!(writer, "Path:")?;
+        for i in &data.path {
+            writeln!(writer, "{}", i + 1)?;
+        }
+        writeln!(writer, "\nCost: {:.*}", prec, data.cost)?;
+        writeln!(writer, "Optimality: {}", data.optimality)?;
+        if let Some(verification) = &data.verification {
+            verification.write_text(&mut writer, prec)?;
+        }
+        if !data.optimal_paths.is_empty() {
+            writeln!(writer, "\nAll optimal paths:")?;
+            for (i, path) in data.optimal_paths.iter().enumerate() {
+                writeln!(writer, "Path {}:", i + 1)?;
+                for p in path {
+                    writeln!(writer, "{}", p + 1)?;
+                }
+            }
+        }
+        if !data.best_paths.is_empty() {
+            writeln!(writer, "\nBest paths:")?;
+            for (i, ranked) in data.best_paths.iter().enumerate() {
+                writeln!(writer, "Path {} (cost {:.*}):", i + 1, prec, ranked.cost)?;
+                for p in &ranked.path {
+                    writeln!(writer, "{}", p + 1)?;
+                }
+            }
+        }
+        if !data.trace.is_empty() {
+            write_trace(&mut writer, &data.trace, prec)?;
+        }
+        if let Some(stats) = &data.stats {
+            stats.write_text(&mut writer, prec)?;
+        }
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, MatricesOutputData<'_>> for TextFormatter {
+    fn fmt(&self, data: &MatricesOutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        let prec = self.precision;
+        writeln!(
+            writer,
+            "Row - departure point, column - destination point\n"
+        )?;
+        writeln!(writer, "Energy:\n{:.*}", prec, data.energy)?;
+        writeln!(writer, "Capacity:\n{:.*}", prec, data.capacity)?;
+        writeln!(writer, "Time:\n{:.*}", prec, data.time)?;
+        write!(writer, "Distance:\n{:.*}", prec, data.distance)?;
+
+        Ok(writer.flush()?)
+    }
+}
+
+/// GitHub-flavored Markdown report: the path is rendered as a table,
+/// the cost matrices as fenced code blocks to preserve the alignment.
+pub struct MarkdownFormatter {
+    /// Number of digits after the decimal point.
+    pub precision: usize,
 }
 
-pub struct TextFormatter;
+impl Default for MarkdownFormatter {
+    fn default() -> Self {
+        MarkdownFormatter { precision: 3 }
+    }
+}
+
+/// Escapes the characters that break a Markdown table cell.
+fn md_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn write_md_path<W: Write>(writer: &mut W, path: &[Point]) -> Result {
+    let labeled = path.iter().any(|p| p.label.is_some());
+    if labeled {
+        writeln!(writer, "| index | label | x | y | z |")?;
+        writeln!(writer, "|------:|-------|--:|--:|--:|")?;
+    } else {
+        writeln!(writer, "| index | x | y | z |")?;
+        writeln!(writer, "|------:|--:|--:|--:|")?;
+    }
+    for p in path {
+        write!(writer, "| {} |", p.point_index + 1)?;
+        if labeled {
+            write!(writer, " {} |", md_cell(p.label.as_deref().unwrap_or("")))?;
+        }
+        writeln!(writer, " {} | {} | {} |", p.x, p.y, p.z)?;
+    }
+
+    Ok(())
+}
 
-impl<W: Write> OutputFormatter<W> for TextFormatter {
+fn write_md_indices<W: Write>(writer: &mut W, path: &[usize]) -> Result {
+    writeln!(writer, "| index |")?;
+    writeln!(writer, "|------:|")?;
+    for i in path {
+        writeln!(writer, "| {} |", i + 1)?;
+    }
+
+    Ok(())
+}
+
+fn write_md_edges<W: Write>(writer: &mut W, edges: &[EdgeCost], precision: usize) -> Result {
+    if edges.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(writer, "\n## Edges\n")?;
+    writeln!(
+        writer,
+        "| from | to | energy | time | capacity | distance |"
+    )?;
+    writeln!(
+        writer,
+        "|-----:|---:|-------:|-----:|---------:|---------:|"
+    )?;
+    for e in edges {
+        writeln!(
+            writer,
+            "| {} | {} | {:.*} | {:.*} | {:.*} | {:.*} |",
+            e.from + 1,
+            e.to + 1,
+            precision,
+            e.energy,
+            precision,
+            e.time,
+            precision,
+            e.capacity,
+            precision,
+            e.distance
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_md_matrix<W: Write>(writer: &mut W, name: &str, table: &str) -> Result {
+    writeln!(writer, "## {}\n\n```\n{}```\n", name, table)?;
+
+    Ok(())
+}
+
+impl Stats {
+    fn write_md<W: Write>(&self, writer: &mut W, precision: usize) -> Result {
+        writeln!(writer, "\n## Stats\n")?;
+        writeln!(writer, "- **Nodes expanded:** {}", self.nodes_expanded)?;
+        writeln!(writer, "- **Nodes pruned:** {}", self.nodes_pruned)?;
+        writeln!(writer, "- **Peak queue length:** {}", self.peak_queue_len)?;
+        writeln!(writer, "- **Mirror tours pruned:** {}", self.mirrors_pruned)?;
+        writeln!(writer, "- **Restarts:** {}", self.restarts)?;
+        writeln!(writer, "- **Elapsed:** {:.*} s", precision, self.elapsed)?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputFormatter<W, OutputData<'_>> for MarkdownFormatter {
     fn fmt(&self, data: &OutputData<'_>, writer: W) -> Result {
         let mut writer = writer;
+        let prec = self.precision;
+        let matrices = [
+            ("Energy", &data.energy),
+            ("Capacity", &data.capacity),
+            ("Time", &data.time),
+            ("Distance", &data.distance),
+        ];
+        if matrices.iter().any(|(_, m)| m.is_some()) {
+            writeln!(
+                writer,
+                "Row - departure point, column - destination point\n"
+            )?;
+        }
+        for (name, m) in &matrices {
+            if let Some(m) = m {
+                let names = vertex_names(&data.path, m.0.nrows());
+                write_md_matrix(&mut writer, name, &m.table(&names, prec))?;
+            }
+        }
+        writeln!(writer, "## Path\n")?;
+        write_md_path(&mut writer, &data.path)?;
+        write_md_edges(&mut writer, &data.edges, prec)?;
+        writeln!(writer, "\n## Summary\n")?;
+        writeln!(writer, "- **Energy:** {:.*} kJ", prec, data.energy_cost)?;
+        writeln!(writer, "- **Capacity:** {:.*} Ah", prec, data.capacity_cost)?;
+        writeln!(writer, "- **Time:** {:.*} s", prec, data.time_cost)?;
+        writeln!(writer, "- **Distance:** {:.*} m", prec, data.distance_cost)?;
+        writeln!(writer, "- **Optimality:** {}", data.optimality)?;
+        if let Some(verification) = &data.verification {
+            verification.write_md(&mut writer, prec)?;
+        }
+        match data.fits_battery {
+            Some(true) => writeln!(writer, "\nRoute fits into the battery capacity.")?,
+            Some(false) => {
+                writeln!(writer, "\nRoute doesn't fit into the battery capacity.")?;
+                for (i, leg) in data.legs.iter().enumerate() {
+                    writeln!(writer, "\n### Leg {}\n", i + 1)?;
+                    write_md_path(&mut writer, leg)?;
+                }
+            }
+            None => (),
+        }
+        if !data.optimal_paths.is_empty() {
+            writeln!(writer, "\n## All optimal paths")?;
+            for (i, path) in data.optimal_paths.iter().enumerate() {
+                writeln!(writer, "\n### Path {}\n", i + 1)?;
+                write_md_path(&mut writer, path)?;
+            }
+        }
+        if !data.best_paths.is_empty() {
+            writeln!(writer, "\n## Best paths")?;
+            for (i, ranked) in data.best_paths.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "\n### Path {} (cost {:.*})\n",
+                    i + 1,
+                    prec,
+                    ranked.cost
+                )?;
+                write_md_path(&mut writer, &ranked.path)?;
+            }
+        }
+        if !data.trace.is_empty() {
+            write_md_trace(&mut writer, &data.trace, prec)?;
+        }
+        if let Some(stats) = &data.stats {
+            stats.write_md(&mut writer, prec)?;
+        }
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, MatrixOutputData> for MarkdownFormatter {
+    fn fmt(&self, data: &MatrixOutputData, writer: W) -> Result {
+        let mut writer = writer;
+        let prec = self.precision;
+        writeln!(writer, "## Path\n")?;
+        write_md_indices(&mut writer, &data.path)?;
+        writeln!(writer, "\n## Summary\n")?;
+        writeln!(writer, "- **Cost:** {:.*}", prec, data.cost)?;
+        writeln!(writer, "- **Optimality:** {}", data.optimality)?;
+        if let Some(verification) = &data.verification {
+            verification.write_md(&mut writer, prec)?;
+        }
+        if !data.optimal_paths.is_empty() {
+            writeln!(writer, "\n## All optimal paths")?;
+            for (i, path) in data.optimal_paths.iter().enumerate() {
+                writeln!(writer, "\n### Path {}\n", i + 1)?;
+                write_md_indices(&mut writer, path)?;
+            }
+        }
+        if !data.best_paths.is_empty() {
+            writeln!(writer, "\n## Best paths")?;
+            for (i, ranked) in data.best_paths.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "\n### Path {} (cost {:.*})\n",
+                    i + 1,
+                    prec,
+                    ranked.cost
+                )?;
+                write_md_indices(&mut writer, &ranked.path)?;
+            }
+        }
+        if !data.trace.is_empty() {
+            write_md_trace(&mut writer, &data.trace, prec)?;
+        }
+        if let Some(stats) = &data.stats {
+            stats.write_md(&mut writer, prec)?;
+        }
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, MatricesOutputData<'_>> for MarkdownFormatter {
+    fn fmt(&self, data: &MatricesOutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        let prec = self.precision;
         writeln!(
             writer,
-            "Row - departure point, cloumn - destination point\n"
+            "Row - departure point, column - destination point\n"
         )?;
-        write!(writer, "Energy:{:.3}", data.energy)?;
-        write!(writer, "Capacity:{:.3}", data.capacity)?;
-        write!(writer, "Time:{:.3}", data.time)?;
-        writeln!(writer, "Path:")?;
+        write_md_matrix(&mut writer, "Energy", &format!("{:.*}", prec, data.energy))?;
+        write_md_matrix(
+            &mut writer,
+            "Capacity",
+            &format!("{:.*}", prec, data.capacity),
+        )?;
+        write_md_matrix(&mut writer, "Time", &format!("{:.*}", prec, data.time))?;
+        write_md_matrix(
+            &mut writer,
+            "Distance",
+            &format!("{:.*}", prec, data.distance),
+        )?;
+
+        Ok(writer.flush()?)
+    }
+}
+
+/// Graphviz digraph of the tour, e.g. for `dot -Tpng`. The nodes are placed
+/// at the horizontal coordinates of the points, the edges are labeled
+/// with the cost of the optimized parameter.
+pub struct DotFormatter {
+    /// Number of digits after the decimal point.
+    pub precision: usize,
+
+    /// Parameter whose edge costs are used as the labels.
+    pub optimize: Optimize,
+}
+
+/// Escapes the string for a quoted DOT identifier.
+fn dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl DotFormatter {
+    fn edge_label(&self, edge: &EdgeCost) -> String {
+        let (cost, unit) = match self.optimize {
+            Optimize::Intuitive => (edge.distance, "m"),
+            Optimize::Time => (edge.time, "s"),
+            Optimize::Battery => (edge.capacity, "Ah"),
+            Optimize::Energy => (edge.energy, "kJ"),
+        };
+        format!("{:.*} {}", self.precision, cost, unit)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, OutputData<'_>> for DotFormatter {
+    fn fmt(&self, data: &OutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        writeln!(writer, "digraph tour {{")?;
+        // The closed tour returns to the start, each point is declared once
+        let mut declared = Vec::new();
         for p in &data.path {
-            writeln!(writer, "{:.3}", p)?;
+            if declared.contains(&p.point_index) {
+                continue;
+            }
+            declared.push(p.point_index);
+            let label = match &p.label {
+                Some(label) => format!("{} {}", p.point_index + 1, label),
+                None => (p.point_index + 1).to_string(),
+            };
+            writeln!(
+                writer,
+                "    {} [label=\"{}\", pos=\"{},{}!\"];",
+                p.point_index + 1,
+                dot_string(&label),
+                p.x,
+                p.y
+            )?;
+        }
+        for e in &data.edges {
+            writeln!(
+                writer,
+                "    {} -> {} [label=\"{}\"];",
+                e.from + 1,
+                e.to + 1,
+                self.edge_label(e)
+            )?;
         }
-        writeln!(writer, "\nEnergy: {:.3} kJ", data.energy_cost)?;
-        writeln!(writer, "Capacity: {:.3} Ah", data.capacity_cost)?;
-        writeln!(writer, "Time: {:.3} s", data.time_cost)?;
+        writeln!(writer, "}}")?;
 
         Ok(writer.flush()?)
     }
 }
 
-pub struct JsonFormatter;
+impl<W: Write> OutputFormatter<W, MatrixOutputData> for DotFormatter {
+    fn fmt(&self, data: &MatrixOutputData, writer: W) -> Result {
+        let mut writer = writer;
+        writeln!(writer, "digraph tour {{")?;
+        let mut declared = Vec::new();
+        for &i in &data.path {
+            if !declared.contains(&i) {
+                declared.push(i);
+                writeln!(writer, "    {};", i + 1)?;
+            }
+        }
+        for (from, to) in data.path.iter().zip(data.path.iter().skip(1)) {
+            writeln!(writer, "    {} -> {};", from + 1, to + 1)?;
+        }
+        writeln!(writer, "}}")?;
+
+        Ok(writer.flush()?)
+    }
+}
 
-impl<W: Write> OutputFormatter<W> for JsonFormatter {
+impl<W: Write> OutputFormatter<W, MatricesOutputData<'_>> for DotFormatter {
+    fn fmt(&self, _data: &MatricesOutputData<'_>, _writer: W) -> Result {
+        Err(Error::Unsupported("DOT"))
+    }
+}
+
+/// Standalone SVG plot of the tour in the horizontal plane. The points are
+/// drawn as circles, the start point is highlighted.
+pub struct SvgFormatter {
+    /// Width of the image in pixels, the height follows the coordinates aspect ratio.
+    pub width: f64,
+}
+
+impl Default for SvgFormatter {
+    fn default() -> Self {
+        SvgFormatter { width: 800.0 }
+    }
+}
+
+/// Minimum ratio of the narrow side of the plotted area to the wide one.
+const SVG_MIN_ASPECT: f64 = 0.25;
+
+/// Escapes the string for the SVG text content.
+fn svg_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Rounds the derived lengths (margins, radii, font sizes) to
+/// three decimal places to keep the document readable.
+fn svg_length(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0
+}
+
+impl<W: Write> OutputFormatter<W, OutputData<'_>> for SvgFormatter {
     fn fmt(&self, data: &OutputData<'_>, writer: W) -> Result {
-        Ok(serde_json::to_writer_pretty(writer, data)?)
+        let mut writer = writer;
+        let (min_x, max_x, min_y, max_y) = data.path.iter().fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(min_x, max_x, min_y, max_y), p| {
+                (
+                    min_x.min(p.x),
+                    max_x.max(p.x),
+                    min_y.min(p.y),
+                    max_y.max(p.y),
+                )
+            },
+        );
+        let (min_x, max_x, min_y, max_y) = if data.path.is_empty() {
+            (0.0, 0.0, 0.0, 0.0)
+        } else {
+            (min_x, max_x, min_y, max_y)
+        };
+        // The points may lie on a line or coincide, the extent is kept positive
+        let extent = (max_x - min_x).max(max_y - min_y);
+        let extent = if extent > 0.0 { extent } else { 1.0 };
+        // The narrow side is widened around its center to keep the image
+        // from degenerating into a line
+        let widen = |min: f64, max: f64| {
+            let pad = (extent * SVG_MIN_ASPECT - (max - min)).max(0.0) / 2.0;
+            (min - pad, max + pad)
+        };
+        let (min_x, max_x) = widen(min_x, max_x);
+        let (min_y, max_y) = widen(min_y, max_y);
+        let margin = svg_length(extent / 20.0);
+        let radius = svg_length(extent / 100.0);
+        let view_width = max_x - min_x + 2.0 * margin;
+        let view_height = max_y - min_y + 2.0 * margin;
+
+        // The y axis of SVG points down, so y is negated (without a negative zero)
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+            self.width,
+            (self.width * view_height / view_width).round(),
+            min_x - margin,
+            -max_y - margin,
+            view_width,
+            view_height
+        )?;
+        let points: Vec<String> = data
+            .path
+            .iter()
+            .map(|p| format!("{},{}", p.x, 0.0 - p.y))
+            .collect();
+        writeln!(
+            writer,
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"{}\"/>",
+            points.join(" "),
+            svg_length(radius / 2.0)
+        )?;
+        let mut drawn = Vec::new();
+        for (i, p) in data.path.iter().enumerate() {
+            if drawn.contains(&p.point_index) {
+                continue;
+            }
+            drawn.push(p.point_index);
+            let (fill, r) = if i == 0 {
+                ("crimson", svg_length(radius * 1.5))
+            } else {
+                ("black", radius)
+            };
+            writeln!(
+                writer,
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                p.x,
+                0.0 - p.y,
+                r,
+                fill
+            )?;
+            let label = match &p.label {
+                Some(label) => format!("{} {}", p.point_index + 1, label),
+                None => (p.point_index + 1).to_string(),
+            };
+            writeln!(
+                writer,
+                "  <text x=\"{}\" y=\"{}\" font-size=\"{}\">{}</text>",
+                svg_length(p.x + r),
+                svg_length(-p.y - r),
+                svg_length(radius * 3.0),
+                svg_text(&label)
+            )?;
+        }
+        writeln!(writer, "</svg>")?;
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, MatrixOutputData> for SvgFormatter {
+    fn fmt(&self, _data: &MatrixOutputData, _writer: W) -> Result {
+        Err(Error::Unsupported("SVG"))
+    }
+}
+
+impl<W: Write> OutputFormatter<W, MatricesOutputData<'_>> for SvgFormatter {
+    fn fmt(&self, _data: &MatricesOutputData<'_>, _writer: W) -> Result {
+        Err(Error::Unsupported("SVG"))
+    }
+}
+
+impl<W: Write> OutputFormatter<W, DryRunOutputData> for TextFormatter {
+    fn fmt(&self, data: &DryRunOutputData, writer: W) -> Result {
+        let mut writer = writer;
+        for (i, size) in data.0.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "\n" };
+            writeln!(writer, "{}{}:", separator, size.file)?;
+            writeln!(writer, "Points: {}", size.n)?;
+            writeln!(writer, "Finite edges: {}", size.finite_edges)?;
+            writeln!(writer, "Isolated points: {}", size.isolated_text())?;
+            writeln!(
+                writer,
+                "Nearest neighbor bound: {}",
+                size.nn_bound_text(self.precision)
+            )?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, DryRunOutputData> for MarkdownFormatter {
+    fn fmt(&self, data: &DryRunOutputData, writer: W) -> Result {
+        let mut writer = writer;
+        writeln!(
+            writer,
+            "| file | points | finite edges | isolated points | nearest neighbor bound |"
+        )?;
+        writeln!(
+            writer,
+            "|------|-------:|-------------:|-----------------|-----------------------:|"
+        )?;
+        for size in &data.0 {
+            writeln!(
+                writer,
+                "| {} | {} | {} | {} | {} |",
+                md_cell(&size.file),
+                size.n,
+                size.finite_edges,
+                size.isolated_text(),
+                size.nn_bound_text(self.precision)
+            )?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, DryRunOutputData> for DotFormatter {
+    fn fmt(&self, _data: &DryRunOutputData, _writer: W) -> Result {
+        Err(Error::Unsupported("DOT"))
+    }
+}
+
+impl<W: Write> OutputFormatter<W, DryRunOutputData> for SvgFormatter {
+    fn fmt(&self, _data: &DryRunOutputData, _writer: W) -> Result {
+        Err(Error::Unsupported("SVG"))
+    }
+}
+
+impl<W: Write> OutputFormatter<W, FleetOutputData<'_>> for TextFormatter {
+    fn fmt(&self, data: &FleetOutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        for (i, drone) in data.drones.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "\n" };
+            writeln!(writer, "{}=== Drone {} ===", separator, i + 1)?;
+            self.fmt(drone, &mut writer)?;
+        }
+        writeln!(writer, "\nMakespan: {:.*}", self.precision, data.makespan)?;
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, FleetOutputData<'_>> for MarkdownFormatter {
+    fn fmt(&self, data: &FleetOutputData<'_>, writer: W) -> Result {
+        let mut writer = writer;
+        for (i, drone) in data.drones.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "\n" };
+            writeln!(writer, "{}# Drone {}\n", separator, i + 1)?;
+            self.fmt(drone, &mut writer)?;
+        }
+        writeln!(
+            writer,
+            "\n**Makespan:** {:.*}",
+            self.precision, data.makespan
+        )?;
+        if let Some(elapsed_ms) = data.elapsed_ms {
+            writeln!(writer, "\nComputed in {:.1} ms", elapsed_ms)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+}
+
+impl<W: Write> OutputFormatter<W, FleetOutputData<'_>> for DotFormatter {
+    fn fmt(&self, _data: &FleetOutputData<'_>, _writer: W) -> Result {
+        Err(Error::Unsupported("DOT"))
+    }
+}
+
+impl<W: Write> OutputFormatter<W, FleetOutputData<'_>> for SvgFormatter {
+    fn fmt(&self, _data: &FleetOutputData<'_>, _writer: W) -> Result {
+        Err(Error::Unsupported("SVG"))
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Default)]
+pub struct JsonFormatter {
+    /// Write JSON without indentation and line breaks.
+    pub compact: bool,
+    /// Number of digits after the decimal point the floating point
+    /// values are rounded to, full precision if not specified.
+    pub round: Option<usize>,
+}
+
+#[cfg(feature = "json")]
+impl JsonFormatter {
+    fn write<W, D, F>(&self, data: &D, writer: W, formatter: F) -> Result
+    where
+        W: Write,
+        D: Serialize,
+        F: serde_json::ser::Formatter,
+    {
+        match self.round {
+            Some(decimals) => {
+                let formatter = RoundingFormatter {
+                    inner: formatter,
+                    scale: 10f64.powi(decimals as i32),
+                };
+                data.serialize(&mut serde_json::Serializer::with_formatter(
+                    writer, formatter,
+                ))?
+            }
+            None => data.serialize(&mut serde_json::Serializer::with_formatter(
+                writer, formatter,
+            ))?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W: Write, D: Serialize> OutputFormatter<W, D> for JsonFormatter {
+    fn fmt(&self, data: &D, writer: W) -> Result {
+        if self.compact {
+            self.write(data, writer, serde_json::ser::CompactFormatter)
+        } else {
+            self.write(data, writer, serde_json::ser::PrettyFormatter::new())
+        }
+    }
+}
+
+/// Rounds the floating point values written by the `inner` formatter.
+/// Non-finite values never reach it, they are either written as strings,
+/// see [`MatrixCell`], or as `null` by `serde_json`.
+#[cfg(feature = "json")]
+struct RoundingFormatter<F> {
+    inner: F,
+    /// Power of ten of the number of the kept decimals.
+    scale: f64,
+}
+
+#[cfg(feature = "json")]
+impl<F: serde_json::ser::Formatter> serde_json::ser::Formatter for RoundingFormatter<F> {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        let rounded = (value * self.scale).round() / self.scale;
+        // Too large values overflow while scaling, they have no decimals anyway
+        let value = if rounded.is_finite() { rounded } else { value };
+        self.inner.write_f64(writer, value)
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    fn point(point_index: usize, x: f64, y: f64, z: f64) -> Point {
+        Point {
+            point_index,
+            label: None,
+            x,
+            y,
+            z,
+            merged: None,
+        }
+    }
+
+    fn output_data(matrix: &DMatrix<f64>) -> OutputData<'_> {
+        OutputData {
+            schema_version: SCHEMA_VERSION,
+            energy: Some(Matrix(matrix)),
+            time: Some(Matrix(matrix)),
+            capacity: Some(Matrix(matrix)),
+            distance: None,
+            path: vec![
+                point(0, 0.0, 0.0, 0.0),
+                point(1, 3.0, 4.0, 0.0),
+                point(0, 0.0, 0.0, 0.0),
+            ],
+            energy_cost: 1.5,
+            time_cost: 2.5,
+            capacity_cost: 0.5,
+            distance_cost: 10.0,
+            edges: vec![
+                EdgeCost {
+                    from: 0,
+                    to: 1,
+                    energy: 0.5,
+                    time: 1.0,
+                    capacity: 0.25,
+                    distance: 5.0,
+                },
+                EdgeCost {
+                    from: 1,
+                    to: 0,
+                    energy: 1.0,
+                    time: 1.5,
+                    capacity: 0.25,
+                    distance: 5.0,
+                },
+            ],
+            optimality: Optimality::Exact,
+            fits_battery: None,
+            legs: Vec::new(),
+            remaining_capacity: Vec::new(),
+            first_infeasible: None,
+            verification: None,
+            optimal_paths: Vec::new(),
+            best_paths: Vec::new(),
+            stats: None,
+            trace: Vec::new(),
+            elapsed_ms: None,
+            params: None,
+            order: vec![0, 1, 0],
+        }
+    }
+
+    #[test]
+    fn json_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut out = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(1.5, json["energy_cost"]);
+        assert_eq!(2.5, json["time_cost"]);
+        assert_eq!(0.5, json["capacity_cost"]);
+        assert_eq!(10.0, json["distance_cost"]);
+        assert_eq!(3, json["path"].as_array().unwrap().len());
+        assert!(json.get("stats").is_none());
+        assert!(json.get("fits_battery").is_none());
+        assert!(json.get("legs").is_none());
+    }
+
+    #[test]
+    fn battery_trace_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let mut data = output_data(&matrix);
+        data.remaining_capacity = vec![0.4, 0.15, -0.1];
+        data.first_infeasible = Some(2);
+
+        let mut out = Vec::new();
+        TextFormatter { precision: 2 }.fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text
+            .lines()
+            .skip_while(|&l| l != "Path:")
+            .skip(1)
+            .take(3)
+            .collect();
+        assert!(lines[0].ends_with("  0.40 Ah"));
+        assert!(lines[1].ends_with("  0.15 Ah"));
+        assert!(lines[2].ends_with("  -0.10 Ah (battery exhausted)"));
+
+        let mut out = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(3, json["remaining_capacity"].as_array().unwrap().len());
+        assert_eq!(2, json["first_infeasible"]);
+    }
+
+    #[test]
+    fn compact_json_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut pretty = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut pretty).unwrap();
+        assert!(pretty.contains(&b'\n'));
+
+        let mut compact = Vec::new();
+        JsonFormatter {
+            compact: true,
+            ..Default::default()
+        }
+        .fmt(&data, &mut compact)
+        .unwrap();
+        assert!(!compact.contains(&b'\n'));
+
+        let pretty: serde_json::Value = serde_json::from_slice(&pretty).unwrap();
+        let compact: serde_json::Value = serde_json::from_slice(&compact).unwrap();
+        assert_eq!(pretty, compact);
+    }
+
+    #[test]
+    fn rounded_json_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![f64::INFINITY, 1.23456, 2.5, f64::INFINITY]);
+        let mut data = output_data(&matrix);
+        data.energy_cost = 1.0 / 3.0;
+
+        let mut out = Vec::new();
+        JsonFormatter {
+            compact: true,
+            round: Some(2),
+        }
+        .fmt(&data, &mut out)
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(0.33, json["energy_cost"]);
+        assert_eq!(1.23, json["energy"][1][0]);
+        assert_eq!(2.5, json["energy"][0][1]);
+        assert_eq!("inf", json["energy"][0][0]);
+        assert_eq!(1, json["path"][1]["point_index"]);
+        assert_eq!(3.0, json["path"][1]["x"]);
+
+        let mut full = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut full).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&full).unwrap();
+        assert_eq!(1.0 / 3.0, json["energy_cost"]);
+    }
+
+    #[test]
+    fn legs_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let mut data = output_data(&matrix);
+        data.fits_battery = Some(false);
+        data.legs = vec![
+            vec![
+                point(0, 0.0, 0.0, 0.0),
+                point(1, 3.0, 4.0, 0.0),
+                point(0, 0.0, 0.0, 0.0),
+            ],
+            vec![
+                point(0, 0.0, 0.0, 0.0),
+                point(2, 6.0, 8.0, 0.0),
+                point(0, 0.0, 0.0, 0.0),
+            ],
+        ];
+
+        let mut out = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(false, json["fits_battery"]);
+        assert_eq!(2, json["legs"].as_array().unwrap().len());
+        assert_eq!(6.0, json["legs"][1][1]["x"]);
+
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Leg 2:\n1: (0, 0, 0)\n3: (6, 8, 0)\n1: (0, 0, 0)\n"));
+    }
+
+    #[test]
+    fn stats_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let mut data = output_data(&matrix);
+        data.stats = Some(Stats {
+            nodes_expanded: 7,
+            nodes_pruned: 3,
+            peak_queue_len: 12,
+            mirrors_pruned: 5,
+            restarts: 0,
+            elapsed: 0.25,
+        });
+
+        let mut out = Vec::new();
+        JsonFormatter::default().fmt(&data, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(7, json["stats"]["nodes_expanded"]);
+        assert_eq!(3, json["stats"]["nodes_pruned"]);
+        assert_eq!(12, json["stats"]["peak_queue_len"]);
+        assert_eq!(5, json["stats"]["mirrors_pruned"]);
+        assert_eq!(0.25, json["stats"]["elapsed"]);
+
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Nodes expanded: 7"));
+        assert!(text.contains("Peak queue length: 12"));
+        assert!(text.contains("Mirror tours pruned: 5"));
+        assert!(!text.contains("Computed in"));
+
+        data.elapsed_ms = Some(1.25);
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with("\nComputed in 1.2 ms\n"));
+    }
+
+    #[test]
+    fn text_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Distance: 10.000 m"));
+        assert!(text.contains("Optimality: exact\n"));
+        assert!(text.contains(
+            "Edges:\nFrom To Energy  Time Capacity Distance\n   1  2  0.500 1.000    0.250    5.000\n"
+        ));
+    }
+
+    #[test]
+    fn markdown_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut out = Vec::new();
+        MarkdownFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("## Energy\n\n```\n"));
+        assert!(text.contains("\n| index | x | y | z |\n"));
+        let rows: Vec<&str> = text
+            .split("## Path\n\n")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .skip(2)
+            .take_while(|l| l.starts_with('|'))
+            .collect();
+        assert_eq!(
+            vec![
+                "| 1 | 0 | 0 | 0 |",
+                "| 2 | 3 | 4 | 0 |",
+                "| 1 | 0 | 0 | 0 |"
+            ],
+            rows
+        );
+        assert!(text.contains("- **Distance:** 10.000 m\n"));
+
+        let data = MatrixOutputData {
+            schema_version: SCHEMA_VERSION,
+            path: vec![0, 2, 1, 0],
+            cost: 4.0,
+            optimality: Optimality::Timeout,
+            verification: None,
+            optimal_paths: Vec::new(),
+            best_paths: Vec::new(),
+            stats: None,
+            trace: Vec::new(),
+            elapsed_ms: None,
+        };
+        let mut out = Vec::new();
+        MarkdownFormatter { precision: 1 }
+            .fmt(&data, &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("## Path\n\n| index |\n|------:|\n| 1 |\n| 3 |\n| 2 |\n| 1 |\n"));
+        assert!(text.contains("- **Cost:** 4.0\n"));
+        assert!(text.contains("- **Optimality:** timeout\n"));
+    }
+
+    #[test]
+    fn dot_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let mut data = output_data(&matrix);
+        data.path[1].label = Some(String::from("Tower \"A\""));
+        let formatter = DotFormatter {
+            precision: 1,
+            optimize: Optimize::Energy,
+        };
+
+        let mut out = Vec::new();
+        formatter.fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(Some(&"digraph tour {"), lines.first());
+        assert_eq!(Some(&"}"), lines.last());
+        let nodes: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.contains("pos="))
+            .copied()
+            .collect();
+        assert_eq!(
+            vec![
+                "    1 [label=\"1\", pos=\"0,0!\"];",
+                "    2 [label=\"2 Tower \\\"A\\\"\", pos=\"3,4!\"];",
+            ],
+            nodes
+        );
+        // The tour is closed, so there are as many edges as points
+        let edges: Vec<&str> = lines.iter().filter(|l| l.contains("->")).copied().collect();
+        assert_eq!(
+            vec![
+                "    1 -> 2 [label=\"0.5 kJ\"];",
+                "    2 -> 1 [label=\"1.0 kJ\"];",
+            ],
+            edges
+        );
+
+        let data = MatricesOutputData {
+            energy: Matrix(&matrix),
+            time: Matrix(&matrix),
+            capacity: Matrix(&matrix),
+            distance: Matrix(&matrix),
+        };
+        assert!(matches!(
+            formatter.fmt(&data, Vec::new()),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn svg_output_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut out = Vec::new();
+        SvgFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(text.ends_with("</svg>\n"));
+        assert!(text.contains("viewBox=\"-0.2 -4.2 3.4 4.4\""));
+        let polyline = text.lines().find(|l| l.contains("<polyline")).unwrap();
+        let points = polyline.split('"').nth(1).unwrap();
+        assert_eq!(
+            vec!["0,0", "3,-4", "0,0"],
+            points.split(' ').collect::<Vec<_>>()
+        );
+        assert_eq!(2, text.matches("<circle").count());
+        assert_eq!(1, text.matches("crimson").count());
+    }
+
+    #[test]
+    fn svg_collinear_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+
+        // All the points on a vertical line
+        let mut data = output_data(&matrix);
+        data.path[1].x = 0.0;
+        let mut out = Vec::new();
+        SvgFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("NaN"));
+        assert!(!text.contains("inf"));
+        assert!(text.contains("width=\"800\" height=\"2514\""));
+
+        // And on a horizontal one
+        let mut data = output_data(&matrix);
+        data.path[1].y = 0.0;
+        let mut out = Vec::new();
+        SvgFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("width=\"800\" height=\"255\""));
+    }
+
+    #[test]
+    fn text_output_precision_test() {
+        let matrix = DMatrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 0.0]);
+        let data = output_data(&matrix);
+
+        let mut out = Vec::new();
+        TextFormatter { precision: 1 }.fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Energy: 1.5 kJ"));
+        assert!(text.contains("Distance: 10.0 m"));
+        assert!(text.contains(" 2.0\n"));
+
+        let mut out = Vec::new();
+        TextFormatter { precision: 6 }.fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Energy: 1.500000 kJ"));
+        assert!(text.contains("Distance: 10.000000 m"));
+        assert!(text.contains(" 2.000000\n"));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn matrix_table_test() {
+        let matrix = DMatrix::from_vec(3, 3, vec![
+            f64::INFINITY, 1.0, 2.0,
+            3.0, f64::INFINITY, 4.0,
+            5.0, 6.0, f64::INFINITY,
+        ]);
+        let text = format!("{:.1}", Matrix(&matrix));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(4, lines.len());
+        for i in 1..=3 {
+            assert!(lines[0].contains(&i.to_string()));
+        }
+        assert_eq!("1  -  3.0 5.0", lines[1]);
+        assert_eq!("3 2.0 4.0  - ", lines[3]);
+
+        let mut data = output_data(&matrix);
+        data.path[1].label = Some(String::from("Tower A"));
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Energy:\n              1 2 Tower A     3\n"));
+        assert!(text.contains("\n2 Tower A 1.000     -     6.000\n"));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn matrix_column_widths_test() {
+        let matrix = DMatrix::from_vec(4, 4, vec![
+            f64::INFINITY, 12345.678, 0.5, 3.0,
+            1.0, f64::INFINITY, 100.25, 7.0,
+            2.0, 9.0, f64::INFINITY, 1e6,
+            f64::INFINITY, 0.0, 8.0, f64::INFINITY,
+        ]);
+        for precision in [0, 2, 4].iter() {
+            let text = format!("{:.*}", precision, Matrix(&matrix));
+            let lengths: Vec<usize> = text.lines().map(|l| l.chars().count()).collect();
+            assert_eq!(5, lengths.len());
+            assert!(lengths.iter().all(|&l| l == lengths[0]), "{}", text);
+        }
+
+        // The columns are only as wide as their own cells
+        let text = format!("{:.1}", Matrix(&matrix));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("        1     2         3   4", lines[0]);
+        assert_eq!("1    -      1.0       2.0  - ", lines[1]);
+        assert_eq!("2 12345.7   -         9.0 0.0", lines[2]);
+        assert_eq!("4     3.0   7.0 1000000.0  - ", lines[4]);
+    }
+
+    #[test]
+    fn matrix_serialize_test() {
+        let n = 50;
+        let matrix = DMatrix::from_fn(n, n, |i, j| {
+            if i == j {
+                f64::INFINITY
+            } else {
+                (i * n + j) as f64
+            }
+        });
+
+        let json = serde_json::to_value(Matrix(&matrix)).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(n, rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let row = row.as_array().unwrap();
+            assert_eq!(n, row.len());
+            for (j, cell) in row.iter().enumerate() {
+                if i == j {
+                    assert_eq!("inf", *cell);
+                } else {
+                    assert_eq!(matrix[(i, j)], *cell);
+                }
+            }
+        }
+
+        let mut data = output_data(&matrix);
+        data.energy = None;
+        data.time = None;
+        data.capacity = None;
+        let json = serde_json::to_value(&data).unwrap();
+        assert!(json.get("energy").is_none());
+        assert!(json.get("time").is_none());
+        assert!(json.get("capacity").is_none());
+        assert!(json.get("path").is_some());
+
+        let mut out = Vec::new();
+        TextFormatter::default().fmt(&data, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("Row - departure point"));
+        assert!(text.starts_with("Path:"));
     }
 }
@@ -4,89 +4,814 @@ extern crate float_cmp;
 extern crate thiserror;
 
 pub mod config;
-mod cost_generator;
-mod formatter;
-mod path_finder;
-
-use crate::config::{Config, Optimize};
-use crate::cost_generator::{CostMatrices, Params};
-use formatter::{JsonFormatter, TextFormatter};
-use std::fs::File;
-use std::io::stdout;
-use std::iter::once_with;
+pub mod cost_generator;
+pub mod fleet;
+#[cfg(feature = "text")]
+pub mod formatter;
+pub mod path_finder;
+pub mod route;
+
+#[cfg(feature = "cli")]
+mod cli;
+
+use crate::config::Optimize;
+use crate::path_finder::{tour_vertices, SearchOptions};
+use nalgebra::DMatrix;
+use serde::{Deserialize, Serialize};
+use std::iter::once;
 use thiserror::Error;
 
+#[cfg(feature = "cli")]
+pub use crate::cli::{run, run_to_writer};
+pub use crate::cost_generator::{CostMatrices, Params, PartialParams};
+pub use crate::path_finder::{FindResult, Optimality, PathEdge, Stats};
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Unable to find path")]
     UnableToFindPath,
+
+    #[error("Cost matrix must be square, got {0}x{1}")]
+    NonSquareMatrix(usize, usize),
+
+    #[error("Cost from point {} to point {} is negative", .from + 1, .to + 1)]
+    NegativeCost { from: usize, to: usize },
+
+    #[error("At least {min} points are required, got {0}", min = path_finder::MIN_VERTICES)]
+    TooFewPoints(usize),
+
+    #[error("Point {} can't be visited within the battery capacity", .0 + 1)]
+    OutOfBatteryRange(usize),
+
+    #[error("Point {} has no finite edges and can't be visited", .vertex + 1)]
+    Disconnected { vertex: usize },
+
+    #[error("Point {} can't be reached without crossing the no-fly zones", .vertex + 1)]
+    BlockedByNoFlyZones { vertex: usize },
+
+    #[error(
+        "Visiting order and optional points are supported only by the branch-and-bound solver"
+    )]
+    UnsupportedConstraints,
+
+    #[error(
+        "Lexicographic optimization is supported only by the branch-and-bound solver \
+         without optional points"
+    )]
+    UnsupportedTieBreaks,
+
+    #[error("Optional points are not supported with the multiple paths output")]
+    OptionalPointsWithMultiplePaths,
+
+    #[error("Visiting order and optional points are not supported with multiple drones")]
+    ConstraintsWithDrones,
+
+    #[error("Multiple drones are supported for a single params file")]
+    DronesWithMultipleFiles,
+
+    #[error("Coinciding points: {}", format_pairs(.0))]
+    DuplicateCoords(Vec<(usize, usize)>),
+
+    #[error("Edge from point {} to point {} costs {cost:e}", .from + 1, .to + 1)]
+    ExtremeEdge { from: usize, to: usize, cost: f64 },
+
+    #[error("Cost of the edge from point {} to point {} is not a number", .from + 1, .to + 1)]
+    NanCost { from: usize, to: usize },
+
+    #[error("Held-Karp solver supports up to {max} points, got {0}", max = path_finder::HELD_KARP_MAX_VERTICES)]
+    TooManyPointsForHeldKarp(usize),
+
+    #[error("{failed} of {total} params files failed")]
+    BatchFailed { failed: usize, total: usize },
+
+    #[error(transparent)]
+    ParamsParse(#[from] cost_generator::ParamsParseError),
+
+    #[error(transparent)]
+    MatrixParse(#[from] cost_generator::MatrixParseError),
+
+    #[cfg(feature = "text")]
+    #[error(transparent)]
+    Output(#[from] formatter::Error),
+
+    #[cfg(feature = "json")]
+    #[error(transparent)]
+    Cache(#[from] cost_generator::CacheError),
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    let params = Params::from_file(config.params_file)?;
-    let cost_matrices = CostMatrices::generate(&params);
+impl Error {
+    /// Exit code of some of the params files failed, also used for the invalid arguments.
+    pub const EXIT_FAILURE: i32 = 1;
 
-    let cost_matrix = match config.optimize {
-        Optimize::Intuitive => &cost_matrices.distance,
-        Optimize::Time => &cost_matrices.time,
-        Optimize::Battery => &cost_matrices.capacity,
-        Optimize::Energy => &cost_matrices.energy,
-    };
-    let result = path_finder::find(cost_matrix).ok_or(Error::UnableToFindPath)?;
+    /// Exit code of the invalid params, matrix or options.
+    pub const EXIT_INVALID_INPUT: i32 = 2;
+
+    /// Exit code of the valid problem that has no path, e.g. a disconnected point.
+    pub const EXIT_UNSOLVABLE: i32 = 3;
+
+    /// Exit code of the failed reading or writing of the files.
+    pub const EXIT_IO: i32 = 4;
+
+    /// Process exit code for the error, so that scripts can tell the failure categories apart.
+    pub fn exit_code(&self) -> i32 {
+        use cost_generator::{MatrixParseError, ParamsParseError};
 
-    let path: Vec<formatter::Point> = result
-        .get_path()
+        match self {
+            Error::UnableToFindPath
+            | Error::OutOfBatteryRange(_)
+            | Error::Disconnected { .. }
+            | Error::BlockedByNoFlyZones { .. }
+            | Error::ExtremeEdge { .. } => Self::EXIT_UNSOLVABLE,
+            Error::ParamsParse(ParamsParseError::IO(_))
+            | Error::ParamsParse(ParamsParseError::IoWithPath { .. })
+            | Error::ParamsParse(ParamsParseError::CoordsIo { .. })
+            | Error::MatrixParse(MatrixParseError::IO(_)) => Self::EXIT_IO,
+            #[cfg(feature = "text")]
+            Error::Output(formatter::Error::Unsupported(_)) => Self::EXIT_INVALID_INPUT,
+            #[cfg(feature = "text")]
+            Error::Output(_) => Self::EXIT_IO,
+            #[cfg(feature = "json")]
+            Error::Cache(_) => Self::EXIT_IO,
+            Error::NonSquareMatrix(..)
+            | Error::NegativeCost { .. }
+            | Error::TooFewPoints(_)
+            | Error::UnsupportedConstraints
+            | Error::UnsupportedTieBreaks
+            | Error::OptionalPointsWithMultiplePaths
+            | Error::ConstraintsWithDrones
+            | Error::DronesWithMultipleFiles
+            | Error::DuplicateCoords(_)
+            | Error::NanCost { .. }
+            | Error::TooManyPointsForHeldKarp(_)
+            | Error::ParamsParse(_)
+            | Error::MatrixParse(_) => Self::EXIT_INVALID_INPUT,
+            Error::BatchFailed { .. } => Self::EXIT_FAILURE,
+        }
+    }
+}
+
+/// Formats the zero-based index pairs as the one-based point numbers.
+fn format_pairs(pairs: &[(usize, usize)]) -> String {
+    pairs
         .iter()
-        .enumerate()
-        .map(|(i, p)| (i, p.from(), p.to()))
-        .flat_map(|(i, from, to)| {
-            let coord = params.coords[from];
-            let p_from = once_with(move || formatter::Point {
-                point_index: from,
-                x: coord.x,
-                y: coord.y,
-                z: coord.z,
-            });
-            let coord = params.coords[to];
-            let p_to = once_with(move || formatter::Point {
-                point_index: to,
-                x: coord.x,
-                y: coord.y,
-                z: coord.z,
-            });
-
-            p_from.chain(p_to).skip(if i == 0 { 0 } else { 1 })
-        })
-        .collect();
+        .map(|(i, j)| format!("{} and {}", i + 1, j + 1))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Planned route of the drone. The path, the edge costs, the totals and the stats
+/// are serialized the same as in the JSON output of the command line tool, so
+/// the output can be deserialized as a plan. The search result and the cost
+/// matrices aren't serialized, they're empty in a deserialized plan.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// Version of the JSON format, see [`route::SCHEMA_VERSION`].
+    pub schema_version: u32,
+
+    /// Path found for the cost matrix of the optimized parameter. Hovering
+    /// at the points is included into its cost only if some points are optional.
+    #[serde(skip)]
+    pub result: FindResult,
+
+    /// Cost matrices generated from the drone parameters.
+    #[serde(skip)]
+    pub cost_matrices: CostMatrices,
+
+    /// Visited points in order, the first point is repeated at the end.
+    pub path: Vec<route::Point>,
+
+    /// Costs of each edge along the path.
+    pub edges: Vec<route::EdgeCost>,
+
+    /// Total costs of the edges and of hovering at the visited points.
+    pub energy_cost: f64,
+    pub time_cost: f64,
+    pub capacity_cost: f64,
+    pub distance_cost: f64,
+
+    /// Whether the path is provably optimal.
+    #[serde(default)]
+    pub optimality: Optimality,
+
+    /// Statistics of the path search.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<route::Stats>,
+}
+
+impl<'a> IntoIterator for &'a Plan {
+    type Item = &'a route::Point;
+    type IntoIter = std::slice::Iter<'a, route::Point>;
+
+    /// Iterates over the visited points in order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.path.iter()
+    }
+}
+
+impl Plan {
+    /// Plan of the `result` path in the `cost_matrices` of the params coordinates.
+    pub(crate) fn new(params: &Params, result: FindResult, cost_matrices: CostMatrices) -> Plan {
+        let path: Vec<route::Point> = tour_vertices(result.get_path())
+            .into_iter()
+            .map(|i| make_point(params, i))
+            .collect();
+        let edges: Vec<route::EdgeCost> = result
+            .get_path()
+            .iter()
+            .map(|e| {
+                let p = (e.from(), e.to());
+                route::EdgeCost {
+                    from: e.from(),
+                    to: e.to(),
+                    energy: cost_matrices.energy[p],
+                    time: cost_matrices.time[p],
+                    capacity: cost_matrices.capacity[p],
+                    distance: cost_matrices.distance[p],
+                }
+            })
+            .collect();
+        let (energy_cost, time_cost, capacity_cost, distance_cost) =
+            path_totals(params, &edges, &path);
+
+        Plan {
+            schema_version: route::SCHEMA_VERSION,
+            stats: Some(route_stats(result.stats())),
+            optimality: result.optimality(),
+            result,
+            cost_matrices,
+            path,
+            edges,
+            energy_cost,
+            time_cost,
+            capacity_cost,
+            distance_cost,
+        }
+    }
+
+    /// Iterates over the visited points in order along with their indices
+    /// in the params coordinates. The first point is repeated at the end,
+    /// so a closed tour of *N* points yields *N + 1* items.
+    pub fn points(&self) -> impl Iterator<Item = (usize, &route::Point)> {
+        self.path.iter().map(|p| (p.point_index, p))
+    }
+}
+
+/// Plans the optimal route of the drone by the specified parameter.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "json")]
+/// # {
+/// use navigation::config::Optimize;
+/// use navigation::Params;
+///
+/// let params: Params = r#"
+///     {
+///         "battery_voltage": 22.8,
+///         "speed_horizontal": 12.5,
+///         "speed_up": 3.1,
+///         "speed_down": 3,
+///         "power_horizontal": 486.2,
+///         "power_up": 899.04,
+///         "power_down": 309.17,
+///         "power_hover": 545.8,
+///         "hover_time": 0,
+///         "coords": [
+///             {"x": 0, "y": 0, "z": 0},
+///             {"x": 10, "y": 200, "z": 300},
+///             {"x": 200, "y": 450, "z": 12}
+///         ]
+///     }
+/// "#.parse().unwrap();
+///
+/// let plan = navigation::plan(&params, Optimize::Energy).unwrap();
+/// assert_eq!(4, plan.path.len());
+/// assert_eq!(plan.path.first(), plan.path.last());
+/// println!("Energy: {:.3} kJ", plan.result.get_cost());
+/// # }
+/// ```
+pub fn plan(params: &Params, optimize: Optimize) -> Result<Plan, Error> {
+    let snapped;
+    let params = match params.snap_grid {
+        Some(grid) if !params.is_snapped() => {
+            snapped = params.clone().snap_to_grid(grid)?;
+            &snapped
+        }
+        _ => params,
+    };
+
+    plan_with(params, optimize, |cost| {
+        path_finder::find_with_options(cost, &search_constraints(params))
+            .ok_or(Error::UnableToFindPath)
+    })
+}
+
+/// Order of the params coordinates in the searched cost matrix. The search
+/// always starts at vertex 0, so the depot goes first.
+fn search_order(params: &Params) -> Vec<usize> {
+    once(params.depot)
+        .chain((0..params.coords.len()).filter(|&v| v != params.depot))
+        .collect()
+}
+
+/// Cost matrix with the vertices reordered, vertex `i` is `order[i]` of `cost`.
+fn reorder(cost: &DMatrix<f64>, order: &[usize]) -> DMatrix<f64> {
+    DMatrix::from_fn(order.len(), order.len(), |i, j| cost[(order[i], order[j])])
+}
+
+/// Search options with the visiting constraints of the params,
+/// the vertices are in the [`search_order`].
+fn search_constraints(params: &Params) -> SearchOptions {
+    let order = search_order(params);
+    let index = |v: usize| order.iter().position(|&o| o == v).unwrap();
+    SearchOptions {
+        precedences: params
+            .precedences
+            .iter()
+            .map(|&(a, b)| (index(a), index(b)))
+            .collect(),
+        required: params
+            .required
+            .as_ref()
+            .map(|r| r.iter().map(|&v| index(v)).collect()),
+        ..SearchOptions::default()
+    }
+}
+
+/// Plans the route of the drone by the specified parameter,
+/// finding the path in the cost matrix with the `find` function.
+/// The vertices of the matrix are reordered so that the depot is vertex 0,
+/// the found path is mapped back to the params coordinates.
+pub fn plan_with<F>(params: &Params, optimize: Optimize, find: F) -> Result<Plan, Error>
+where
+    F: FnOnce(&DMatrix<f64>) -> Result<FindResult, Error>,
+{
+    plan_with_matrices(params, CostMatrices::generate(params), optimize, find)
+}
+
+/// Same as [`plan_with`], but uses already generated cost matrices.
+pub fn plan_with_matrices<F>(
+    params: &Params,
+    cost_matrices: CostMatrices,
+    optimize: Optimize,
+    find: F,
+) -> Result<Plan, Error>
+where
+    F: FnOnce(&DMatrix<f64>) -> Result<FindResult, Error>,
+{
+    let mut cost_matrices = cost_matrices;
+    cost_matrices.replace_nan();
+    // Skipping an optional point saves its hovering, so the search needs
+    // it on the edges; it's constant for the tours visiting all the points
+    let with_hover;
+    let cost_matrix = match params.required {
+        Some(_) => {
+            with_hover = with_hover_costs(cost_matrices.get(optimize), params, optimize);
+            &with_hover
+        }
+        None => cost_matrices.get(optimize),
+    };
+    check_non_negative(cost_matrix)?;
+    // Disconnected optional points are just never visited
+    let disconnected = (0..cost_matrix.nrows())
+        .find(|&v| params.is_required(v) && path_finder::is_disconnected(cost_matrix, v));
+    match disconnected {
+        Some(vertex) if params.has_no_fly_zones() => {
+            return Err(Error::BlockedByNoFlyZones { vertex })
+        }
+        Some(vertex) => return Err(Error::Disconnected { vertex }),
+        None => (),
+    }
+    let order = search_order(params);
+    let result = find(&reorder(cost_matrix, &order))?.map_vertices(&order);
 
-    let (energy_cost, time_cost, capacity_cost) = result
-        .get_path()
+    Ok(Plan::new(params, result, cost_matrices))
+}
+
+/// Hovering cost at the point `i` by the optimized parameter.
+fn hover_cost_at(params: &Params, optimize: Optimize, i: usize) -> f64 {
+    match optimize {
+        Optimize::Intuitive => 0.0,
+        Optimize::Time => params.hover_time_at(i),
+        Optimize::Battery => params.hover_capacity_at(i),
+        Optimize::Energy => params.hover_energy_at(i),
+    }
+}
+
+/// Adds hovering at the destination point to each edge of the cost matrix.
+fn with_hover_costs(cost: &DMatrix<f64>, params: &Params, optimize: Optimize) -> DMatrix<f64> {
+    DMatrix::from_fn(cost.nrows(), cost.ncols(), |i, j| {
+        cost[(i, j)] + hover_cost_at(params, optimize, j)
+    })
+}
+
+fn make_point(params: &Params, i: usize) -> route::Point {
+    let coord = params.coords[i];
+    route::Point {
+        point_index: i,
+        label: params.label_at(i).map(String::from),
+        x: coord.x,
+        y: coord.y,
+        z: coord.z,
+        merged: Some(params.merged_at(i)).filter(|&m| m > 1),
+    }
+}
+
+/// Solves the traveling salesman problem for a precomputed cost matrix,
+/// bypassing the cost generation. The diagonal is treated as infinity.
+pub fn solve_matrix(cost: &DMatrix<f64>) -> Result<FindResult, Error> {
+    let cost = prepare_matrix(cost)?;
+
+    path_finder::find(&cost).ok_or(Error::UnableToFindPath)
+}
+
+fn prepare_matrix(cost: &DMatrix<f64>) -> Result<DMatrix<f64>, Error> {
+    if !cost.is_square() {
+        return Err(Error::NonSquareMatrix(cost.nrows(), cost.ncols()));
+    }
+    if cost.nrows() < path_finder::MIN_VERTICES {
+        return Err(Error::TooFewPoints(cost.nrows()));
+    }
+    let mut cost = cost.clone();
+    cost.fill_diagonal(f64::INFINITY);
+    check_non_negative(&cost)?;
+    check_connected(&cost)?;
+
+    Ok(cost)
+}
+
+fn check_non_negative(cost: &DMatrix<f64>) -> Result<(), Error> {
+    match path_finder::find_negative(cost) {
+        Some((from, to)) if cost[(from, to)].is_nan() => Err(Error::NanCost { from, to }),
+        Some((from, to)) => Err(Error::NegativeCost { from, to }),
+        None => Ok(()),
+    }
+}
+
+fn check_connected(cost: &DMatrix<f64>) -> Result<(), Error> {
+    match path_finder::find_disconnected(cost) {
+        Some(vertex) => Err(Error::Disconnected { vertex }),
+        None => Ok(()),
+    }
+}
+
+/// Total energy, time, capacity and distance of the `edges`
+/// with hovering at the points of the `path`.
+fn path_totals(
+    params: &Params,
+    edges: &[route::EdgeCost],
+    path: &[route::Point],
+) -> (f64, f64, f64, f64) {
+    // Hovering is paid once at each visited point, the start point included
+    let hover = path.iter().skip(1).fold((0.0, 0.0, 0.0), |sum, p| {
+        (
+            sum.0 + params.hover_energy_at(p.point_index),
+            sum.1 + params.hover_time_at(p.point_index),
+            sum.2 + params.hover_capacity_at(p.point_index),
+        )
+    });
+    edges
         .iter()
-        .map(|p| (p.from(), p.to()))
-        .fold((0.0, 0.0, 0.0), |sum, p| {
+        .fold((hover.0, hover.1, hover.2, 0.0), |sum, e| {
             (
-                sum.0 + cost_matrices.energy[p],
-                sum.1 + cost_matrices.time[p],
-                sum.2 + cost_matrices.capacity[p],
+                sum.0 + e.energy,
+                sum.1 + e.time,
+                sum.2 + e.capacity,
+                sum.3 + e.distance,
             )
+        })
+}
+
+fn route_stats(stats: &Stats) -> route::Stats {
+    route::Stats {
+        nodes_expanded: stats.nodes_expanded,
+        nodes_pruned: stats.nodes_pruned,
+        peak_queue_len: stats.peak_queue_len,
+        mirrors_pruned: stats.mirrors_pruned,
+        restarts: stats.restarts,
+        elapsed: stats.elapsed.as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[rustfmt::skip]
+    fn solve_matrix_test() {
+        let cost = DMatrix::from_vec(3, 3, vec![
+            0.0, 1.0, 5.0,
+            5.0, 0.0, 1.0,
+            1.0, 5.0, 0.0,
+        ]).transpose();
+
+        let result = solve_matrix(&cost).unwrap();
+        assert!(approx_eq!(f64, 3.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+        let path: Vec<(usize, usize)> = result
+            .get_path()
+            .iter()
+            .map(|p| (p.from(), p.to()))
+            .collect();
+        assert_eq!(vec![(0, 1), (1, 2), (2, 0)], path);
+    }
+
+    /// The solver and the cost model are usable without the formatters.
+    #[test]
+    #[cfg(not(feature = "text"))]
+    #[rustfmt::skip]
+    fn core_without_formatters_test() {
+        let cost = DMatrix::from_vec(4, 4, vec![
+            0.0, 2.0, 9.0, 1.0,
+            1.0, 0.0, 6.0, 4.0,
+            9.0, 7.0, 0.0, 8.0,
+            6.0, 3.0, 1.0, 0.0,
+        ]).transpose();
+
+        let result = path_finder::find(&cost).unwrap();
+        assert!(approx_eq!(f64, 10.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+        assert_eq!(4, result.get_path().len());
+        assert_eq!(result.get_cost(), solve_matrix(&cost).unwrap().get_cost());
+
+        // The planning doesn't depend on the formatters either
+        use crate::cost_generator::Point;
+        let coords = [(0.0, 0.0, 0.0), (10.0, 200.0, 300.0), (200.0, 450.0, 12.0)];
+        let params = Params {
+            battery_voltage: 22.8,
+            speed_horizontal: 12.5,
+            speed_up: 3.1,
+            speed_down: 3.0,
+            power_horizontal: 486.2,
+            power_up: 899.04,
+            power_down: 309.17,
+            power_hover: 545.8,
+            coords: coords.iter().map(|&(x, y, z)| Point { x, y, z }).collect(),
+            ..Params::default()
+        };
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        assert_eq!(4, plan.path.len());
+        assert_eq!(3, plan.edges.len());
+        let fleet = fleet::plan_fleet(&params, Optimize::Energy, 2).unwrap();
+        assert_eq!(2, fleet.plans.len());
+    }
+
+    #[test]
+    fn solve_non_square_matrix_test() {
+        let cost = DMatrix::repeat(2, 3, 1.0);
+        match solve_matrix(&cost) {
+            Err(Error::NonSquareMatrix(2, 3)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_negative_cost_matrix_test() {
+        let mut cost = DMatrix::repeat(3, 3, 1.0);
+        cost[(0, 2)] = -0.5;
+        match solve_matrix(&cost) {
+            Err(e @ Error::NegativeCost { from: 0, to: 2 }) => {
+                assert_eq!("Cost from point 1 to point 3 is negative", e.to_string());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn solve_non_finite_cost_matrix_test() {
+        use crate::cost_generator::matrix_from_str;
+
+        let cost = matrix_from_str(r#"[[null, 1, "-inf"], [1, null, 1], [1, 1, null]]"#).unwrap();
+        match solve_matrix(&cost) {
+            Err(Error::NegativeCost { from: 0, to: 2 }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let cost = matrix_from_str(r#"[[null, 1, 1], [1, null, "nan"], [1, 1, null]]"#).unwrap();
+        match solve_matrix(&cost) {
+            Err(Error::NanCost { from: 1, to: 2 }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn solve_too_small_matrix_test() {
+        for n in 0..2 {
+            let cost = DMatrix::repeat(n, n, 1.0);
+            match solve_matrix(&cost) {
+                Err(e @ Error::TooFewPoints(_)) => {
+                    assert_eq!(
+                        format!("At least 2 points are required, got {}", n),
+                        e.to_string()
+                    );
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn disconnected_matrix_test() {
+        let inf = f64::INFINITY;
+        let cost = DMatrix::from_vec(4, 4, vec![
+            0.0, 1.0, inf, 4.0,
+            1.0, 0.0, inf, 2.0,
+            inf, inf, 0.0, inf,
+            4.0, 2.0, inf, 0.0,
+        ]).transpose();
+        match solve_matrix(&cost) {
+            Err(Error::Disconnected { vertex: 2 }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Vertex 2 can be left but not reached
+        let mut cost = cost;
+        cost[(2, 0)] = 1.0;
+        match solve_matrix(&cost) {
+            Err(Error::Disconnected { vertex: 2 }) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn no_fly_zones_test() {
+        let s = r#"
+        {
+            "battery_voltage": 22.8,
+            "speed_horizontal": 12.5,
+            "speed_up": 3.1,
+            "speed_down": 3,
+            "power_horizontal": 486.2,
+            "power_up": 899.04,
+            "power_down": 309.17,
+            "power_hover": 545.8,
+            "coords": [
+                {"x": 0, "y": 0, "z": 0},
+                {"x": 100, "y": 0, "z": 0},
+                {"x": 50, "y": 200, "z": 0}
+            ],
+            "no_fly_zones": [{"x_min": 40, "y_min": 190, "x_max": 60, "y_max": 210}]
+        }
+        "#;
+        let params: Params = s.parse().unwrap();
+        match plan(&params, Optimize::Energy) {
+            Err(Error::BlockedByNoFlyZones { vertex: 2 }) => (),
+            other => panic!("unexpected result: {:?}", other.map(|p| p.path)),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn keep_out_test() {
+        let s = r#"
+        {
+            "battery_voltage": 22.8,
+            "speed_horizontal": 12.5,
+            "speed_up": 3.1,
+            "speed_down": 3,
+            "power_horizontal": 486.2,
+            "power_up": 899.04,
+            "power_down": 309.17,
+            "power_hover": 545.8,
+            "coords": [
+                {"x": 0, "y": 0, "z": 0},
+                {"x": 100, "y": 0, "z": 0},
+                {"x": 100, "y": 100, "z": 0},
+                {"x": 0, "y": 100, "z": 0}
+            ]
+        }
+        "#;
+        let has_edge = |plan: &Plan, a, b| {
+            plan.result
+                .get_path()
+                .iter()
+                .any(|e| (e.from(), e.to()) == (a, b) || (e.from(), e.to()) == (b, a))
+        };
+
+        let mut params: Params = s.parse().unwrap();
+        let direct = plan(&params, Optimize::Intuitive).unwrap();
+        assert!(has_edge(&direct, 0, 1));
+
+        // The tower between the first two points forces the diagonals
+        params.keep_out.push(cost_generator::Circle {
+            x: 50.0,
+            y: 0.0,
+            radius: 10.0,
         });
+        let around = plan(&params, Optimize::Intuitive).unwrap();
+        assert!(!has_edge(&around, 0, 1));
+        assert_eq!(4, around.result.get_path().len());
+        assert!(around.result.get_cost() > direct.result.get_cost());
+    }
 
-    let out_data = formatter::OutputData {
-        energy: formatter::Matrix(&cost_matrices.energy),
-        time: formatter::Matrix(&cost_matrices.time),
-        capacity: formatter::Matrix(&cost_matrices.capacity),
-        path,
-        energy_cost,
-        time_cost,
-        capacity_cost,
-    };
+    #[test]
+    #[cfg(feature = "json")]
+    fn snap_grid_test() {
+        let s = r#"
+        {
+            "battery_voltage": 22.8,
+            "speed_horizontal": 12.5,
+            "speed_up": 3.1,
+            "speed_down": 3,
+            "power_horizontal": 486.2,
+            "power_up": 899.04,
+            "power_down": 309.17,
+            "power_hover": 545.8,
+            "coords": [
+                {"x": 0, "y": 0, "z": 0},
+                {"x": 100, "y": 0, "z": 0},
+                {"x": 100.1, "y": 0, "z": 0},
+                {"x": 100, "y": 100, "z": 0}
+            ],
+            "snap_grid": 1.0
+        }
+        "#;
+        let params: Params = s.parse().unwrap();
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        // 3 points and the return to the start
+        assert_eq!(4, plan.path.len());
+        let merged = plan.path.iter().find(|p| p.merged.is_some()).unwrap();
+        assert_eq!(Some(2), merged.merged);
+        assert_eq!("2: (100, 0, 0) [2 points merged]", merged.to_string());
+        let json = serde_json::to_value(merged).unwrap();
+        assert_eq!(2, json["merged"]);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn plan_points_test() {
+        let params = Params::from_file("params.json").unwrap();
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        let points: Vec<(usize, &route::Point)> = plan.points().collect();
+
+        assert_eq!(params.coords.len() + 1, points.len());
+        assert_eq!(points.first().map(|p| p.0), points.last().map(|p| p.0));
+        let indices: Vec<usize> = points.iter().map(|&(i, _)| i).collect();
+        assert_eq!(tour_vertices(plan.result.get_path()), indices);
+        for (i, p) in points {
+            assert_eq!(params.coords[i].x, p.x);
+            assert_eq!(params.coords[i].y, p.y);
+            assert_eq!(params.coords[i].z, p.z);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn plan_nan_costs_test() {
+        // The edges between points 2 and 3 have NaN costs, they are excluded
+        let params = Params::from_file("nan_params.json").unwrap();
+        let plan = plan(&params, Optimize::Energy).unwrap();
+        assert!(plan.result.get_cost().is_finite());
+        assert!(plan.cost_matrices.energy[(1, 2)].is_infinite());
+
+        let fleet = fleet::plan_fleet(&params, Optimize::Energy, 1).unwrap();
+        assert!(fleet.makespan.is_finite());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn labels_output_test() {
+        let s = r#"
+        {
+            "battery_voltage": 22.8,
+            "speed_horizontal": 12.5,
+            "speed_up": 3.1,
+            "speed_down": 3,
+            "power_horizontal": 486.2,
+            "power_up": 899.04,
+            "power_down": 309.17,
+            "power_hover": 545.8,
+            "hover_time": 0,
+            "coords": [
+                {"x": 0, "y": 0, "z": 0},
+                {"x": 10, "y": 200, "z": 300},
+                {"x": 200, "y": 450, "z": 12}
+            ],
+            "labels": ["Base", "Tower A", "Tower B"]
+        }
+        "#;
+        let params: Params = s.parse().unwrap();
+        let plan = plan(&params, Optimize::Energy).unwrap();
 
-    match (config.out_as_json, config.out_filename.map(File::create)) {
-        (true, Some(file)) => out_data.to_writer(file?, JsonFormatter),
-        (false, Some(file)) => out_data.to_writer(file?, TextFormatter),
-        (true, None) => out_data.to_writer(stdout(), JsonFormatter),
-        (false, None) => out_data.to_writer(stdout(), TextFormatter),
-    }?;
+        let json = serde_json::to_value(&plan.path).unwrap();
+        for (p, v) in plan.path.iter().zip(json.as_array().unwrap()) {
+            let label = params.labels.as_ref().unwrap()[p.point_index].as_str();
+            assert_eq!(label, v["label"]);
+        }
+        assert_eq!("1 Base: (0, 0, 0)", plan.path[0].to_string());
 
-    Ok(())
+        match s
+            .replace(r#""Tower B""#, "")
+            .replace(", ]", "]")
+            .parse::<Params>()
+        {
+            Err(cost_generator::ParamsParseError::LabelsLength { expected, actual }) => {
+                assert_eq!((3, 2), (expected, actual));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
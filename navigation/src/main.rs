@@ -6,7 +6,7 @@ fn main() {
         Ok(_) => 0,
         Err(e) => {
             eprintln!("{}", e);
-            -1
+            e.exit_code()
         }
     })
 }
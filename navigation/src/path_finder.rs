@@ -2,16 +2,50 @@ extern crate min_max_heap;
 extern crate nalgebra;
 extern crate ordered_float;
 
+use crate::cost_generator::CostMatrices;
 use min_max_heap::MinMaxHeap;
-use nalgebra::DMatrix;
+use nalgebra::{DMatrix, Scalar};
 use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
-use std::ops::AddAssign;
+use std::fmt::{self, Display, Formatter};
+use std::ops::{AddAssign, SubAssign};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
+/// Whether the found tour is provably optimal.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Optimality {
+    /// The search was complete, no cheaper tour exists.
+    Exact,
+
+    /// The tour was found by a heuristic or the search dropped some
+    /// of the nodes, so a cheaper tour may exist.
+    #[default]
+    Heuristic,
+
+    /// The search was stopped by the timeout before proving the tour optimal.
+    Timeout,
+}
+
+impl Display for Optimality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Optimality::Exact => "exact",
+            Optimality::Heuristic => "heuristic",
+            Optimality::Timeout => "timeout",
+        })
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct FindResult {
     cost: f64,
     path: Vec<PathEdge>,
+    optimality: Optimality,
+    stats: Stats,
 }
 
 #[allow(dead_code)]
@@ -23,10 +57,103 @@ impl FindResult {
     pub fn get_path(&self) -> &Vec<PathEdge> {
         &self.path
     }
+
+    /// Visited vertices in order, the starting vertex is repeated at the end,
+    /// e.g. `[0, 2, 4, 5, 3, 1, 0]`.
+    pub fn order(&self) -> Vec<usize> {
+        tour_vertices(&self.path)
+    }
+
+    /// Returns `false` if the search was stopped early or the path
+    /// was found by the heuristic, so it may be not optimal.
+    pub fn is_optimal(&self) -> bool {
+        self.optimality == Optimality::Exact
+    }
+
+    /// Whether the path is provably optimal, or why it may be not.
+    pub fn optimality(&self) -> Optimality {
+        self.optimality
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Total energy, time, capacity and distance of the path edges
+    /// by the `matrices`, whichever of them was optimized. Hovering
+    /// at the points isn't included.
+    pub fn costs_against(&self, matrices: &CostMatrices) -> (f64, f64, f64, f64) {
+        self.path
+            .iter()
+            .map(|e| (e.0, e.1))
+            .fold((0.0, 0.0, 0.0, 0.0), |sum, p| {
+                (
+                    sum.0 + matrices.energy[p],
+                    sum.1 + matrices.time[p],
+                    sum.2 + matrices.capacity[p],
+                    sum.3 + matrices.distance[p],
+                )
+            })
+    }
+
+    /// Cost of traversing the path in reverse by the `cost` matrix.
+    /// It's the same as the path cost for a symmetric matrix.
+    pub fn reverse_cost(&self, cost: &DMatrix<f64>) -> f64 {
+        self.path.iter().map(|e| cost[(e.1, e.0)]).sum()
+    }
+
+    /// Renumbers the path vertices, the vertex `i` becomes `vertices[i]`.
+    /// Used to map the path in a submatrix back to the full cost matrix.
+    pub(crate) fn map_vertices(mut self, vertices: &[usize]) -> FindResult {
+        for edge in &mut self.path {
+            *edge = PathEdge(vertices[edge.0], vertices[edge.1]);
+        }
+        self
+    }
+}
+
+/// Statistics of the branch-and-bound search.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Number of nodes taken from the queue.
+    pub nodes_expanded: usize,
+
+    /// Number of nodes discarded by the upper bound or evicted from the queue.
+    pub nodes_pruned: usize,
+
+    /// Maximum number of nodes in the queue.
+    pub peak_queue_len: usize,
+
+    /// Number of nodes discarded as mirror images of other tours,
+    /// non-zero only for a symmetric cost matrix.
+    pub mirrors_pruned: usize,
+
+    /// Current number of nodes in the queue.
+    pub queue_len: usize,
+
+    /// Cost of the best complete tour known so far.
+    pub best_cost: Option<f64>,
+
+    /// Number of the heuristic runs from random start vertices,
+    /// non-zero only for [`random_restarts`].
+    pub restarts: usize,
+
+    /// Search duration.
+    pub elapsed: Duration,
+}
+
+/// Converts the path edges into the visited vertices,
+/// the first vertex is repeated at the end.
+pub fn tour_vertices(path: &[PathEdge]) -> Vec<usize> {
+    path.iter()
+        .take(1)
+        .map(PathEdge::from)
+        .chain(path.iter().map(PathEdge::to))
+        .collect()
 }
 
 /// Contains cost matrix indices vector *(from, to)*.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PathEdge(usize, usize);
 
 impl PathEdge {
@@ -42,105 +169,240 @@ impl PathEdge {
 #[derive(Debug)]
 /// A an object that corresponds to visiting vertex *j* from vertex *i*
 /// and contains the data calculated at this step.
-struct Node {
+struct Node<T: ReducedCost = f64> {
     path: Vec<PathEdge>,
-    reduced_matrix: DMatrix<f64>,
+    /// Reduced matrix of the parent node, shared by all its children,
+    /// or the cost matrix for the root node. The node's own reduced matrix
+    /// is only built when the node is expanded, so the queued nodes don't
+    /// keep a copy of the matrix each.
+    parent_matrix: Rc<DMatrix<T>>,
     cost: NodePriority,
     vertex: usize,
     level: usize,
 }
 
-impl Ord for Node {
+/// Nodes are ordered by the lower bound of the cost. Ties are broken
+/// by the level, the deeper node goes first, and then by the path
+/// in lexicographic order, so the search is deterministic and always
+/// returns the same tour among equally optimal ones.
+impl<T: ReducedCost> Ord for Node<T> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.cost.cmp(&other.cost)
+        self.cost
+            .cmp(&other.cost)
+            .then_with(|| other.level.cmp(&self.level))
+            .then_with(|| self.path.cmp(&other.path))
     }
 }
 
-impl PartialOrd for Node {
+impl<T: ReducedCost> PartialOrd for Node<T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cost.cmp(&other.cost))
+        Some(self.cmp(other))
     }
 }
 
-impl Eq for Node {}
+impl<T: ReducedCost> Eq for Node<T> {}
 
-impl PartialEq for Node {
+impl<T: ReducedCost> PartialEq for Node<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.cost == other.cost
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl Node {
-    fn new(
-        reduced_matrix: DMatrix<f64>,
-        level: usize,
-        i: usize,
-        j: usize,
-        path: Vec<PathEdge>,
-    ) -> Node {
-        let mut path = path.to_vec();
-        // Add current edge to path. Skip for root node
-        if level != 0 {
-            path.push(PathEdge(i, j));
-        }
+impl<T: ReducedCost> Node<T> {
+    /// Creates the node of the starting vertex 0.
+    fn root(cost: DMatrix<T>) -> Node<T> {
+        let cost = Rc::new(cost);
+        let bound = Self::reduction_cost(&cost, None, 0);
 
-        let mut reduced_matrix = reduced_matrix;
-        // Change all entries of row i and column j to infinity.
-        // Skip root node
-        if level != 0 {
-            reduced_matrix.fill_row(i, f64::INFINITY.into());
-            reduced_matrix.fill_column(j, f64::INFINITY.into());
+        Node {
+            path: Vec::new(),
+            parent_matrix: cost,
+            cost: bound.into(),
+            vertex: 0,
+            level: 0,
         }
+    }
 
-        // Start node is 0
-        reduced_matrix[(j, 0)] = f64::INFINITY.into();
-        let cost = Self::calculate_expected_cost(&mut reduced_matrix).into();
+    /// Creates the node of visiting vertex `j` from the current one,
+    /// `matrix` is the reduced matrix of the current node.
+    fn child(&self, matrix: &Rc<DMatrix<T>>, j: usize) -> Node<T> {
+        let i = self.vertex;
+        let mut path = self.path.to_vec();
+        // Add current edge to path
+        path.push(PathEdge(i, j));
+
+        let mut cost = NodePriority::new(Self::reduction_cost(matrix, Some(PathEdge(i, j)), j));
+        cost += f64::from(self.cost) + matrix[(i, j)].into();
 
         Node {
             path,
-            reduced_matrix,
+            parent_matrix: Rc::clone(matrix),
             cost,
             vertex: j,
-            level,
+            level: self.level + 1,
+        }
+    }
+
+    /// Builds the reduced matrix of the node from the parent's one.
+    fn reduced_matrix(&self) -> DMatrix<T> {
+        let mut reduced_matrix = (*self.parent_matrix).clone();
+        // Change all entries of row i and column j to infinity.
+        // Skip root node
+        if let Some(&PathEdge(i, j)) = self.path.last() {
+            reduced_matrix.fill_row(i, T::INFINITY);
+            reduced_matrix.fill_column(j, T::INFINITY);
+        }
+
+        // Start node is 0
+        reduced_matrix[(self.vertex, 0)] = T::INFINITY;
+        Self::calculate_expected_cost(&mut reduced_matrix);
+        reduced_matrix
+    }
+
+    /// Creates the nodes of visiting each vertex reachable from the current one,
+    /// skipping the vertices whose predecessors by `precedences` aren't visited yet.
+    fn children<'a>(
+        &'a self,
+        precedences: &'a [(usize, usize)],
+    ) -> impl Iterator<Item = Node<T>> + 'a {
+        let i = self.vertex;
+        let matrix = Rc::new(self.reduced_matrix());
+
+        (0..matrix.ncols()).filter_map(move |j| {
+            if matrix[(i, j)] == T::INFINITY || !self.can_visit(j, precedences) {
+                return None;
+            }
+
+            Some(self.child(&matrix, j))
+        })
+    }
+
+    /// Checks whether the tour can only end at a vertex less than the first
+    /// visited one. Such a tour is the reverse of a tour that ends at a greater
+    /// vertex, which has the same cost if the cost matrix is symmetric.
+    fn is_mirrored(&self) -> bool {
+        let first = match self.path.first() {
+            Some(e) => e.to(),
+            None => return false,
+        };
+        let n = self.parent_matrix.nrows();
+        let last_candidate = (1..n)
+            .rev()
+            .find(|&v| !self.path.iter().any(|e| e.to() == v))
+            .unwrap_or(self.vertex);
+
+        last_candidate < first
+    }
+
+    /// Checks that all the vertices which must precede `j` are already visited.
+    fn can_visit(&self, j: usize, precedences: &[(usize, usize)]) -> bool {
+        precedences
+            .iter()
+            .filter(|&&(_, b)| b == j)
+            .all(|&(a, _)| a == 0 || self.path.iter().any(|e| e.to() == a))
+    }
+
+    fn min(costs: impl Iterator<Item = T>) -> T {
+        costs.fold(T::INFINITY, |min, c| if c < min { c } else { min })
+    }
+
+    /// Subtracts the row/column minimum `min` from the element of this row/column.
+    fn subtract(f: &mut T, min: T) {
+        if *f != T::INFINITY && min != T::INFINITY {
+            *f -= min
         }
     }
 
+    /// The total expected cost is the sum of all reductions.
+    fn sum_reductions(row_min_elems: &[T], col_min_elems: &[T]) -> f64 {
+        row_min_elems
+            .iter()
+            .zip(col_min_elems.iter())
+            .fold(0.0, |cost, (&row, &col)| {
+                let row = if row == T::INFINITY { 0.0 } else { row.into() };
+                let col = if col == T::INFINITY { 0.0 } else { col.into() };
+                cost + row + col
+            })
+    }
+
     /// Calculate the lower bound of the path starting at current min node.
-    fn calculate_expected_cost(reduced_matrix: &mut DMatrix<f64>) -> f64 {
+    fn calculate_expected_cost(reduced_matrix: &mut DMatrix<T>) -> f64 {
         // Subtracts the minimum element of each row/column
         // from each element of this row/column
-        let subtract = |f: &mut f64, min: f64| {
-            if *f != f64::INFINITY && min != f64::INFINITY {
-                *f -= min
-            }
-        };
         let row_min_elems = reduced_matrix
             .row_iter_mut()
             .map(|mut row| {
-                let min = row.min();
-                row.iter_mut().for_each(|f| subtract(f, min));
+                let min = Self::min(row.iter().copied());
+                row.iter_mut().for_each(|f| Self::subtract(f, min));
                 min
             })
-            .collect::<Vec<f64>>();
+            .collect::<Vec<T>>();
 
         let col_min_elems = reduced_matrix
             .column_iter_mut()
             .map(|mut col| {
-                let min = col.min();
-                col.iter_mut().for_each(|f| subtract(f, min));
+                let min = Self::min(col.iter().copied());
+                col.iter_mut().for_each(|f| Self::subtract(f, min));
                 min
             })
-            .collect::<Vec<f64>>();
+            .collect::<Vec<T>>();
 
-        // The total expected cost is the sum of all reductions
-        row_min_elems
-            .iter()
-            .zip(col_min_elems.iter())
-            .fold(0.0, |cost, (&row, &col)| {
-                let row = if row == f64::INFINITY { 0.0 } else { row };
-                let col = if col == f64::INFINITY { 0.0 } else { col };
-                cost + row + col
+        Self::sum_reductions(&row_min_elems, &col_min_elems)
+    }
+
+    /// Same as [`Node::calculate_expected_cost`] of the `matrix` with the row
+    /// and column of the `edge` and the way from `vertex` back to vertex 0
+    /// changed to infinity, but computed without copying the matrix.
+    fn reduction_cost(matrix: &DMatrix<T>, edge: Option<PathEdge>, vertex: usize) -> f64 {
+        let crossed_out = |r: usize, c: usize| {
+            edge.is_some_and(|e| e.0 == r || e.1 == c) || (r, c) == (vertex, 0)
+        };
+        let value = |r: usize, c: usize| {
+            if crossed_out(r, c) {
+                T::INFINITY
+            } else {
+                matrix[(r, c)]
+            }
+        };
+
+        let row_min_elems = (0..matrix.nrows())
+            .map(|r| Self::min((0..matrix.ncols()).map(|c| value(r, c))))
+            .collect::<Vec<T>>();
+        let col_min_elems = (0..matrix.ncols())
+            .map(|c| {
+                Self::min((0..matrix.nrows()).map(|r| {
+                    let mut f = value(r, c);
+                    Self::subtract(&mut f, row_min_elems[r]);
+                    f
+                }))
             })
+            .collect::<Vec<T>>();
+
+        Self::sum_reductions(&row_min_elems, &col_min_elems)
+    }
+}
+
+/// Element of the reduced matrices kept by the search nodes,
+/// see [`SearchOptions::single_precision`].
+trait ReducedCost: Scalar + Copy + PartialOrd + SubAssign + Into<f64> {
+    const INFINITY: Self;
+
+    fn from_f64(cost: f64) -> Self;
+}
+
+impl ReducedCost for f64 {
+    const INFINITY: f64 = f64::INFINITY;
+
+    fn from_f64(cost: f64) -> f64 {
+        cost
+    }
+}
+
+impl ReducedCost for f32 {
+    const INFINITY: f32 = f32::INFINITY;
+
+    fn from_f64(cost: f64) -> f32 {
+        cost as f32
     }
 }
 
@@ -159,9 +421,9 @@ impl From<f64> for NodePriority {
     }
 }
 
-impl Into<f64> for NodePriority {
-    fn into(self) -> f64 {
-        self.0.into_inner()
+impl From<NodePriority> for f64 {
+    fn from(p: NodePriority) -> Self {
+        p.0.into_inner()
     }
 }
 
@@ -171,143 +433,2190 @@ impl AddAssign<f64> for NodePriority {
     }
 }
 
-/// Solves the traveling salesman problem for a given cost matrix.
-pub fn find(cost: &DMatrix<f64>) -> Option<FindResult> {
-    let mut queue = MinMaxHeap::new();
-    let root = Node::new(cost.clone(), 0, 0, 0, Vec::new());
-    queue.push(root);
+/// Node of the branch-and-bound search on the way to a tour, see [`trace`].
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    /// Edge leading to the node, the root node has none.
+    pub edge: Option<PathEdge>,
 
-    // Finds node with least cost, add its children to list of
-    // nodes and finally deletes it from the list
+    /// Lower bound of the cost of the tours going through the node.
+    pub lower_bound: f64,
+
+    /// Cost matrix reduced by the node.
+    pub reduced_matrix: DMatrix<f64>,
+}
+
+/// Replays the branch-and-bound search along the `tour` and returns
+/// the nodes expanded on the way to it, from the root. The `tour` lists
+/// the vertices in the visiting order starting from vertex 0, the return
+/// to vertex 0 isn't a node of its own. So a tour of *N* vertices has
+/// *N* nodes, the last one bounded by the tour cost.
+pub fn trace(cost: &DMatrix<f64>, tour: &[usize]) -> Vec<TraceStep> {
+    let step = |node: &Node| TraceStep {
+        edge: node.path.last().copied(),
+        lower_bound: node.cost.into(),
+        reduced_matrix: node.reduced_matrix(),
+    };
+
+    let mut node = Node::root(cost.clone());
+    let mut steps = vec![step(&node)];
+    for &j in tour.iter().skip(1).filter(|&&j| j != 0) {
+        node = node.child(&Rc::new(node.reduced_matrix()), j);
+        steps.push(step(&node));
+    }
+
+    steps
+}
+
+/// Minimum number of vertices of the cost matrix accepted by the search.
+pub const MIN_VERTICES: usize = 2;
+
+/// Checks that the cost matrix is square, has at least [`MIN_VERTICES`]
+/// vertices and no negative costs. The search functions return `None`
+/// for other matrices instead of panicking or returning a wrong tour.
+pub fn is_valid_matrix(cost: &DMatrix<f64>) -> bool {
+    cost.is_square() && cost.nrows() >= MIN_VERTICES && find_negative(cost).is_none()
+}
+
+/// Returns the first edge *(i, j)* with a negative or NaN cost, only the positive
+/// infinity marks a missing edge. The lower bound of the search is the sum
+/// of the row and column minimums subtracted from the matrix, so all the costs
+/// must be non-negative for it to be valid.
+pub fn find_negative(cost: &DMatrix<f64>) -> Option<(usize, usize)> {
+    (0..cost.nrows())
+        .flat_map(|i| (0..cost.ncols()).map(move |j| (i, j)))
+        .find(|&p| cost[p].is_nan() || cost[p] < 0.0)
+}
+
+/// Default relative tolerance used when comparing tour costs,
+/// see [`SearchOptions::epsilon`].
+pub const EPSILON: f64 = 1e-9;
+
+/// Checks whether the cost is infinite or within the relative tolerance
+/// of the maximum finite value, so a tour using it is practically impossible.
+pub fn is_near_infinite(cost: f64) -> bool {
+    cost >= f64::MAX * (1.0 - EPSILON)
+}
+
+/// Builds a tour by greedily moving to the cheapest unvisited vertex,
+/// starting from vertex `start`. Returns `None` if the walk is stuck
+/// because only infinite edges are left.
+pub fn nearest_neighbor(cost: &DMatrix<f64>, start: usize) -> Option<FindResult> {
+    if !is_valid_matrix(cost) || start >= cost.nrows() {
+        return None;
+    }
     let n = cost.nrows();
-    while let Some(mut min) = queue.pop_min() {
-        let i = min.vertex;
-        // All vertex are visited
-        if min.level == n - 1 {
-            // Go back to starting vertex
-            min.path.push(PathEdge(i, 0));
+    let mut visited = vec![false; n];
+    visited[start] = true;
 
-            return Some(FindResult {
-                cost: min.cost.into(),
-                path: min.path,
-            });
+    let mut path = Vec::with_capacity(n);
+    let mut total = 0.0;
+    let mut i = start;
+    for _ in 1..n {
+        let (j, c) = (0..n)
+            .filter(|&j| !visited[j] && cost[(i, j)] != f64::INFINITY)
+            .map(|j| (j, cost[(i, j)]))
+            .min_by_key(|&(_, c)| OrderedFloat(c))?;
+        visited[j] = true;
+        path.push(PathEdge(i, j));
+        total += c;
+        i = j;
+    }
+
+    // Go back to starting vertex
+    if i != start {
+        let c = cost[(i, start)];
+        if c == f64::INFINITY {
+            return None;
         }
+        total += c;
+    }
+    path.push(PathEdge(i, start));
 
-        for (j, col) in min.reduced_matrix.row(i).column_iter().enumerate() {
-            let col_val: f64 = col[0].into();
-            if col_val == f64::INFINITY {
-                continue;
+    Some(FindResult {
+        cost: total,
+        path,
+        optimality: Optimality::Heuristic,
+        stats: Stats::default(),
+    })
+}
+
+/// Builds a tour from vertex 0 by greedily moving to the cheapest unvisited
+/// vertex of `required`, skipping the rest. Returns `None` if the walk
+/// is stuck because only infinite edges are left.
+fn nearest_required(cost: &DMatrix<f64>, required: &[usize]) -> Option<FindResult> {
+    let mut left: Vec<usize> = required.iter().copied().filter(|&v| v != 0).collect();
+    left.sort_unstable();
+    left.dedup();
+
+    let mut path = Vec::with_capacity(left.len() + 1);
+    let mut total = 0.0;
+    let mut i = 0;
+    while !left.is_empty() {
+        let (k, c) = left
+            .iter()
+            .enumerate()
+            .filter(|&(_, &j)| cost[(i, j)] != f64::INFINITY)
+            .map(|(k, &j)| (k, cost[(i, j)]))
+            .min_by_key(|&(_, c)| OrderedFloat(c))?;
+        let j = left.remove(k);
+        path.push(PathEdge(i, j));
+        total += c;
+        i = j;
+    }
+
+    // Go back to starting vertex
+    if i != 0 {
+        let c = cost[(i, 0)];
+        if c == f64::INFINITY {
+            return None;
+        }
+        total += c;
+    }
+    path.push(PathEdge(i, 0));
+
+    Some(FindResult {
+        cost: total,
+        path,
+        optimality: Optimality::Heuristic,
+        stats: Stats::default(),
+    })
+}
+
+/// Improves the tour by repeatedly reversing its segments while the
+/// total cost strictly decreases. Moves that introduce an infinite edge
+/// are rejected. The starting vertex of the tour is kept in place.
+pub fn two_opt(cost: &DMatrix<f64>, tour: &[PathEdge]) -> FindResult {
+    let tour_cost = |vertices: &[usize]| -> f64 {
+        vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .map(|(&i, &j)| cost[(i, j)])
+            .sum()
+    };
+
+    let mut vertices: Vec<usize> = tour.iter().map(PathEdge::from).collect();
+    let mut best = tour_cost(&vertices);
+    let n = vertices.len();
+
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..n {
+            for j in i + 1..n {
+                vertices[i..=j].reverse();
+                let new_cost = tour_cost(&vertices);
+                if new_cost != f64::INFINITY && new_cost < best {
+                    best = new_cost;
+                    improved = true;
+                } else {
+                    vertices[i..=j].reverse();
+                }
             }
+        }
+    }
 
-            let mut child = Node::new(
-                min.reduced_matrix.clone(),
-                min.level + 1,
-                i,
-                j,
-                min.path.to_vec(),
-            );
-            let min_cost: f64 = min.cost.into();
+    let path = vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(&i, &j)| PathEdge(i, j))
+        .collect();
 
-            child.cost += min_cost + col_val;
-            queue.push(child);
+    FindResult {
+        cost: best,
+        path,
+        optimality: Optimality::Heuristic,
+        stats: Stats::default(),
+    }
+}
+
+/// Default number of the nodes kept at each level by [`beam_search`].
+pub const BEAM_WIDTH: usize = 100;
+
+/// Solves the traveling salesman problem for a given cost matrix with
+/// the beam search: the branch-and-bound nodes are expanded level by level
+/// and only `width` nodes of the lowest bound are kept at each level.
+/// Smaller widths trade the optimality for speed and memory, the tour
+/// is optimal only if no nodes were dropped, e.g. with `usize::MAX`.
+/// Returns `None` if none of the kept nodes leads to a complete tour.
+pub fn beam_search(cost: &DMatrix<f64>, width: usize) -> Option<FindResult> {
+    if !is_valid_matrix(cost) || width == 0 {
+        return None;
+    }
+    let started = Instant::now();
+    let mut stats = Stats::default();
+    let mut beam = vec![Node::root(cost.clone())];
+    for _ in 1..cost.nrows() {
+        let mut next: Vec<Node> = beam.iter().flat_map(|node| node.children(&[])).collect();
+        stats.nodes_expanded += beam.len();
+        next.sort();
+        stats.nodes_pruned += next.len().saturating_sub(width);
+        next.truncate(width);
+        stats.peak_queue_len = stats.peak_queue_len.max(next.len());
+        beam = next;
+    }
+
+    let (best, path) = beam
+        .into_iter()
+        .filter(|node| cost[(node.vertex, 0)].is_finite())
+        .map(|node| {
+            let mut path = node.path;
+            path.push(PathEdge(node.vertex, 0));
+            let tour_cost: f64 = path.iter().map(|e| cost[(e.0, e.1)]).sum();
+            (tour_cost, path)
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+
+    Some(FindResult {
+        cost: best,
+        path,
+        optimality: exact_if(stats.nodes_pruned == 0),
+        stats: Stats {
+            best_cost: Some(best),
+            elapsed: started.elapsed(),
+            ..stats
+        },
+    })
+}
+
+/// Default number of the runs of [`random_restarts`].
+pub const RESTARTS: usize = 10;
+
+/// Runs [`nearest_neighbor`] from `restarts` start vertices chosen at random,
+/// improves each tour with [`two_opt`] and returns the cheapest one.
+/// The tours are rotated to start at vertex 0. The same `seed` gives
+/// the same tour. Returns `None` if none of the walks reached all the vertices.
+pub fn random_restarts(cost: &DMatrix<f64>, restarts: usize, seed: u64) -> Option<FindResult> {
+    if !is_valid_matrix(cost) {
+        return None;
+    }
+    let started = Instant::now();
+    let n = cost.nrows() as u64;
+    let mut rng = SplitMix64(seed);
+    let mut best: Option<FindResult> = None;
+    for _ in 0..restarts {
+        let start = (rng.next_u64() % n) as usize;
+        let mut path = match nearest_neighbor(cost, start) {
+            Some(result) => result.path,
+            None => continue,
+        };
+        let first = path.iter().position(|e| e.from() == 0).unwrap_or(0);
+        path.rotate_left(first);
+        let result = two_opt(cost, &path);
+        if best.iter().all(|b| result.cost < b.cost) {
+            best = Some(result);
         }
     }
 
-    None
+    best.map(|result| FindResult {
+        stats: Stats {
+            restarts,
+            best_cost: Some(result.cost),
+            elapsed: started.elapsed(),
+            ..Stats::default()
+        },
+        ..result
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// SplitMix64 pseudorandom generator. It keeps the random restarts
+/// reproducible between the builds without the optional `rand` dependency.
+struct SplitMix64(u64);
 
-    #[test]
-    fn node_priority_test() {
-        let mut priorities = [
-            NodePriority::new(1.0),
-            NodePriority::new(2.0),
-            NodePriority::new(1.1),
-            NodePriority::new(3.5),
-            NodePriority::new(2.005),
-        ];
-        let sorted = [
-            NodePriority::new(1.0),
-            NodePriority::new(1.1),
-            NodePriority::new(2.0),
-            NodePriority::new(2.005),
-            NodePriority::new(3.5),
-        ];
-        priorities.sort_by(|a, b| a.cmp(b));
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
 
-        assert_eq!(sorted, priorities);
+/// Maximum number of vertices accepted by [`held_karp`], its memory
+/// usage grows as *2^N · N*.
+pub const HELD_KARP_MAX_VERTICES: usize = 20;
+
+/// Solves the traveling salesman problem for a given cost matrix using
+/// the Held–Karp dynamic programming over subsets of vertices, starting
+/// from vertex `start`. Returns `None` if there is no tour with finite cost
+/// or the matrix has more than [`HELD_KARP_MAX_VERTICES`] vertices.
+pub fn held_karp(cost: &DMatrix<f64>, start: usize) -> Option<FindResult> {
+    if !is_valid_matrix(cost) || start >= cost.nrows() {
+        return None;
+    }
+    let n = cost.nrows();
+    if n > HELD_KARP_MAX_VERTICES {
+        return None;
     }
+    let started = Instant::now();
 
-    #[test]
-    #[rustfmt::skip]
-    fn find_test() {
-        let energy_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
-            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
-            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
-            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
-            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
-            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
-        ]).transpose();
+    // Vertices except the starting one, subsets are bitmasks over them
+    let others: Vec<usize> = (0..n).filter(|&v| v != start).collect();
+    let m = others.len();
+    let subsets = 1usize << m;
 
-        let time_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 96.774, 39.395, 112.903, 33.951, 64.516,
-            100.000, f64::INFINITY, 96.000, 37.498, 95.333, 36.098,
-            39.395, 92.903, f64::INFINITY, 109.032, 20.646, 60.645,
-            116.667, 37.498, 112.667, f64::INFINITY, 112.000, 50.000,
-            33.951, 92.258, 20.646, 108.387, f64::INFINITY, 60.000,
-            66.667, 36.098, 62.667, 48.387, 62.000, f64::INFINITY
-        ]).transpose();
+    // dist[mask * m + k] is the min cost of the path that starts at `start`,
+    // visits all vertices of the mask and ends at the vertex `others[k]`
+    let mut dist = vec![f64::INFINITY; subsets * m];
+    let mut parent = vec![u8::MAX; subsets * m];
+    for (k, &v) in others.iter().enumerate() {
+        dist[(1 << k) * m + k] = cost[(start, v)];
+    }
 
-        let capacity_cost = DMatrix::from_vec(6, 6, vec![
-            f64::INFINITY, 1.155, 0.276, 1.526, 0.251, 0.925,
-            0.472, f64::INFINITY, 0.510, 0.399, 0.521, 0.339,
-            0.248, 1.166, f64::INFINITY, 1.289, 0.129, 0.868,
-            0.728, 0.285, 0.519, f64::INFINITY, 0.529, 0.361,
-            0.219, 1.173, 0.125, 1.294, f64::INFINITY, 0.739,
-            0.470, 0.567, 0.440, 0.702, 0.315, f64::INFINITY
-        ]).transpose();
+    for mask in 1..subsets {
+        for k in (0..m).filter(|&k| mask & (1 << k) != 0) {
+            let d = dist[mask * m + k];
+            if d == f64::INFINITY {
+                continue;
+            }
+            for l in (0..m).filter(|&l| mask & (1 << l) == 0) {
+                let next = (mask | (1 << l)) * m + l;
+                let new_dist = d + cost[(others[k], others[l])];
+                if new_dist < dist[next] {
+                    dist[next] = new_dist;
+                    parent[next] = k as u8;
+                }
+            }
+        }
+    }
 
-        let energy_expected_path = vec![
-            PathEdge(0, 2),
-            PathEdge(2, 4),
-            PathEdge(4, 5),
-            PathEdge(5, 3),
-            PathEdge(3, 1),
-            PathEdge(1, 0),
-        ];
+    // Go back to starting vertex
+    let full = subsets - 1;
+    let (mut k, total) = (0..m)
+        .map(|k| (k, dist[full * m + k] + cost[(others[k], start)]))
+        .min_by_key(|&(_, c)| OrderedFloat(c))?;
+    if total == f64::INFINITY {
+        return None;
+    }
 
-        let time_expected_path = vec![
-            PathEdge(0, 5),
-            PathEdge(5, 3),
-            PathEdge(3, 1),
-            PathEdge(1, 2),
-            PathEdge(2, 4),
-            PathEdge(4, 0),
-        ];
+    let mut path = vec![PathEdge(others[k], start)];
+    let mut mask = full;
+    while parent[mask * m + k] != u8::MAX {
+        let prev = parent[mask * m + k] as usize;
+        path.push(PathEdge(others[prev], others[k]));
+        mask &= !(1 << k);
+        k = prev;
+    }
+    path.push(PathEdge(start, others[k]));
+    path.reverse();
 
-        let capacity_expected_path = vec![
-            PathEdge(0, 2),
-            PathEdge(2, 4),
-            PathEdge(4, 5),
-            PathEdge(5, 3),
-            PathEdge(3, 1),
-            PathEdge(1, 0),
-        ];
+    Some(FindResult {
+        cost: total,
+        path,
+        optimality: Optimality::Exact,
+        stats: Stats {
+            elapsed: started.elapsed(),
+            ..Stats::default()
+        },
+    })
+}
 
-        let result = find(&energy_cost).unwrap();
-        assert!(approx_eq!(f64, 213.615, result.get_cost(), epsilon = 0.001, ulps = 0));
-        assert_eq!(energy_expected_path, *result.get_path());
+/// Settings of the branch-and-bound search.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Maximum number of nodes kept in the queue. Once it's exceeded,
+    /// the nodes with the worst lower bound are evicted, so the found
+    /// path may be not optimal. Unlimited if `None`.
+    pub queue_cap: Option<usize>,
 
-        let result = find(&time_cost).unwrap();
-        assert!(approx_eq!(f64, 300.997, result.get_cost(), epsilon = 0.001, ulps = 0));
-        assert_eq!(time_expected_path, *result.get_path());
+    /// Time limit of the search. Once it's expired, the best complete
+    /// tour found so far is returned. Unlimited if `None`.
+    pub timeout: Option<Duration>,
 
-        let result = find(&capacity_cost).unwrap();
+    /// Number of expanded nodes between the progress reports,
+    /// `PROGRESS_INTERVAL` if `None`.
+    pub progress_interval: Option<usize>,
+
+    /// Pairs of vertices *(a, b)* meaning that *a* must be visited before *b*.
+    /// Partial paths violating the order are pruned.
+    pub precedences: Vec<(usize, usize)>,
+
+    /// Relative tolerance of the tour costs comparison, [`EPSILON`] if `None`.
+    /// Tours whose costs differ by less than `epsilon * max(|cost|, 1)`
+    /// are treated as equal and the lexicographically smaller one is returned.
+    pub epsilon: Option<f64>,
+
+    /// Vertices that must be visited, the rest may be skipped if it makes
+    /// the tour cheaper. The starting vertex is always visited.
+    /// All the vertices are required if `None`.
+    pub required: Option<Vec<usize>>,
+
+    /// Keep the reduced matrices of the search nodes in `f32`, which roughly
+    /// halves the memory of the queue that dominates it for large instances.
+    /// The lower bounds get the `f32` precision of about 7 significant digits,
+    /// so the tours whose costs differ less than that may be ranked wrongly,
+    /// the default `epsilon` is [`SINGLE_PRECISION_EPSILON`] then. The cost
+    /// of the found tour is summed up in `f64`. Ignored with the optional vertices.
+    pub single_precision: bool,
+
+    /// Order of taking the nodes from the queue. Ignored with the optional vertices.
+    pub order: SearchOrder,
+}
+
+/// Order of the branch-and-bound search, both return the same tour.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum SearchOrder {
+    /// Take the node of the lowest bound first. The first complete tour
+    /// taken is optimal, but the queue may grow large.
+    #[default]
+    BestFirst,
+
+    /// Take the last pushed node first, diving to a complete tour quickly
+    /// to tighten the upper bound. The queue stays much smaller, but more
+    /// nodes may be expanded and the optimum is only proven once it's empty.
+    DepthFirst,
+}
+
+/// Queue of the search nodes in the [`SearchOrder`].
+enum NodeQueue<T: ReducedCost> {
+    BestFirst(MinMaxHeap<Node<T>>),
+    DepthFirst(Vec<Node<T>>),
+}
+
+impl<T: ReducedCost> NodeQueue<T> {
+    fn new(order: SearchOrder) -> NodeQueue<T> {
+        match order {
+            SearchOrder::BestFirst => NodeQueue::BestFirst(MinMaxHeap::new()),
+            SearchOrder::DepthFirst => NodeQueue::DepthFirst(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, node: Node<T>) {
+        match self {
+            NodeQueue::BestFirst(heap) => heap.push(node),
+            NodeQueue::DepthFirst(stack) => stack.push(node),
+        }
+    }
+
+    /// Takes the node of the lowest bound or the last pushed one.
+    fn pop(&mut self) -> Option<Node<T>> {
+        match self {
+            NodeQueue::BestFirst(heap) => heap.pop_min(),
+            NodeQueue::DepthFirst(stack) => stack.pop(),
+        }
+    }
+
+    /// Evicts the node of the highest bound.
+    fn pop_max(&mut self) -> Option<Node<T>> {
+        match self {
+            NodeQueue::BestFirst(heap) => heap.pop_max(),
+            NodeQueue::DepthFirst(stack) => {
+                let (max, _) = stack.iter().enumerate().max_by(|a, b| a.1.cmp(b.1))?;
+                Some(stack.remove(max))
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            NodeQueue::BestFirst(heap) => heap.len(),
+            NodeQueue::DepthFirst(stack) => stack.len(),
+        }
+    }
+}
+
+/// Default relative tolerance of the tour costs comparison
+/// in the single precision search, see [`SearchOptions::single_precision`].
+pub const SINGLE_PRECISION_EPSILON: f64 = 1e-5;
+
+/// Default number of expanded nodes between the progress reports.
+pub const PROGRESS_INTERVAL: usize = 1000;
+
+/// Returns a vertex that has no finite outgoing or incoming edges,
+/// so no tour can visit it. The diagonal must be set to infinity.
+pub fn find_disconnected(cost: &DMatrix<f64>) -> Option<usize> {
+    (0..cost.nrows()).find(|&v| is_disconnected(cost, v))
+}
+
+/// Checks whether the vertex `v` has no finite outgoing or incoming edges.
+pub fn is_disconnected(cost: &DMatrix<f64>, v: usize) -> bool {
+    let unreachable = |c: &f64| !c.is_finite();
+
+    cost.row(v).iter().all(unreachable) || cost.column(v).iter().all(unreachable)
+}
+
+/// Checks whether `cost[(i, j)]` equals `cost[(j, i)]` for each pair
+/// of vertices within the relative tolerance.
+pub fn is_symmetric(cost: &DMatrix<f64>) -> bool {
+    let n = cost.nrows();
+
+    (0..n).all(|i| {
+        (i + 1..n).all(|j| {
+            let (a, b) = (cost[(i, j)], cost[(j, i)]);
+            // A one-way edge has an infinite difference within the infinite tolerance
+            if a.is_finite() != b.is_finite() {
+                return false;
+            }
+            a == b || (a - b).abs() <= EPSILON * a.abs().max(b.abs()).max(1.0)
+        })
+    })
+}
+
+/// Checks that the closed `tour` starting at vertex 0 visits
+/// the vertex *a* before *b* for each pair of `precedences`.
+/// If *b* isn't visited by the tour, the pair is satisfied.
+pub fn satisfies_precedences(tour: &[PathEdge], precedences: &[(usize, usize)]) -> bool {
+    let position = |v: usize| match v {
+        0 => Some(0),
+        _ => tour.iter().position(|e| e.to() == v).map(|k| k + 1),
+    };
+
+    precedences
+        .iter()
+        .all(|&(a, b)| match (position(a), position(b)) {
+            (_, None) => true,
+            (Some(a), Some(b)) => a < b,
+            (None, Some(_)) => false,
+        })
+}
+
+/// Solves the traveling salesman problem for a given cost matrix.
+pub fn find(cost: &DMatrix<f64>) -> Option<FindResult> {
+    find_with_options(cost, &SearchOptions::default())
+}
+
+/// Solves the traveling salesman problem for a given cost matrix,
+/// but stops after the time `budget` and returns the best tour found so far.
+pub fn find_with_budget(cost: &DMatrix<f64>, budget: Duration) -> Option<FindResult> {
+    let options = SearchOptions {
+        timeout: Some(budget),
+        ..SearchOptions::default()
+    };
+
+    find_with_options(cost, &options)
+}
+
+/// Solves the traveling salesman problem for a given cost matrix
+/// with the specified search settings.
+pub fn find_with_options(cost: &DMatrix<f64>, options: &SearchOptions) -> Option<FindResult> {
+    find_with_progress(cost, options, &mut |_| ())
+}
+
+/// Solves the traveling salesman problem for a given cost matrix, but
+/// returns `None` as soon as the `cancel` flag is set from another thread.
+/// The flag is checked before each node expansion. No tour is returned
+/// on cancellation, use [`find_with_budget`] if the best tour found
+/// so far is wanted instead.
+pub fn find_cancellable(cost: &DMatrix<f64>, cancel: &AtomicBool) -> Option<FindResult> {
+    find_cancellable_with_options(cost, &SearchOptions::default(), cancel)
+}
+
+/// Same as [`find_cancellable`] with the specified search settings,
+/// the search stops at the timeout or the cancellation, whichever is first.
+pub fn find_cancellable_with_options(
+    cost: &DMatrix<f64>,
+    options: &SearchOptions,
+    cancel: &AtomicBool,
+) -> Option<FindResult> {
+    find_with_cancel(cost, options, &mut |_| (), cancel)
+}
+
+/// Solves the traveling salesman problem for a given cost matrix
+/// with the specified search settings. The `progress` callback is
+/// periodically invoked with the current search statistics.
+pub fn find_with_progress(
+    cost: &DMatrix<f64>,
+    options: &SearchOptions,
+    progress: &mut dyn FnMut(&Stats),
+) -> Option<FindResult> {
+    find_with_cancel(cost, options, progress, &AtomicBool::new(false))
+}
+
+fn find_with_cancel(
+    cost: &DMatrix<f64>,
+    options: &SearchOptions,
+    progress: &mut dyn FnMut(&Stats),
+    cancel: &AtomicBool,
+) -> Option<FindResult> {
+    if !is_valid_matrix(cost) || cancel.load(AtomicOrdering::Relaxed) {
+        return None;
+    }
+    if let Some(required) = &options.required {
+        let greedy = nearest_required(cost, required)
+            .filter(|r| satisfies_precedences(&r.path, &options.precedences));
+        let (result, stats) = search_optional(cost, required, options, progress, cancel);
+        if cancel.load(AtomicOrdering::Relaxed) {
+            return None;
+        }
+        // The search isn't bounded by the greedy tour, so it may be cheaper
+        // than the best one found before the timeout
+        return result
+            .into_iter()
+            .chain(greedy)
+            .min_by_key(|r| OrderedFloat(r.cost))
+            .map(|result| with_search_stats(result, stats, options));
+    }
+
+    // The cost of a greedy tour is the upper bound of the optimal one,
+    // unless it breaks the visiting order
+    let greedy =
+        nearest_neighbor(cost, 0).filter(|r| satisfies_precedences(&r.path, &options.precedences));
+    let upper_bound = greedy.as_ref().map_or(f64::INFINITY, |r| r.cost);
+
+    // The search may drop all the complete tours if the queue
+    // is capped or not reach any of them before the timeout
+    let (result, stats) = if options.single_precision {
+        let (result, stats) = search::<f32>(cost, upper_bound, options, progress, cancel);
+        let exact_cost = |r: &FindResult| r.path.iter().map(|e| cost[(e.from(), e.to())]).sum();
+        (
+            result.map(|r| FindResult {
+                cost: exact_cost(&r),
+                ..r
+            }),
+            stats,
+        )
+    } else {
+        search::<f64>(cost, upper_bound, options, progress, cancel)
+    };
+    if cancel.load(AtomicOrdering::Relaxed) {
+        return None;
+    }
+    result
+        .or(greedy)
+        .map(|result| with_search_stats(result, stats, options))
+}
+
+/// Sets the search `stats` of the `result`. The result that isn't proven
+/// optimal is marked as [`Optimality::Timeout`] if the search ran out of time.
+fn with_search_stats(result: FindResult, stats: Stats, options: &SearchOptions) -> FindResult {
+    let timed_out = options
+        .timeout
+        .is_some_and(|timeout| stats.elapsed >= timeout);
+    let optimality = match result.optimality {
+        Optimality::Heuristic if timed_out => Optimality::Timeout,
+        optimality => optimality,
+    };
+
+    FindResult {
+        optimality,
+        stats,
+        ..result
+    }
+}
+
+/// Solves the traveling salesman problem for a given cost matrix
+/// and returns all the tours of the minimum cost (within a relative
+/// tolerance) along with the cost. The tours visit the vertices in order
+/// of `precedences`, see [`SearchOptions::precedences`].
+pub fn find_all(
+    cost: &DMatrix<f64>,
+    precedences: &[(usize, usize)],
+) -> Option<(f64, Vec<Vec<PathEdge>>)> {
+    if !is_valid_matrix(cost) {
+        return None;
+    }
+    let upper_bound = nearest_neighbor(cost, 0)
+        .filter(|r| satisfies_precedences(&r.path, precedences))
+        .map_or(f64::INFINITY, |r| r.cost);
+    let upper_bound = with_tolerance(upper_bound);
+    let mut optimum: Option<f64> = None;
+    let mut tours = Vec::new();
+
+    let mut queue = MinMaxHeap::new();
+    queue.push(Node::root(cost.clone()));
+
+    // Unlike the search for a single tour, the first complete tour
+    // doesn't stop it. The nodes are taken in order of the lower bound,
+    // so the rest of optimal tours are taken before any worse node
+    let n = cost.nrows();
+    while let Some(mut min) = queue.pop_min() {
+        let min_cost = f64::from(min.cost);
+        if let Some(optimum) = optimum {
+            if min_cost > with_tolerance(optimum) {
+                break;
+            }
+        }
+
+        let i = min.vertex;
+        if min.level == n - 1 {
+            if cost[(i, 0)].is_finite() {
+                min.path.push(PathEdge(i, 0));
+                optimum.get_or_insert(min_cost);
+                tours.push(min.path);
+            }
+            continue;
+        }
+
+        for child in min.children(precedences) {
+            if f64::from(child.cost) <= upper_bound {
+                queue.push(child);
+            }
+        }
+    }
+
+    optimum.map(|cost| (cost, tours))
+}
+
+/// Solves the traveling salesman problem lexicographically: finds all the
+/// tours of the minimum cost by the first of `costs`, see [`find_all`],
+/// and keeps the tours of the minimum cost by each next matrix in turn.
+/// The cost of the result is by the first matrix.
+pub fn find_lexicographic(
+    costs: &[&DMatrix<f64>],
+    precedences: &[(usize, usize)],
+) -> Option<FindResult> {
+    let (primary, tie_breaks) = costs.split_first()?;
+    let (cost, mut tours) = find_all(primary, precedences)?;
+    for tie_break in tie_breaks {
+        let tour_cost =
+            |tour: &[PathEdge]| -> f64 { tour.iter().map(|e| tie_break[(e.0, e.1)]).sum() };
+        let min = tours
+            .iter()
+            .map(|tour| tour_cost(tour))
+            .fold(f64::INFINITY, f64::min);
+        tours.retain(|tour| tour_cost(tour) <= with_tolerance(min));
+    }
+
+    Some(FindResult {
+        cost,
+        path: tours.swap_remove(0),
+        optimality: Optimality::Exact,
+        stats: Stats::default(),
+    })
+}
+
+/// Solves the traveling salesman problem for a given cost matrix
+/// and returns up to `k` cheapest distinct tours in increasing cost order.
+/// The tours visit the vertices in order of `precedences`,
+/// see [`SearchOptions::precedences`].
+pub fn find_k_best(
+    cost: &DMatrix<f64>,
+    k: usize,
+    precedences: &[(usize, usize)],
+) -> Vec<FindResult> {
+    if k == 0 || !is_valid_matrix(cost) {
+        return Vec::new();
+    }
+
+    // Bounded max-heap of the best complete tours found so far
+    let mut best = MinMaxHeap::new();
+    // Nodes whose lower bound exceeds it can't give a better tour
+    let kth_best = |best: &MinMaxHeap<(NodePriority, Vec<PathEdge>)>| match best.peek_max() {
+        Some((cost, _)) if best.len() >= k => with_tolerance(f64::from(*cost)),
+        _ => f64::INFINITY,
+    };
+
+    let mut queue = MinMaxHeap::new();
+    queue.push(Node::root(cost.clone()));
+
+    let n = cost.nrows();
+    while let Some(min) = queue.pop_min() {
+        if f64::from(min.cost) > kth_best(&best) {
+            break;
+        }
+
+        for child in min.children(precedences) {
+            if f64::from(child.cost) > kth_best(&best) {
+                continue;
+            }
+            // Complete tours are collected instead of stopping the search
+            if child.level == n - 1 {
+                let j = child.vertex;
+                if cost[(j, 0)].is_finite() {
+                    let mut path = child.path;
+                    path.push(PathEdge(j, 0));
+                    best.push((child.cost, path));
+                    if best.len() > k {
+                        best.pop_max();
+                    }
+                }
+                continue;
+            }
+
+            queue.push(child);
+        }
+    }
+    best.into_vec_asc()
+        .into_iter()
+        .enumerate()
+        .map(|(i, (cost, path))| FindResult {
+            cost: cost.into(),
+            path,
+            optimality: exact_if(i == 0),
+            stats: Stats::default(),
+        })
+        .collect()
+}
+
+/// [`Optimality::Exact`] if the search was `exact`, [`Optimality::Heuristic`] otherwise.
+fn exact_if(exact: bool) -> Optimality {
+    if exact {
+        Optimality::Exact
+    } else {
+        Optimality::Heuristic
+    }
+}
+
+fn with_tolerance(cost: f64) -> f64 {
+    with_epsilon(cost, EPSILON)
+}
+
+fn with_epsilon(cost: f64, epsilon: f64) -> f64 {
+    cost + epsilon * cost.abs().max(1.0)
+}
+
+/// Branch-and-bound search keeping the reduced matrices in `T`. Nodes whose
+/// lower bound exceeds the cost of the best complete tour known so far
+/// are never pushed to the queue.
+fn search<T: ReducedCost>(
+    cost: &DMatrix<f64>,
+    upper_bound: f64,
+    options: &SearchOptions,
+    progress: &mut dyn FnMut(&Stats),
+    cancel: &AtomicBool,
+) -> (Option<FindResult>, Stats) {
+    let started = Instant::now();
+    let progress_interval = options
+        .progress_interval
+        .unwrap_or(PROGRESS_INTERVAL)
+        .max(1);
+    let mut next_progress = progress_interval;
+    let mut stats = Stats {
+        best_cost: Some(upper_bound).filter(|c| c.is_finite()),
+        ..Stats::default()
+    };
+    let epsilon = options.epsilon.unwrap_or(if options.single_precision {
+        SINGLE_PRECISION_EPSILON
+    } else {
+        EPSILON
+    });
+    let mut upper_bound = with_epsilon(upper_bound, epsilon);
+    let mut evicted = false;
+    // The best complete tour found so far, in case the search is stopped
+    // or the tour is evicted from the queue
+    let mut best: Option<FindResult> = None;
+    // The complete tours taken from the queue within the tolerance of the
+    // cheapest one, the lexicographically smallest of them is returned.
+    // The first one is optimal in the best-first order
+    let mut tours: Vec<FindResult> = Vec::new();
+    let mut optimal_limit = f64::INFINITY;
+    let mut stopped = false;
+
+    // Each tour of a symmetric matrix has the reverse one of the same cost,
+    // only the tours ending at a vertex greater than the first one are explored.
+    // The reversal would break the visiting order, so it's kept in full then
+    let symmetric = options.precedences.is_empty() && is_symmetric(cost);
+
+    let mut queue = NodeQueue::new(options.order);
+    let root = Node::root(cost.map(T::from_f64));
+    queue.push(root);
+    stats.peak_queue_len = queue.len();
+
+    // Finds node with least cost, add its children to list of
+    // nodes and finally deletes it from the list
+    let n = cost.nrows();
+    while let Some(mut min) = queue.pop() {
+        if cancel.load(AtomicOrdering::Relaxed)
+            || options.timeout.is_some_and(|t| started.elapsed() >= t)
+        {
+            stopped = true;
+            break;
+        }
+        match options.order {
+            SearchOrder::BestFirst if f64::from(min.cost) > optimal_limit => break,
+            // The upper bound may be lowered since the node was pushed
+            SearchOrder::DepthFirst if f64::from(min.cost) > upper_bound => {
+                stats.nodes_pruned += 1;
+                continue;
+            }
+            _ => (),
+        }
+        stats.nodes_expanded += 1;
+        if stats.nodes_expanded == next_progress {
+            next_progress += progress_interval;
+            stats.queue_len = queue.len();
+            stats.elapsed = started.elapsed();
+            progress(&stats);
+        }
+
+        let i = min.vertex;
+        // All vertex are visited
+        if min.level == n - 1 {
+            // There is no way back to starting vertex
+            if !cost[(i, 0)].is_finite() {
+                continue;
+            }
+            // Go back to starting vertex
+            min.path.push(PathEdge(i, 0));
+
+            let tour_cost = f64::from(min.cost);
+            if tour_cost > optimal_limit {
+                continue;
+            }
+            if tours.iter().all(|t| tour_cost < t.cost) {
+                optimal_limit = with_epsilon(tour_cost, epsilon);
+                tours.retain(|t| t.cost <= optimal_limit);
+            }
+            tours.push(FindResult {
+                cost: tour_cost,
+                path: min.path,
+                optimality: exact_if(!evicted),
+                stats: Stats::default(),
+            });
+            continue;
+        }
+
+        let mut children: Vec<Node<T>> = min.children(&options.precedences).collect();
+        // The stack takes the child of the lowest bound first
+        if options.order == SearchOrder::DepthFirst {
+            children.sort_by(|a, b| b.cmp(a));
+        }
+        for child in children {
+            let j = child.vertex;
+            let child_cost = f64::from(child.cost);
+            if symmetric && child.is_mirrored() {
+                stats.mirrors_pruned += 1;
+                continue;
+            }
+            if child_cost > upper_bound {
+                stats.nodes_pruned += 1;
+                continue;
+            }
+            // Complete tour, its cost is the new best known
+            if child.level == n - 1 && cost[(j, 0)].is_finite() {
+                upper_bound = upper_bound.min(with_epsilon(child_cost, epsilon));
+                stats.best_cost = Some(child_cost);
+                let mut path = child.path.to_vec();
+                path.push(PathEdge(j, 0));
+                best = Some(FindResult {
+                    cost: child_cost,
+                    path,
+                    optimality: Optimality::Heuristic,
+                    stats: Stats::default(),
+                });
+            }
+
+            queue.push(child);
+            stats.peak_queue_len = stats.peak_queue_len.max(queue.len());
+            if let Some(cap) = options.queue_cap {
+                while queue.len() > cap {
+                    queue.pop_max();
+                    stats.nodes_pruned += 1;
+                    evicted = true;
+                }
+            }
+        }
+    }
+
+    let optimal = tours.into_iter().min_by(|a, b| a.path.cmp(&b.path));
+    // The depth-first search proves the optimum only by exhausting the stack
+    let optimal = match options.order {
+        SearchOrder::DepthFirst => optimal.map(|o| FindResult {
+            optimality: exact_if(!evicted && !stopped),
+            ..o
+        }),
+        SearchOrder::BestFirst => optimal,
+    };
+
+    stats.elapsed = started.elapsed();
+    stats.queue_len = queue.len();
+    if let Some(optimal) = &optimal {
+        stats.best_cost = Some(optimal.cost);
+    }
+    (optimal.or(best), stats)
+}
+
+/// Partial tour of the search with optional vertices.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+struct PartialTour {
+    /// Lower bound of the complete tour cost.
+    bound: NodePriority,
+    path: Vec<PathEdge>,
+    /// Cost of the path so far.
+    cost: NodePriority,
+    vertex: usize,
+    /// The tour is returned to the starting vertex.
+    closed: bool,
+}
+
+/// Best-first search of the cheapest tour that visits all the `required`
+/// vertices and any subset of the rest (prize-collecting TSP without prizes).
+/// The reduced matrix bound assumes that all the vertices are visited,
+/// so the lower bound here is the path cost plus the cheapest edge entering
+/// each of the unvisited required vertices and the starting one. The first
+/// closed tour taken from the queue is optimal. Costs must be non-negative.
+/// If the search is stopped, the cheapest closed tour pushed so far is returned.
+fn search_optional(
+    cost: &DMatrix<f64>,
+    required: &[usize],
+    options: &SearchOptions,
+    progress: &mut dyn FnMut(&Stats),
+    cancel: &AtomicBool,
+) -> (Option<FindResult>, Stats) {
+    let started = Instant::now();
+    let progress_interval = options
+        .progress_interval
+        .unwrap_or(PROGRESS_INTERVAL)
+        .max(1);
+    let mut next_progress = progress_interval;
+    let mut stats = Stats::default();
+    let mut evicted = false;
+    // The cheapest closed tour pushed so far, in case the search is stopped
+    // or the tour is evicted from the queue
+    let mut best: Option<FindResult> = None;
+
+    let n = cost.nrows();
+    let min_in: Vec<f64> = cost.column_iter().map(|col| col.min()).collect();
+    let mut is_required = vec![false; n];
+    for &v in required {
+        is_required[v] = true;
+    }
+    is_required[0] = false;
+    // Vertices that aren't required are never visited unless it's cheaper,
+    // so the bound only accounts for the required ones
+    let bound = |path: &[PathEdge], path_cost: f64| {
+        let missing: f64 = (1..n)
+            .filter(|&v| is_required[v] && !path.iter().any(|e| e.to() == v))
+            .map(|v| min_in[v])
+            .sum();
+        path_cost + missing + min_in[0]
+    };
+
+    if !is_required.contains(&true) {
+        // Nothing to visit, stay at the starting vertex
+        let result = FindResult {
+            cost: 0.0,
+            path: vec![PathEdge(0, 0)],
+            optimality: Optimality::Exact,
+            stats: Stats::default(),
+        };
+        return (Some(result), stats);
+    }
+
+    let mut queue = MinMaxHeap::new();
+    queue.push(PartialTour {
+        bound: bound(&[], 0.0).into(),
+        path: Vec::new(),
+        cost: 0.0.into(),
+        vertex: 0,
+        closed: false,
+    });
+    stats.peak_queue_len = queue.len();
+
+    while let Some(min) = queue.pop_min() {
+        if cancel.load(AtomicOrdering::Relaxed) {
+            break;
+        }
+        if let Some(timeout) = options.timeout {
+            if started.elapsed() >= timeout {
+                break;
+            }
+        }
+        stats.nodes_expanded += 1;
+        if stats.nodes_expanded == next_progress {
+            next_progress += progress_interval;
+            stats.queue_len = queue.len();
+            stats.elapsed = started.elapsed();
+            progress(&stats);
+        }
+
+        if min.closed {
+            stats.elapsed = started.elapsed();
+            stats.queue_len = queue.len();
+            stats.best_cost = Some(min.cost.into());
+            let result = FindResult {
+                cost: min.cost.into(),
+                path: min.path,
+                optimality: exact_if(!evicted),
+                stats: Stats::default(),
+            };
+            return (Some(result), stats);
+        }
+
+        let i = min.vertex;
+        let visited = |v: usize| v == 0 || min.path.iter().any(|e| e.to() == v);
+        let all_required = (1..n).all(|v| !is_required[v] || visited(v));
+        let children = (1..n)
+            .filter(|&j| !visited(j) && cost[(i, j)].is_finite())
+            .filter(|&j| {
+                options
+                    .precedences
+                    .iter()
+                    .filter(|&&(_, b)| b == j)
+                    .all(|&(a, _)| visited(a))
+            })
+            .chain(Some(0).filter(|_| all_required && cost[(i, 0)].is_finite()));
+        for j in children {
+            let mut path = min.path.to_vec();
+            path.push(PathEdge(i, j));
+            let path_cost = f64::from(min.cost) + cost[(i, j)];
+            let closed = j == 0;
+            let child = PartialTour {
+                bound: if closed {
+                    path_cost
+                } else {
+                    bound(&path, path_cost)
+                }
+                .into(),
+                path,
+                cost: path_cost.into(),
+                vertex: j,
+                closed,
+            };
+            if closed && best.iter().all(|b| path_cost < b.cost) {
+                stats.best_cost = Some(path_cost);
+                best = Some(FindResult {
+                    cost: path_cost,
+                    path: child.path.clone(),
+                    optimality: Optimality::Heuristic,
+                    stats: Stats::default(),
+                });
+            }
+
+            queue.push(child);
+            stats.peak_queue_len = stats.peak_queue_len.max(queue.len());
+            if let Some(cap) = options.queue_cap {
+                while queue.len() > cap {
+                    queue.pop_max();
+                    stats.nodes_pruned += 1;
+                    evicted = true;
+                }
+            }
+        }
+    }
+
+    stats.elapsed = started.elapsed();
+    stats.queue_len = queue.len();
+    (best, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::once;
+
+    #[test]
+    fn node_priority_test() {
+        let mut priorities = [
+            NodePriority::new(1.0),
+            NodePriority::new(2.0),
+            NodePriority::new(1.1),
+            NodePriority::new(3.5),
+            NodePriority::new(2.005),
+        ];
+        let sorted = [
+            NodePriority::new(1.0),
+            NodePriority::new(1.1),
+            NodePriority::new(2.0),
+            NodePriority::new(2.005),
+            NodePriority::new(3.5),
+        ];
+        priorities.sort();
+
+        assert_eq!(sorted, priorities);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn nearest_neighbor_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let result = nearest_neighbor(&energy_cost, 0).unwrap();
+        let path = result.get_path();
+        assert_eq!(6, path.len());
+        assert_eq!(0, path[0].from());
+        assert_eq!(0, path[5].to());
+        for (prev, next) in path.iter().zip(path.iter().skip(1)) {
+            assert_eq!(prev.to(), next.from());
+        }
+        let mut visited: Vec<usize> = path.iter().map(PathEdge::to).collect();
+        visited.sort();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], visited);
+
+        let cost: f64 = path.iter().map(|p| energy_cost[(p.from(), p.to())]).sum();
+        assert!(approx_eq!(f64, cost, result.get_cost(), epsilon = 0.001, ulps = 0));
+        // Greedy tour can't be better than the optimal one
+        assert!(result.get_cost() >= 213.615 - 0.001);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn trace_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+        let result = find(&energy_cost).unwrap();
+        let tour: Vec<usize> = once(0).chain(result.get_path().iter().map(PathEdge::to)).collect();
+
+        let steps = trace(&energy_cost, &tour);
+        assert_eq!(6, steps.len());
+        assert_eq!(None, steps[0].edge);
+        for (step, edge) in steps.iter().skip(1).zip(result.get_path()) {
+            assert_eq!(Some(*edge), step.edge);
+        }
+        // The lower bound only grows on the way to the tour and reaches its cost
+        for pair in steps.windows(2) {
+            assert!(pair[0].lower_bound <= pair[1].lower_bound);
+        }
+        assert!(approx_eq!(f64, result.get_cost(), steps[5].lower_bound, epsilon = 1e-9, ulps = 0));
+        // The visited rows are crossed out of the reduced matrix
+        let last = &steps[5].reduced_matrix;
+        assert!(last.row(0).iter().all(|c| c.is_infinite()));
+        // Each row and column of the reduced root matrix has a zero
+        let root = &steps[0].reduced_matrix;
+        assert!(root.row_iter().all(|r| r.iter().any(|&c| c == 0.0)));
+        assert!(root.column_iter().all(|c| c.iter().any(|&c| c == 0.0)));
+    }
+
+    #[test]
+    fn nearest_neighbor_stuck_test() {
+        let inf = f64::INFINITY;
+        // Vertex 2 can't return to the start
+        let cost = DMatrix::from_row_slice(3, 3, &[inf, 1.0, 2.0, 1.0, inf, 1.0, inf, 1.0, inf]);
+
+        assert!(nearest_neighbor(&cost, 0).is_none());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn two_opt_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let initial = nearest_neighbor(&energy_cost, 0).unwrap();
+        let result = two_opt(&energy_cost, initial.get_path());
+        assert!(result.get_cost() <= initial.get_cost());
+        assert!(result.get_cost() >= 213.615 - 0.001);
+        assert_eq!(6, result.get_path().len());
+        assert_eq!(0, result.get_path()[0].from());
+
+        // Already converged, no improving move left
+        let again = two_opt(&energy_cost, result.get_path());
+        assert_eq!(result.get_path(), again.get_path());
+        assert!(approx_eq!(f64, result.get_cost(), again.get_cost(), epsilon = 0.001, ulps = 0));
+
+        let worst = vec![
+            PathEdge(0, 3),
+            PathEdge(3, 2),
+            PathEdge(2, 1),
+            PathEdge(1, 4),
+            PathEdge(4, 5),
+            PathEdge(5, 0),
+        ];
+        let worst_cost: f64 = worst.iter().map(|p| energy_cost[(p.from(), p.to())]).sum();
+        let result = two_opt(&energy_cost, &worst);
+        assert!(result.get_cost() < worst_cost);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn two_opt_infinite_edge_test() {
+        let inf = f64::INFINITY;
+        // Reversed tour 0 -> 3 -> 2 -> 1 -> 0 would be cheaper,
+        // but there is no edge 1 -> 0
+        let cost = DMatrix::from_row_slice(4, 4, &[
+            inf, 5.0, 1.0, 1.0,
+            inf, inf, 5.0, 1.0,
+            1.0, 1.0, inf, 5.0,
+            5.0, 1.0, 1.0, inf,
+        ]);
+        let tour = vec![
+            PathEdge(0, 1),
+            PathEdge(1, 2),
+            PathEdge(2, 3),
+            PathEdge(3, 0),
+        ];
+
+        let result = two_opt(&cost, &tour);
+        assert!(result.get_cost() <= 20.0);
+        assert!(result.get_cost().is_finite());
+        assert!(result
+            .get_path()
+            .iter()
+            .all(|p| cost[(p.from(), p.to())].is_finite()));
+    }
+
+    /// Euclidean distances between 10 points on the plane.
+    fn ten_points_cost() -> DMatrix<f64> {
+        let points: [(f64, f64); 10] = [
+            (0.0, 0.0),
+            (12.0, 85.0),
+            (40.0, 20.0),
+            (95.0, 60.0),
+            (33.0, 71.0),
+            (70.0, 5.0),
+            (58.0, 90.0),
+            (88.0, 31.0),
+            (20.0, 45.0),
+            (65.0, 48.0),
+        ];
+        DMatrix::from_fn(10, 10, |i, j| {
+            if i == j {
+                f64::INFINITY
+            } else {
+                let (x_i, y_i) = points[i];
+                let (x_j, y_j) = points[j];
+                (x_i - x_j).hypot(y_i - y_j)
+            }
+        })
+    }
+
+    #[test]
+    fn lazy_reduction_test() {
+        let cost = ten_points_cost();
+        // The bound of each node built from the shared parent matrix is the same
+        // as the one of the matrix copied and reduced for the node alone
+        let root = Node::root(cost.clone());
+        let mut eager = cost.clone();
+        eager[(0, 0)] = f64::INFINITY;
+        assert_eq!(
+            Node::calculate_expected_cost(&mut eager),
+            f64::from(root.cost)
+        );
+        assert_eq!(eager, root.reduced_matrix());
+        let children: Vec<Node> = root.children(&[]).collect();
+        assert_eq!(9, children.len());
+        for child in &children {
+            let PathEdge(i, j) = *child.path.last().unwrap();
+            let mut matrix = eager.clone();
+            let bound = f64::from(root.cost) + matrix[(i, j)];
+            matrix.fill_row(i, f64::INFINITY);
+            matrix.fill_column(j, f64::INFINITY);
+            matrix[(j, 0)] = f64::INFINITY;
+            let reduction = Node::calculate_expected_cost(&mut matrix);
+            assert_eq!(reduction + bound, f64::from(child.cost));
+            assert_eq!(matrix, child.reduced_matrix());
+            // The siblings share the reduced matrix of the parent
+            assert!(Rc::ptr_eq(&children[0].parent_matrix, &child.parent_matrix));
+        }
+
+        let result = find(&cost).unwrap();
+        let expected = held_karp(&cost, 0).unwrap();
+        assert!(result.is_optimal());
+        assert_eq!(10, result.get_path().len());
+        assert!(approx_eq!(
+            f64,
+            expected.get_cost(),
+            result.get_cost(),
+            epsilon = 1e-9,
+            ulps = 0
+        ));
+    }
+
+    #[test]
+    fn upper_bound_pruning_test() {
+        let cost = ten_points_cost();
+        let options = SearchOptions::default();
+
+        let (unpruned, unpruned_stats) = search::<f64>(
+            &cost,
+            f64::INFINITY,
+            &options,
+            &mut |_| (),
+            &AtomicBool::new(false),
+        );
+        let unpruned = unpruned.unwrap();
+        let upper_bound = nearest_neighbor(&cost, 0).unwrap().get_cost();
+        let (pruned, pruned_stats) = search::<f64>(
+            &cost,
+            upper_bound,
+            &options,
+            &mut |_| (),
+            &AtomicBool::new(false),
+        );
+        let pruned = pruned.unwrap();
+
+        assert!(approx_eq!(
+            f64,
+            unpruned.get_cost(),
+            pruned.get_cost(),
+            epsilon = 0.001,
+            ulps = 0
+        ));
+        assert_eq!(10, pruned.get_path().len());
+        assert!(pruned_stats.nodes_pruned > unpruned_stats.nodes_pruned);
+        assert!(pruned_stats.peak_queue_len < unpruned_stats.peak_queue_len);
+    }
+
+    #[test]
+    fn queue_cap_test() {
+        let cost = ten_points_cost();
+        let exact = find(&cost).unwrap();
+
+        let options = SearchOptions {
+            queue_cap: Some(5),
+            ..SearchOptions::default()
+        };
+        let result = find_with_options(&cost, &options).unwrap();
+        assert_eq!(10, result.get_path().len());
+        assert!(result.get_cost() >= exact.get_cost() - 0.001);
+    }
+
+    #[test]
+    fn depth_first_test() {
+        let depth_first = SearchOptions {
+            order: SearchOrder::DepthFirst,
+            ..SearchOptions::default()
+        };
+        // Euclidean distances between 12 scattered points
+        let points: Vec<(f64, f64)> = (0..12)
+            .map(|i| ((i * 37 % 101) as f64, (i * 61 % 97) as f64))
+            .collect();
+        let cost = DMatrix::from_fn(12, 12, |i, j| {
+            if i == j {
+                f64::INFINITY
+            } else {
+                (points[i].0 - points[j].0).hypot(points[i].1 - points[j].1)
+            }
+        });
+        let best = find(&cost).unwrap();
+        let depth = find_with_options(&cost, &depth_first).unwrap();
+        assert!(depth.is_optimal());
+        assert_eq!(best.get_path(), depth.get_path());
+        assert_eq!(best.get_cost(), depth.get_cost());
+        // Only the siblings of the nodes on the way down are kept
+        let (best, depth) = (best.stats(), depth.stats());
+        assert!(depth.peak_queue_len * 10 < best.peak_queue_len);
+
+        // The same tour is picked among the equally optimal ones
+        let cost = DMatrix::repeat(5, 5, 1.0);
+        let best = find(&cost).unwrap();
+        let depth = find_with_options(&cost, &depth_first).unwrap();
+        assert_eq!(best.get_path(), depth.get_path());
+
+        // A stopped search isn't proven optimal
+        let options = SearchOptions {
+            timeout: Some(Duration::from_secs(0)),
+            ..depth_first
+        };
+        assert!(!find_with_options(&ten_points_cost(), &options)
+            .unwrap()
+            .is_optimal());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn stats_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let result = find(&energy_cost).unwrap();
+        let stats = result.stats();
+        assert!(stats.nodes_expanded >= 6);
+        assert!(stats.peak_queue_len >= 1);
+    }
+
+    #[test]
+    fn progress_test() {
+        let cost = ten_points_cost();
+        let options = SearchOptions {
+            progress_interval: Some(2),
+            ..SearchOptions::default()
+        };
+
+        let mut calls = 0;
+        let mut last_expanded = 0;
+        let result = find_with_progress(&cost, &options, &mut |stats| {
+            calls += 1;
+            assert!(stats.nodes_expanded > last_expanded);
+            assert!(stats.best_cost.is_some());
+            last_expanded = stats.nodes_expanded;
+        })
+        .unwrap();
+
+        assert!(calls > 0);
+        assert_eq!(result.stats().nodes_expanded / 2, calls);
+        assert!(approx_eq!(
+            f64,
+            find(&cost).unwrap().get_cost(),
+            result.get_cost(),
+            epsilon = 0.001,
+            ulps = 0
+        ));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn held_karp_test() {
+        let time_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 96.774, 39.395, 112.903, 33.951, 64.516,
+            100.000, f64::INFINITY, 96.000, 37.498, 95.333, 36.098,
+            39.395, 92.903, f64::INFINITY, 109.032, 20.646, 60.645,
+            116.667, 37.498, 112.667, f64::INFINITY, 112.000, 50.000,
+            33.951, 92.258, 20.646, 108.387, f64::INFINITY, 60.000,
+            66.667, 36.098, 62.667, 48.387, 62.000, f64::INFINITY
+        ]).transpose();
+
+        for cost in &[time_cost, ten_points_cost()] {
+            let expected = find(cost).unwrap();
+            let result = held_karp(cost, 0).unwrap();
+            assert!(result.is_optimal());
+            assert!(approx_eq!(f64, expected.get_cost(), result.get_cost(), epsilon = 0.001, ulps = 0));
+
+            let path = result.get_path();
+            assert_eq!(cost.nrows(), path.len());
+            assert_eq!(0, path[0].from());
+            for (prev, next) in path.iter().zip(path.iter().cycle().skip(1)) {
+                assert_eq!(prev.to(), next.from());
+            }
+            let path_cost: f64 = path.iter().map(|p| cost[(p.from(), p.to())]).sum();
+            assert!(approx_eq!(f64, path_cost, result.get_cost(), epsilon = 0.001, ulps = 0));
+        }
+
+        assert!(held_karp(&DMatrix::repeat(21, 21, 1.0), 0).is_none());
+    }
+
+    #[test]
+    fn find_with_budget_test() {
+        let cost = ten_points_cost();
+        let exact = find(&cost).unwrap();
+        assert!(exact.is_optimal());
+
+        let result = find_with_budget(&cost, Duration::from_nanos(1)).unwrap();
+        assert!(!result.is_optimal());
+        assert!(result.get_cost() >= exact.get_cost() - 0.001);
+        let path = result.get_path();
+        assert_eq!(10, path.len());
+        assert_eq!(0, path[0].from());
+        for (prev, next) in path.iter().zip(path.iter().cycle().skip(1)) {
+            assert_eq!(prev.to(), next.from());
+        }
+        let mut visited: Vec<usize> = path.iter().map(PathEdge::to).collect();
+        visited.sort();
+        assert_eq!((0..10).collect::<Vec<usize>>(), visited);
+    }
+
+    #[test]
+    fn optimality_test() {
+        let cost = ten_points_cost();
+        let greedy = nearest_neighbor(&cost, 0).unwrap();
+        let queue_cap = SearchOptions {
+            queue_cap: Some(1),
+            ..SearchOptions::default()
+        };
+        let results = [
+            (Optimality::Exact, find(&cost)),
+            (Optimality::Exact, held_karp(&cost, 0)),
+            (Optimality::Heuristic, nearest_neighbor(&cost, 0)),
+            (
+                Optimality::Heuristic,
+                Some(two_opt(&cost, greedy.get_path())),
+            ),
+            (Optimality::Heuristic, random_restarts(&cost, 3, 42)),
+            (Optimality::Heuristic, beam_search(&cost, 1)),
+            (Optimality::Heuristic, find_with_options(&cost, &queue_cap)),
+            (
+                Optimality::Timeout,
+                find_with_budget(&cost, Duration::from_nanos(1)),
+            ),
+        ];
+        for (i, (expected, result)) in results.iter().enumerate() {
+            let result = result.as_ref().unwrap();
+            assert_eq!(*expected, result.optimality(), "solver {}", i);
+            assert_eq!(*expected == Optimality::Exact, result.is_optimal());
+        }
+        assert_eq!("timeout", Optimality::Timeout.to_string());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn beam_search_test() {
+        let time_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 96.774, 39.395, 112.903, 33.951, 64.516,
+            100.000, f64::INFINITY, 96.000, 37.498, 95.333, 36.098,
+            39.395, 92.903, f64::INFINITY, 109.032, 20.646, 60.645,
+            116.667, 37.498, 112.667, f64::INFINITY, 112.000, 50.000,
+            33.951, 92.258, 20.646, 108.387, f64::INFINITY, 60.000,
+            66.667, 36.098, 62.667, 48.387, 62.000, f64::INFINITY
+        ]).transpose();
+        let exact = find(&time_cost).unwrap();
+
+        let result = beam_search(&time_cost, usize::MAX).unwrap();
+        assert!(result.is_optimal());
+        assert_eq!(0, result.stats().nodes_pruned);
+        assert!(approx_eq!(f64, exact.get_cost(), result.get_cost(), epsilon = 0.001, ulps = 0));
+
+        let cost = ten_points_cost();
+        let exact = find(&cost).unwrap();
+        for &width in &[1, 10, BEAM_WIDTH] {
+            let result = beam_search(&cost, width).unwrap();
+            let path = result.get_path();
+            assert_eq!(10, path.len());
+            assert_eq!(0, path[0].from());
+            for (prev, next) in path.iter().zip(path.iter().cycle().skip(1)) {
+                assert_eq!(prev.to(), next.from());
+            }
+            assert!(result.get_cost() >= exact.get_cost() - 0.001);
+            assert!(result.stats().peak_queue_len <= width);
+        }
+        assert!(beam_search(&cost, 0).is_none());
+    }
+
+    #[test]
+    fn random_restarts_test() {
+        let cost = ten_points_cost();
+        let exact = find(&cost).unwrap();
+
+        let result = random_restarts(&cost, 5, 42).unwrap();
+        let again = random_restarts(&cost, 5, 42).unwrap();
+        assert_eq!(result.get_path(), again.get_path());
+        assert_eq!(result.get_cost(), again.get_cost());
+        assert_eq!(5, result.stats().restarts);
+        assert!(!result.is_optimal());
+        assert!(result.get_cost() >= exact.get_cost() - 0.001);
+
+        for seed in 0..10 {
+            let path = random_restarts(&cost, 3, seed).unwrap().path;
+            assert_eq!(10, path.len());
+            assert_eq!(0, path[0].from());
+            for (prev, next) in path.iter().zip(path.iter().cycle().skip(1)) {
+                assert_eq!(prev.to(), next.from());
+            }
+        }
+        assert!(random_restarts(&cost, 0, 42).is_none());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn single_precision_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+        let options = SearchOptions {
+            single_precision: true,
+            ..SearchOptions::default()
+        };
+
+        let double = find(&energy_cost).unwrap();
+        let single = find_with_options(&energy_cost, &options).unwrap();
+        assert_eq!(double.get_path(), single.get_path());
+        assert!(approx_eq!(f64, double.get_cost(), single.get_cost(), epsilon = 0.001, ulps = 0));
+        assert!(single.is_optimal());
+        // The cost of the found tour is summed up in f64
+        let exact: f64 = single.get_path().iter().map(|e| energy_cost[(e.from(), e.to())]).sum();
+        assert_eq!(exact, single.get_cost());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn reverse_cost_test() {
+        let cost = DMatrix::from_vec(3, 3, vec![
+            0.0, 1.0, 2.0,
+            3.0, 0.0, 4.0,
+            5.0, 6.0, 0.0,
+        ]).transpose();
+        let result = find(&cost).unwrap();
+        let reverse: DMatrix<f64> = cost.transpose();
+        assert_eq!(result.get_cost(), result.reverse_cost(&reverse));
+
+        let symmetric = &cost + &reverse;
+        let result = find(&symmetric).unwrap();
+        assert_eq!(result.get_cost(), result.reverse_cost(&symmetric));
+    }
+
+    #[test]
+    fn find_cancellable_test() {
+        let cost = ten_points_cost();
+        let exact = find(&cost).unwrap();
+        let result = find_cancellable(&cost, &AtomicBool::new(false)).unwrap();
+        assert_eq!(exact.get_path(), result.get_path());
+
+        let started = Instant::now();
+        assert!(find_cancellable(&cost, &AtomicBool::new(true)).is_none());
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        // The flag is checked during the search as well
+        let cancel = AtomicBool::new(false);
+        let mut expanded = 0;
+        let options = SearchOptions {
+            progress_interval: Some(1),
+            ..SearchOptions::default()
+        };
+        let result = find_with_cancel(
+            &cost,
+            &options,
+            &mut |stats| {
+                expanded = stats.nodes_expanded;
+                cancel.store(true, AtomicOrdering::Relaxed);
+            },
+            &cancel,
+        );
+        assert!(result.is_none());
+        assert_eq!(1, expanded);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn find_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let time_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 96.774, 39.395, 112.903, 33.951, 64.516,
+            100.000, f64::INFINITY, 96.000, 37.498, 95.333, 36.098,
+            39.395, 92.903, f64::INFINITY, 109.032, 20.646, 60.645,
+            116.667, 37.498, 112.667, f64::INFINITY, 112.000, 50.000,
+            33.951, 92.258, 20.646, 108.387, f64::INFINITY, 60.000,
+            66.667, 36.098, 62.667, 48.387, 62.000, f64::INFINITY
+        ]).transpose();
+
+        let capacity_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 1.155, 0.276, 1.526, 0.251, 0.925,
+            0.472, f64::INFINITY, 0.510, 0.399, 0.521, 0.339,
+            0.248, 1.166, f64::INFINITY, 1.289, 0.129, 0.868,
+            0.728, 0.285, 0.519, f64::INFINITY, 0.529, 0.361,
+            0.219, 1.173, 0.125, 1.294, f64::INFINITY, 0.739,
+            0.470, 0.567, 0.440, 0.702, 0.315, f64::INFINITY
+        ]).transpose();
+
+        // The reverse tour has the same cost, the lexicographically
+        // smaller path wins the tie
+        let energy_expected_path = vec![
+            PathEdge(0, 1),
+            PathEdge(1, 3),
+            PathEdge(3, 5),
+            PathEdge(5, 4),
+            PathEdge(4, 2),
+            PathEdge(2, 0),
+        ];
+
+        let time_expected_path = vec![
+            PathEdge(0, 5),
+            PathEdge(5, 3),
+            PathEdge(3, 1),
+            PathEdge(1, 2),
+            PathEdge(2, 4),
+            PathEdge(4, 0),
+        ];
+
+        // The reverse tour has the same cost, the lexicographically
+        // smaller path wins the tie
+        let capacity_expected_path = vec![
+            PathEdge(0, 1),
+            PathEdge(1, 3),
+            PathEdge(3, 5),
+            PathEdge(5, 4),
+            PathEdge(4, 2),
+            PathEdge(2, 0),
+        ];
+
+        let result = find(&energy_cost).unwrap();
+        assert!(approx_eq!(f64, 213.615, result.get_cost(), epsilon = 0.001, ulps = 0));
+        assert_eq!(energy_expected_path, *result.get_path());
+
+        let result = find(&time_cost).unwrap();
+        assert!(approx_eq!(f64, 300.997, result.get_cost(), epsilon = 0.001, ulps = 0));
+        assert_eq!(time_expected_path, *result.get_path());
+
+        let result = find(&capacity_cost).unwrap();
         assert!(approx_eq!(f64, 2.603, result.get_cost(), epsilon = 0.001, ulps = 0));
         assert_eq!(capacity_expected_path, *result.get_path());
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn order_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        let result = find(&energy_cost).unwrap();
+        assert_eq!(vec![0, 1, 3, 5, 4, 2, 0], result.order());
+        // The reverse tour of the same cost
+        let reverse = FindResult {
+            path: result.get_path().iter().rev().map(|e| PathEdge(e.to(), e.from())).collect(),
+            ..FindResult::default()
+        };
+        assert_eq!(vec![0, 2, 4, 5, 3, 1, 0], reverse.order());
+        assert!(FindResult::default().order().is_empty());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn tie_break_test() {
+        // Corners of a square, both directions around it are optimal
+        let cost = DMatrix::from_vec(4, 4, vec![
+            f64::INFINITY, 1.0, 2.0, 1.0,
+            1.0, f64::INFINITY, 1.0, 2.0,
+            2.0, 1.0, f64::INFINITY, 1.0,
+            1.0, 2.0, 1.0, f64::INFINITY,
+        ]);
+
+        let expected = vec![PathEdge(0, 1), PathEdge(1, 2), PathEdge(2, 3), PathEdge(3, 0)];
+        for _ in 0..10 {
+            let result = find(&cost).unwrap();
+            assert!(approx_eq!(f64, 4.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+            assert_eq!(&expected, result.get_path());
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn epsilon_test() {
+        // 0 -> 2 -> 3 -> 1 -> 0 costs 4, while the lexicographically
+        // smaller 0 -> 1 -> 2 -> 3 -> 0 is 1e-12 more expensive
+        let inf = f64::INFINITY;
+        let cost = DMatrix::from_row_slice(4, 4, &[
+            inf,           1.0,  1.0,  10.0,
+            1.0,           inf,  1.0,  10.0,
+            10.0,          10.0, inf,  1.0,
+            1.0 + 1e-12,   1.0,  10.0, inf,
+        ]);
+        let smallest = vec![PathEdge(0, 1), PathEdge(1, 2), PathEdge(2, 3), PathEdge(3, 0)];
+        let cheapest = vec![PathEdge(0, 2), PathEdge(2, 3), PathEdge(3, 1), PathEdge(1, 0)];
+
+        let exact = SearchOptions {
+            epsilon: Some(0.0),
+            ..SearchOptions::default()
+        };
+        for _ in 0..10 {
+            assert_eq!(&smallest, find(&cost).unwrap().get_path());
+            assert_eq!(&cheapest, find_with_options(&cost, &exact).unwrap().get_path());
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn precedences_test() {
+        let energy_cost = DMatrix::from_vec(6, 6, vec![
+            f64::INFINITY, 94.793, 22.634, 125.215, 20.567, 75.933,
+            38.706, f64::INFINITY, 41.894, 32.732, 42.790, 27.856,
+            20.391, 95.737, f64::INFINITY, 105.813, 10.618, 71.252,
+            59.780, 23.384, 42.622, f64::INFINITY, 43.402, 29.595,
+            17.950, 96.260, 10.244, 106.220, f64::INFINITY, 60.634,
+            38.542, 46.552, 36.104, 57.639, 25.860, f64::INFINITY
+        ]).transpose();
+
+        // Neither the unconstrained optimal tour nor the reverse one
+        // visits 2, 1 and 3 in this order
+        let unconstrained = find(&energy_cost).unwrap();
+        assert!(!satisfies_precedences(unconstrained.get_path(), &[(2, 1), (1, 3)]));
+
+        let options = SearchOptions {
+            precedences: vec![(2, 1), (1, 3)],
+            ..SearchOptions::default()
+        };
+        let result = find_with_options(&energy_cost, &options).unwrap();
+        assert!(satisfies_precedences(result.get_path(), &options.precedences));
+        assert!(result.get_cost() > unconstrained.get_cost());
+
+        // Brute force over all the tours satisfying the order
+        let mut best = f64::INFINITY;
+        let mut rest = [1, 2, 3, 4, 5];
+        let mut check = |tour: &[usize]| {
+            let position = |v| tour.iter().position(|&u| u == v);
+            if position(2) < position(1) && position(1) < position(3) {
+                let vertices: Vec<usize> = once(0).chain(tour.iter().copied()).collect();
+                let cost: f64 = vertices
+                    .iter()
+                    .zip(vertices.iter().cycle().skip(1))
+                    .map(|(&i, &j)| energy_cost[(i, j)])
+                    .sum();
+                best = best.min(cost);
+            }
+        };
+        heap_permutations(&mut rest, 5, &mut check);
+        assert!(approx_eq!(f64, best, result.get_cost(), epsilon = 0.001, ulps = 0));
+
+        let (cost, tours) = find_all(&energy_cost, &options.precedences).unwrap();
+        assert!(approx_eq!(f64, best, cost, epsilon = 0.001, ulps = 0));
+        assert!(tours.iter().all(|t| satisfies_precedences(t, &options.precedences)));
+        let results = find_k_best(&energy_cost, 3, &options.precedences);
+        assert_eq!(3, results.len());
+        assert!(results.iter().all(|r| satisfies_precedences(r.get_path(), &options.precedences)));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn required_test() {
+        let inf = f64::INFINITY;
+        // Vertex 3 is far from the rest, 0 -> 2 -> 1 -> 0 is cheaper
+        // than any tour visiting it
+        let cost = DMatrix::from_row_slice(4, 4, &[
+            inf, 3.0, 1.0, 50.0,
+            1.0, inf, 3.0, 50.0,
+            3.0, 1.0, inf, 50.0,
+            50.0, 50.0, 50.0, inf,
+        ]);
+        let options = SearchOptions {
+            required: Some(vec![1]),
+            ..SearchOptions::default()
+        };
+        let result = find_with_options(&cost, &options).unwrap();
+        assert_eq!(&vec![PathEdge(0, 2), PathEdge(2, 1), PathEdge(1, 0)], result.get_path());
+        assert!(approx_eq!(f64, 3.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+        assert!(result.is_optimal());
+        assert!(find(&cost).unwrap().get_cost() > result.get_cost());
+
+        // Skipping 2 is cheaper if it isn't on the way
+        let mut detour = cost.clone();
+        detour[(2, 1)] = 10.0;
+        let result = find_with_options(&detour, &options).unwrap();
+        assert_eq!(&vec![PathEdge(0, 1), PathEdge(1, 0)], result.get_path());
+
+        // The required vertex is visited even if it's expensive
+        let options = SearchOptions {
+            required: Some(vec![3]),
+            ..SearchOptions::default()
+        };
+        let result = find_with_options(&cost, &options).unwrap();
+        assert!(result.get_path().iter().any(|e| e.to() == 3));
+        assert!(approx_eq!(f64, 100.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+
+        let options = SearchOptions {
+            required: Some(Vec::new()),
+            ..SearchOptions::default()
+        };
+        let result = find_with_options(&cost, &options).unwrap();
+        assert_eq!(&vec![PathEdge(0, 0)], result.get_path());
+    }
+
+    #[test]
+    fn required_timeout_test() {
+        let cost = ten_points_cost();
+        let options = SearchOptions {
+            required: Some(vec![3, 7]),
+            timeout: Some(Duration::from_secs(0)),
+            ..SearchOptions::default()
+        };
+        // The greedy tour through the required vertices is returned
+        let result = find_with_options(&cost, &options).unwrap();
+        assert_eq!(Optimality::Timeout, result.optimality());
+        let mut visited = result.order();
+        visited.sort();
+        assert_eq!(vec![0, 0, 3, 7], visited);
+
+        let exact = find_with_options(
+            &cost,
+            &SearchOptions {
+                timeout: None,
+                ..options
+            },
+        )
+        .unwrap();
+        assert!(exact.is_optimal());
+        assert!(result.get_cost() >= exact.get_cost() - 0.001);
+    }
+
+    #[test]
+    fn symmetric_test() {
+        let coords = [
+            (0.0, 0.0),
+            (3.0, 9.0),
+            (7.0, 2.0),
+            (12.0, 8.0),
+            (5.0, 14.0),
+            (15.0, 1.0),
+            (9.0, 11.0),
+            (1.0, 6.0),
+        ];
+        let n = coords.len();
+        let distance = DMatrix::from_fn(n, n, |i, j| {
+            if i == j {
+                f64::INFINITY
+            } else {
+                let (a, b): ((f64, f64), (f64, f64)) = (coords[i], coords[j]);
+                (a.0 - b.0).hypot(a.1 - b.1)
+            }
+        });
+        assert!(is_symmetric(&distance));
+        let mut asymmetric = distance.clone();
+        asymmetric[(1, 2)] += 1.0;
+        assert!(!is_symmetric(&asymmetric));
+
+        let result = find(&distance).unwrap();
+        // Vertex 0 always comes first, so the constraint only
+        // disables the mirror pruning
+        let options = SearchOptions {
+            precedences: vec![(0, 1)],
+            ..SearchOptions::default()
+        };
+        let full = find_with_options(&distance, &options).unwrap();
+
+        assert_eq!(full.get_path(), result.get_path());
+        assert!(approx_eq!(
+            f64,
+            full.get_cost(),
+            result.get_cost(),
+            epsilon = 0.001,
+            ulps = 0
+        ));
+        assert!(result.stats().mirrors_pruned > 0);
+        assert_eq!(0, full.stats().mirrors_pruned);
+        assert!(result.stats().nodes_expanded < full.stats().nodes_expanded);
+
+        // One-way edges, the only tour 0 -> 3 -> 1 -> 2 -> 0 can't be reversed
+        let inf = f64::INFINITY;
+        #[rustfmt::skip]
+        let one_way = DMatrix::from_row_slice(4, 4, &[
+            inf, 1.0, inf, 10.0,
+            inf, inf, 1.0, inf,
+            1.0, inf, inf, inf,
+            inf, 1.0, inf, inf,
+        ]);
+        assert!(!is_symmetric(&one_way));
+        let result = find(&one_way).unwrap();
+        assert_eq!(vec![0, 3, 1, 2, 0], result.order());
+        assert_eq!(13.0, result.get_cost());
+    }
+
+    #[test]
+    fn invalid_matrix_test() {
+        let matrices = [
+            DMatrix::repeat(2, 3, 1.0),
+            DMatrix::repeat(0, 0, 1.0),
+            DMatrix::repeat(1, 1, f64::INFINITY),
+        ];
+        for cost in matrices.iter() {
+            assert!(!is_valid_matrix(cost));
+            assert!(find(cost).is_none());
+            assert!(find_all(cost, &[]).is_none());
+            assert!(find_k_best(cost, 2, &[]).is_empty());
+            assert!(nearest_neighbor(cost, 0).is_none());
+            assert!(held_karp(cost, 0).is_none());
+        }
+        assert!(is_valid_matrix(&DMatrix::repeat(2, 2, 1.0)));
+        assert!(held_karp(&DMatrix::repeat(2, 2, 1.0), 2).is_none());
+    }
+
+    #[test]
+    fn negative_cost_test() {
+        let inf = f64::INFINITY;
+        let mut cost =
+            DMatrix::from_row_slice(3, 3, &[inf, 1.0, 2.0, 1.0, inf, 1.0, 2.0, 1.0, inf]);
+        assert_eq!(None, find_negative(&cost));
+        assert!(find(&cost).is_some());
+
+        for &c in &[-1.0, -inf, f64::NAN] {
+            cost[(2, 1)] = c;
+            assert_eq!(Some((2, 1)), find_negative(&cost));
+            assert!(!is_valid_matrix(&cost));
+            assert!(find(&cost).is_none());
+        }
+    }
+
+    /// Calls `f` with each permutation of the first `k` items (Heap's algorithm).
+    fn heap_permutations(items: &mut [usize], k: usize, f: &mut dyn FnMut(&[usize])) {
+        if k == 1 {
+            f(items);
+            return;
+        }
+        for i in 0..k {
+            heap_permutations(items, k - 1, f);
+            let swap = if k % 2 == 1 { 0 } else { i };
+            items.swap(swap, k - 1);
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn find_all_test() {
+        // Corners of a square, both directions around it are optimal
+        let cost = DMatrix::from_vec(4, 4, vec![
+            f64::INFINITY, 1.0, 2.0, 1.0,
+            1.0, f64::INFINITY, 1.0, 2.0,
+            2.0, 1.0, f64::INFINITY, 1.0,
+            1.0, 2.0, 1.0, f64::INFINITY,
+        ]);
+
+        let (cost, tours) = find_all(&cost, &[]).unwrap();
+        assert!(approx_eq!(f64, 4.0, cost, epsilon = 0.001, ulps = 0));
+        assert_eq!(
+            vec![
+                vec![PathEdge(0, 1), PathEdge(1, 2), PathEdge(2, 3), PathEdge(3, 0)],
+                vec![PathEdge(0, 3), PathEdge(3, 2), PathEdge(2, 1), PathEdge(1, 0)],
+            ],
+            tours
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn find_lexicographic_test() {
+        // Both directions around the square cost the same energy,
+        // but flying clockwise is faster
+        let energy = DMatrix::from_vec(4, 4, vec![
+            f64::INFINITY, 1.0, 2.0, 1.0,
+            1.0, f64::INFINITY, 1.0, 2.0,
+            2.0, 1.0, f64::INFINITY, 1.0,
+            1.0, 2.0, 1.0, f64::INFINITY,
+        ]);
+        let time = DMatrix::from_vec(4, 4, vec![
+            f64::INFINITY, 2.0, 3.0, 1.0,
+            1.0, f64::INFINITY, 2.0, 3.0,
+            3.0, 1.0, f64::INFINITY, 2.0,
+            2.0, 3.0, 1.0, f64::INFINITY,
+        ]).transpose();
+
+        let result = find_lexicographic(&[&energy, &time], &[]).unwrap();
+        assert!(approx_eq!(f64, 4.0, result.get_cost(), epsilon = 0.001, ulps = 0));
+        assert_eq!(
+            &vec![PathEdge(0, 3), PathEdge(3, 2), PathEdge(2, 1), PathEdge(1, 0)],
+            result.get_path()
+        );
+        let time_cost: f64 = result.get_path().iter().map(|e| time[(e.0, e.1)]).sum();
+        assert_eq!(4.0, time_cost);
+
+        // Without the tie-break the first of the optimal tours is taken
+        let result = find_lexicographic(&[&energy], &[]).unwrap();
+        assert_eq!(find_all(&energy, &[]).unwrap().1[0], *result.get_path());
+    }
+
+    #[test]
+    fn find_k_best_test() {
+        let cost = ten_points_cost();
+        let optimum = find(&cost).unwrap();
+
+        let results = find_k_best(&cost, 5, &[]);
+        assert_eq!(5, results.len());
+        assert!(approx_eq!(
+            f64,
+            optimum.get_cost(),
+            results[0].get_cost(),
+            epsilon = 0.001,
+            ulps = 0
+        ));
+        assert!(results[0].is_optimal());
+        for (prev, next) in results.iter().zip(results.iter().skip(1)) {
+            assert!(prev.get_cost() <= next.get_cost());
+        }
+        for (i, a) in results.iter().enumerate() {
+            for b in &results[i + 1..] {
+                assert_ne!(a.get_path(), b.get_path());
+            }
+        }
+
+        assert!(find_k_best(&cost, 0, &[]).is_empty());
+    }
 }
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Version of the JSON output, incremented when the format changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    /// 0-based index of the point in the params coordinates.
+    pub point_index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    /// Number of the source points merged into this one by snapping to the grid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged: Option<usize>,
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.point_index + 1)?;
+        if let Some(label) = &self.label {
+            write!(f, " {}", label)?;
+        }
+        write!(f, ": ({}, {}, {})", self.x, self.y, self.z)?;
+        if let Some(merged) = self.merged {
+            write!(f, " [{} points merged]", merged)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Costs of flying from the point `from` to the point `to`, hovering at the points
+/// is included only into the total costs. The points are 0-based indices.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeCost {
+    pub from: usize,
+    pub to: usize,
+    pub energy: f64,
+    pub time: f64,
+    pub capacity: f64,
+    pub distance: f64,
+}
+
+/// Path search statistics.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub nodes_expanded: usize,
+    pub nodes_pruned: usize,
+    pub peak_queue_len: usize,
+    /// Nodes skipped as the reverse tours of a symmetric matrix.
+    pub mirrors_pruned: usize,
+    /// Heuristic runs from random start vertices.
+    pub restarts: usize,
+    /// Search duration in seconds.
+    pub elapsed: f64,
+}